@@ -0,0 +1,15 @@
+use std::env;
+use std::process::Command;
+
+// The version of the compiler is part of rinecs::version_hash since the
+// layout of rust types crossing a dynamic library boundary is only stable
+// for the same compiler
+fn main(){
+    let rustc = env::var("RUSTC").unwrap_or("rustc".to_owned());
+    let output = Command::new(&rustc)
+        .arg("--version")
+        .output()
+        .expect(&format!("Couldn't run {} --version", rustc));
+    let version = String::from_utf8_lossy(&output.stdout);
+    println!("cargo:rustc-env=RINECS_RUSTC_VERSION={}", version.trim());
+}
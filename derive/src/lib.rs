@@ -5,6 +5,7 @@ extern crate syn;
 
 use proc_macro::TokenStream;
 use syn::{Ident, MacroInput, MetaItem, NestedMetaItem};
+use syn::{Item, ItemKind, FnArg, Ty, Mutability, Visibility};
 use quote::Tokens;
 
 #[proc_macro_derive(Component, attributes(storage))]
@@ -437,4 +438,110 @@ fn impl_gpu_component(ast: &MacroInput) -> Tokens {
         _ => ()
 	}
     impl_gpu_other_structs(ast)
-}
\ No newline at end of file
+}
+
+// Exports a function as a hot reloadable system together with its abi
+// fingerprint and manifest entry. The kind of system is deduced from the
+// signature and the components it accesses are passed as arguments:
+//
+// #[dynamic_system(Position, Velocity)]
+// pub fn update(state: &mut State, entities: Entities, resources: Resources)
+//
+// It expands to the dynamic_systems! macro so rinecs has to be imported with
+// #[macro_use]. List the systems in dynamic_systems_manifest! to export the
+// library manifest
+#[proc_macro_attribute]
+pub fn dynamic_system(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let components = parse_components(&attr.to_string());
+    let item = syn::parse_item(&input.to_string()).unwrap();
+    let gen = impl_dynamic_system(item, &components);
+    gen.parse().unwrap()
+}
+
+fn parse_components(attr: &str) -> Vec<Ty> {
+    let attr = attr.trim().trim_end_matches(',');
+    if attr.is_empty() {
+        return vec![];
+    }
+    match syn::parse_type(&format!("({},)", attr)).unwrap() {
+        Ty::Tup(components) => components,
+        _ => panic!("dynamic_system expects a list of components"),
+    }
+}
+
+fn type_name(ty: &Ty) -> Option<&Ident> {
+    match *ty {
+        Ty::Path(_, ref path) => path.segments.last().map(|segment| &segment.ident),
+        _ => None,
+    }
+}
+
+fn impl_dynamic_system(mut item: Item, components: &[Ty]) -> Tokens {
+    let name = item.ident.clone();
+    let abi = Ident::new(format!("{}_abi", name));
+    let abi_header = Ident::new(format!("{}_abi_header", name));
+    let inputs = match item.node {
+        ItemKind::Fn(ref decl, _, _, _, ref generics, _) => {
+            if !generics.ty_params.is_empty() {
+                panic!("dynamic_system {} can't be generic", name);
+            }
+            decl.inputs.iter()
+                .map(|input| match *input {
+                    FnArg::Captured(_, ref ty) => ty.clone(),
+                    _ => panic!("dynamic_system {} has an unsupported argument", name),
+                })
+                .collect::<Vec<_>>()
+        }
+        _ => panic!("dynamic_system can only be used on functions"),
+    };
+
+    // with data systems receive a &mut D before the entities
+    let (data, entities) = match inputs.len() {
+        2 => (None, &inputs[0]),
+        3 => match inputs[0] {
+            Ty::Rptr(_, ref data) if data.mutability == Mutability::Mutable => (Some(&data.ty), &inputs[1]),
+            _ => panic!("dynamic_system {} data has to be a &mut", name),
+        },
+        _ => panic!("dynamic_system {} has to receive entities and resources", name),
+    };
+
+    let kind = match type_name(entities) {
+        Some(ident) if ident == "Entities" => "Send",
+        Some(ident) if ident == "EntitiesThreadLocal" => "ThreadLocal",
+        Some(ident) if ident == "EntitiesCreation" => "Creation",
+        _ => panic!("dynamic_system {} has to receive Entities, EntitiesThreadLocal or EntitiesCreation", name),
+    };
+
+    // The original function goes in a module with the same name as the
+    // system, next to its manifest entry, so the exported wrapper can take
+    // its name. The code is generated by the dynamic_systems! macro
+    item.vis = Visibility::Public;
+    let kind = Ident::new(if data.is_some() { kind.to_owned() + "WithData" } else { kind.to_owned() });
+    let data = data.map(|data| quote!(<#data>));
+    quote! {
+        pub mod #name {
+            use super::*;
+
+            #item
+
+            pub fn manifest() -> ::rinecs::SystemManifest{
+                dynamic_systems!(@manifest #name #kind [#(#components),*])
+            }
+        }
+
+        dynamic_systems!(@system #name #kind #data #name::#name);
+
+        #[no_mangle]
+        pub extern "C" fn #abi_header() -> ::rinecs::AbiHeader{
+            dynamic_systems!(@header #kind [#(#components),*])
+        }
+
+        #[no_mangle]
+        pub fn #abi() -> ::rinecs::AbiFingerprint{
+            dynamic_systems!(@fingerprint #kind [#(#components),*])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,62 @@
+use super::{impl_dynamic_system, parse_components};
+
+fn expand(attr: &str, item: &str) -> String {
+    let components = parse_components(attr);
+    let item = syn::parse_item(item).unwrap();
+    impl_dynamic_system(item, &components).to_string()
+}
+
+fn tokens(source: &str) -> String {
+    let tts = syn::parse_token_trees(source).unwrap();
+    quote!(#(#tts)*).to_string()
+}
+
+#[test]
+fn dynamic_system_expansion() {
+    let expanded = expand("Position, Velocity", "
+        pub fn update(entities: Entities, resources: Resources){
+            update(entities, resources)
+        }
+    ");
+    assert_eq!(expanded, tokens("
+        pub mod update {
+            use super::*;
+
+            pub fn update(entities: Entities, resources: Resources){
+                update(entities, resources)
+            }
+
+            pub fn manifest() -> ::rinecs::SystemManifest{
+                dynamic_systems!(@manifest update Send [Position, Velocity])
+            }
+        }
+
+        dynamic_systems!(@system update Send update::update);
+
+        #[no_mangle]
+        pub extern \"C\" fn update_abi_header() -> ::rinecs::AbiHeader{
+            dynamic_systems!(@header Send [Position, Velocity])
+        }
+
+        #[no_mangle]
+        pub fn update_abi() -> ::rinecs::AbiFingerprint{
+            dynamic_systems!(@fingerprint Send [Position, Velocity])
+        }
+    "));
+}
+
+#[test]
+fn dynamic_system_with_data_expansion() {
+    let expanded = expand("", "
+        fn spawn(spawner: &mut Spawner, entities: EntitiesCreation, resources: ResourcesThreadLocal){}
+    ");
+    assert!(expanded.contains(&tokens("dynamic_systems!(@manifest spawn CreationWithData [])")));
+    assert!(expanded.contains(&tokens("dynamic_systems!(@system spawn CreationWithData <Spawner> spawn::spawn);")));
+    assert!(expanded.contains(&tokens("pub fn spawn(spawner: &mut Spawner, entities: EntitiesCreation, resources: ResourcesThreadLocal){}")));
+}
+
+#[test]
+#[should_panic]
+fn dynamic_system_without_entities() {
+    expand("", "fn update(resources: Resources){}");
+}
@@ -0,0 +1,309 @@
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use fxhash::FxHasher64;
+use fxhash::FxHashMap as HashMap;
+use ::Component;
+
+#[repr(u32)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum SystemKind{
+    Send,
+    ThreadLocal,
+    Creation,
+    SendWithData,
+    ThreadLocalWithData,
+    CreationWithData,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ComponentLayout{
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl ComponentLayout{
+    pub fn of<C: Component>() -> ComponentLayout{
+        ComponentLayout{
+            name: C::type_name(),
+            size: mem::size_of::<C>(),
+            align: mem::align_of::<C>(),
+        }
+    }
+}
+
+// Plain data part of the fingerprint, exported through the C abi so the
+// loader can refuse a library built against a different rinecs before any
+// rust type crosses the library boundary
+#[repr(C)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct AbiHeader{
+    pub version_hash: u64,
+    pub kind: u32,
+    pub components: u32,
+    pub layout_hash: u64,
+}
+
+// Exported by dynamic libraries for every system so the loader can check
+// that the library was built against the same rinecs and components as the
+// host before calling into it. Generate it with the dynamic_systems! macro
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AbiFingerprint{
+    pub version: String,
+    pub kind: SystemKind,
+    pub components: Vec<String>,
+    pub layout_hash: u64,
+}
+
+// Describes a system exported by a dynamic library. Libraries export all of
+// them from rinecs_manifest so World::load_dynamic_library can add them at
+// once, sorted by order, and add or remove systems when the library changes
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SystemManifest{
+    pub name: String,
+    pub kind: SystemKind,
+    pub order: i32,
+    pub components: Vec<String>,
+}
+
+pub fn layout_hash<'a, I: IntoIterator<Item = &'a ComponentLayout>>(layouts: I) -> u64{
+    let mut hasher = FxHasher64::default();
+    for layout in layouts {
+        layout.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// Hashes the rinecs version, the compiler version and the layout of the
+// rinecs types passed to and returned by dynamic systems. Rust doesn't
+// have a stable abi so all of them have to match the host's
+pub fn version_hash() -> u64{
+    let mut hasher = FxHasher64::default();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    env!("RINECS_RUSTC_VERSION").hash(&mut hasher);
+    let layouts = [
+        type_layout::<::Entities<'static>>(),
+        type_layout::<::EntitiesThreadLocal<'static>>(),
+        type_layout::<::EntitiesCreation<'static>>(),
+        type_layout::<::Resources<'static>>(),
+        type_layout::<::ResourcesThreadLocal<'static>>(),
+        type_layout::<SystemManifest>(),
+        type_layout::<AbiFingerprint>(),
+    ];
+    layouts.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn type_layout<T>() -> (usize, usize){
+    (mem::size_of::<T>(), mem::align_of::<T>())
+}
+
+impl AbiHeader{
+    pub fn new(kind: SystemKind, layouts: &[ComponentLayout]) -> AbiHeader{
+        AbiHeader{
+            version_hash: version_hash(),
+            kind: kind as u32,
+            components: layouts.len() as u32,
+            layout_hash: layout_hash(layouts),
+        }
+    }
+
+    pub fn check(&self, kind: SystemKind) -> Result<(), String>{
+        if self.version_hash != version_hash() {
+            return Err(format!("Library built with a different rinecs or rustc than the host {} {}",
+                env!("CARGO_PKG_VERSION"), env!("RINECS_RUSTC_VERSION")));
+        }
+        if self.kind != kind as u32 {
+            return Err(format!("Library system kind {} doesn't match {:?}", self.kind, kind));
+        }
+        Ok(())
+    }
+}
+
+impl AbiFingerprint{
+    pub fn new(kind: SystemKind, layouts: Vec<ComponentLayout>) -> AbiFingerprint{
+        AbiFingerprint{
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            kind,
+            layout_hash: layout_hash(&layouts),
+            components: layouts.into_iter().map(|layout| layout.name).collect(),
+        }
+    }
+
+    // Recomputes the hash with the layouts of the components registered in
+    // the host, any component the host doesn't know about is a mismatch
+    pub fn check(&self, kind: SystemKind, layouts: &HashMap<String, ComponentLayout>) -> Result<(), String>{
+        if self.version != env!("CARGO_PKG_VERSION") {
+            return Err(format!("Library built with rinecs {} but host uses {}", self.version, env!("CARGO_PKG_VERSION")));
+        }
+        if self.kind != kind {
+            return Err(format!("Library system is {:?} but was loaded as {:?}", self.kind, kind));
+        }
+        let host_layouts = self.components.iter()
+            .map(|name| layouts.get(name)
+                .ok_or_else(|| format!("Component {} is not registered in the host", name)))
+            .collect::<Result<Vec<_>, String>>()?;
+        if layout_hash(host_layouts) != self.layout_hash {
+            return Err(format!("Layout of components {:?} differs from the host", self.components));
+        }
+        Ok(())
+    }
+}
+
+// Exports systems from a dynamic library together with their abi
+// fingerprint, split in a C abi header and the component names, and the
+// library manifest in the order they are declared,
+// listing the components each system accesses:
+//
+// dynamic_systems!{
+//     update_physics: Send = physics::update [Position, Velocity];
+//     spawn: CreationWithData<Spawner> = spawner::spawn [Position];
+// }
+//
+// with data systems receive their data as &mut D. The #[dynamic_system]
+// attribute expands to the @ arms of this macro
+#[macro_export]
+macro_rules! dynamic_systems {
+    (@system $name: ident Send $system: path) => {
+        #[no_mangle]
+        pub fn $name(entities: $crate::Entities, resources: $crate::Resources){
+            $system(entities, resources)
+        }
+    };
+
+    (@system $name: ident ThreadLocal $system: path) => {
+        #[no_mangle]
+        pub fn $name(entities: $crate::EntitiesThreadLocal, resources: $crate::ResourcesThreadLocal){
+            $system(entities, resources)
+        }
+    };
+
+    (@system $name: ident Creation $system: path) => {
+        #[no_mangle]
+        pub fn $name(entities: $crate::EntitiesCreation, resources: $crate::ResourcesThreadLocal){
+            $system(entities, resources)
+        }
+    };
+
+    (@system $name: ident SendWithData <$data: ty> $system: path) => {
+        #[no_mangle]
+        pub fn $name(data: *mut ::std::os::raw::c_void, entities: $crate::Entities, resources: $crate::Resources){
+            $system(unsafe{ &mut *(data as *mut $data) }, entities, resources)
+        }
+    };
+
+    (@system $name: ident ThreadLocalWithData <$data: ty> $system: path) => {
+        #[no_mangle]
+        pub fn $name(data: *mut ::std::os::raw::c_void, entities: $crate::EntitiesThreadLocal, resources: $crate::ResourcesThreadLocal){
+            $system(unsafe{ &mut *(data as *mut $data) }, entities, resources)
+        }
+    };
+
+    (@system $name: ident CreationWithData <$data: ty> $system: path) => {
+        #[no_mangle]
+        pub fn $name(data: *mut ::std::os::raw::c_void, entities: $crate::EntitiesCreation, resources: $crate::ResourcesThreadLocal){
+            $system(unsafe{ &mut *(data as *mut $data) }, entities, resources)
+        }
+    };
+
+    (@kind $kind: ident) => {
+        $crate::SystemKind::$kind
+    };
+
+    (@header $kind: ident [$($component: ty),*]) => {
+        $crate::AbiHeader::new(
+            dynamic_systems!(@kind $kind),
+            &[$($crate::ComponentLayout::of::<$component>()),*]
+        )
+    };
+
+    (@fingerprint $kind: ident [$($component: ty),*]) => {
+        $crate::AbiFingerprint::new(
+            dynamic_systems!(@kind $kind),
+            vec![$($crate::ComponentLayout::of::<$component>()),*]
+        )
+    };
+
+    // The order is set from the position of the system in the manifest
+    (@manifest $name: ident $kind: ident [$($component: ty),*]) => {
+        $crate::SystemManifest{
+            name: stringify!($name).to_owned(),
+            kind: dynamic_systems!(@kind $kind),
+            order: 0,
+            components: vec![$($crate::ComponentLayout::of::<$component>().name),*],
+        }
+    };
+
+    // Lets the loader check the library uses the same rinecs before reading
+    // the manifest
+    (@version) => {
+        #[no_mangle]
+        pub extern "C" fn rinecs_version_hash() -> u64{
+            $crate::version_hash()
+        }
+    };
+
+    (@ordered $systems: expr) => {
+        $systems.into_iter()
+            .enumerate()
+            .map(|(order, system): (usize, $crate::SystemManifest)| $crate::SystemManifest{
+                order: order as i32,
+                .. system
+            })
+            .collect()
+    };
+
+    ($($name: ident: $kind: ident $(<$data: ty>)* = $system: path [$($component: ty),*];)*) => {
+        $(
+            dynamic_systems!(@system $name $kind $(<$data>)* $system);
+        )*
+
+        #[no_mangle]
+        pub extern "C" fn rinecs_abi_header(system: *const u8, len: usize, header: *mut $crate::AbiHeader) -> bool{
+            let system = unsafe{ ::std::slice::from_raw_parts(system, len) };
+            $(
+                if system == stringify!($name).as_bytes() {
+                    unsafe{ *header = dynamic_systems!(@header $kind [$($component),*]) };
+                    return true;
+                }
+            )*
+            false
+        }
+
+        #[no_mangle]
+        pub fn rinecs_abi(system: &str) -> Option<$crate::AbiFingerprint>{
+            $(
+                if system == stringify!($name) {
+                    return Some(dynamic_systems!(@fingerprint $kind [$($component),*]));
+                }
+            )*
+            None
+        }
+
+        dynamic_systems!(@version);
+
+        #[no_mangle]
+        pub fn rinecs_manifest() -> Vec<$crate::SystemManifest>{
+            dynamic_systems!(@ordered vec![$(
+                dynamic_systems!(@manifest $name $kind [$($component),*])
+            ),*])
+        }
+    };
+}
+
+// Exports the manifest of systems declared with #[dynamic_system] in the
+// order they are listed:
+//
+// dynamic_systems_manifest![update_physics, spawn];
+#[macro_export]
+macro_rules! dynamic_systems_manifest {
+    ($($system: ident),*) => {
+        dynamic_systems!(@version);
+
+        #[no_mangle]
+        pub fn rinecs_manifest() -> Vec<$crate::SystemManifest>{
+            dynamic_systems!(@ordered vec![$($system::manifest()),*])
+        }
+    };
+}
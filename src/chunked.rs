@@ -0,0 +1,214 @@
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::cell::UnsafeCell;
+use std::slice;
+
+use storage::SliceStorage;
+use ::Component;
+use ::ComponentSync;
+use ::World;
+use ::Entity;
+use ::Bitmask;
+use ::Read;
+use ::Write;
+
+// Chunked iteration: entities matching a mask are visited in chunks of up to
+// max_len, if the components for every entity in a chunk are contiguous in
+// their storages the chunk is handed directly as slices, otherwise the
+// components are gathered into a temporary buffer and, for writes, scattered
+// back after the chunk is processed
+pub trait ChunkedData<'a>: for<'b> ChunkRef<'b>{
+    type Storage;
+    type Buffer: Default;
+    fn components_mask(world: &'a World) -> Bitmask;
+    fn storage(world: &'a World) -> Self::Storage;
+    unsafe fn contiguous(storage: &Self::Storage, prev_guid: usize, guid: usize) -> bool;
+    unsafe fn slice<'b>(storage: &'b Self::Storage, first_guid: usize, len: usize) -> <Self as ChunkRef<'b>>::Chunk;
+    unsafe fn gather(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer);
+    fn buffer_chunk<'b>(buffer: &'b mut Self::Buffer) -> <Self as ChunkRef<'b>>::Chunk;
+    unsafe fn scatter(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer);
+}
+
+// Chunks only live for the call to the chunk callback so they can't be kept
+// past the scatter, the reuse of the buffer or the storage guard
+pub trait ChunkRef<'b>{
+    type Chunk;
+}
+
+impl<'a, 'b, T: 'a + Component> ChunkRef<'b> for Read<'a,T>{
+    type Chunk = &'b [T];
+}
+
+impl<'a, 'b, T: 'a + Component> ChunkRef<'b> for Write<'a,T>{
+    type Chunk = &'b mut [T];
+}
+
+impl<'a, T: 'a + ComponentSync + Clone> ChunkedData<'a> for Read<'a,T>
+    where <T as Component>::Storage: for<'b> SliceStorage<'b,T>
+{
+    type Storage = RwLockReadGuard<'a, <T as Component>::Storage>;
+    type Buffer = Vec<T>;
+
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn storage(world: &'a World) -> Self::Storage{
+        world.storage::<T>()
+            .expect(&format!("Trying to use non registered type {}", T::type_name()))
+    }
+
+    unsafe fn contiguous(storage: &Self::Storage, prev_guid: usize, guid: usize) -> bool{
+        storage.position(guid) == storage.position(prev_guid) + 1
+    }
+
+    unsafe fn slice<'b>(storage: &'b Self::Storage, first_guid: usize, len: usize) -> &'b [T]{
+        let first = storage.position(first_guid);
+        &storage.as_slice()[first .. first + len]
+    }
+
+    unsafe fn gather(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer){
+        let components = storage.as_slice();
+        buffer.clear();
+        buffer.extend(guids.iter().map(|guid| components[storage.position(*guid)].clone()));
+    }
+
+    fn buffer_chunk<'b>(buffer: &'b mut Self::Buffer) -> &'b [T]{
+        buffer.as_slice()
+    }
+
+    unsafe fn scatter(_storage: &Self::Storage, _guids: &[usize], _buffer: &mut Self::Buffer){
+    }
+}
+
+impl<'a, T: 'a + ComponentSync + Clone> ChunkedData<'a> for Write<'a,T>
+    where <T as Component>::Storage: for<'b> SliceStorage<'b,T>
+{
+    type Storage = UnsafeCell<RwLockWriteGuard<'a, <T as Component>::Storage>>;
+    type Buffer = Vec<T>;
+
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn storage(world: &'a World) -> Self::Storage{
+        UnsafeCell::new(world.storage_mut::<T>()
+            .expect(&format!("Trying to use non registered type {}", T::type_name())))
+    }
+
+    unsafe fn contiguous(storage: &Self::Storage, prev_guid: usize, guid: usize) -> bool{
+        let storage = &*storage.get();
+        storage.position(guid) == storage.position(prev_guid) + 1
+    }
+
+    // Only one chunk is alive at a time so the mutable borrow is unique
+    unsafe fn slice<'b>(storage: &'b Self::Storage, first_guid: usize, len: usize) -> &'b mut [T]{
+        let storage: &'b mut RwLockWriteGuard<'a, <T as Component>::Storage> = &mut *storage.get();
+        let first = storage.position(first_guid);
+        &mut storage.as_mut_slice()[first .. first + len]
+    }
+
+    unsafe fn gather(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer){
+        let storage = &*storage.get();
+        let components = storage.as_slice();
+        buffer.clear();
+        buffer.extend(guids.iter().map(|guid| components[storage.position(*guid)].clone()));
+    }
+
+    fn buffer_chunk<'b>(buffer: &'b mut Self::Buffer) -> &'b mut [T]{
+        buffer.as_mut_slice()
+    }
+
+    unsafe fn scatter(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer){
+        let storage = &mut *storage.get();
+        for (guid, component) in guids.iter().zip(buffer.drain(..)) {
+            let position = storage.position(*guid);
+            storage.as_mut_slice()[position] = component;
+        }
+    }
+}
+
+macro_rules! impl_combined_chunked {
+    ($($c: ident, $s: ident, $b: ident),*) => (
+        impl<'b, $($c: ChunkRef<'b>),*> ChunkRef<'b> for ($($c),*){
+            type Chunk = ($(<$c as ChunkRef<'b>>::Chunk),*);
+        }
+
+        impl<'a, $($c: ChunkedData<'a>),*> ChunkedData<'a> for ($($c),*){
+            type Storage = ($(<$c as ChunkedData<'a>>::Storage),*);
+            type Buffer = ($(<$c as ChunkedData<'a>>::Buffer),*);
+
+            fn components_mask(world: &'a World) -> Bitmask{
+                $($c::components_mask(world)) | *
+            }
+
+            fn storage(world: &'a World) -> Self::Storage{
+                ($($c::storage(world)),*)
+            }
+
+            unsafe fn contiguous(storage: &Self::Storage, prev_guid: usize, guid: usize) -> bool{
+                let ($(ref $s),*) = *storage;
+                $($c::contiguous($s, prev_guid, guid)) && *
+            }
+
+            unsafe fn slice<'b>(storage: &'b Self::Storage, first_guid: usize, len: usize) -> <Self as ChunkRef<'b>>::Chunk{
+                let ($(ref $s),*) = *storage;
+                ($($c::slice($s, first_guid, len)),*)
+            }
+
+            unsafe fn gather(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer){
+                let ($(ref $s),*) = *storage;
+                let ($(ref mut $b),*) = *buffer;
+                $($c::gather($s, guids, $b);)*
+            }
+
+            fn buffer_chunk<'b>(buffer: &'b mut Self::Buffer) -> <Self as ChunkRef<'b>>::Chunk{
+                let ($(ref mut $b),*) = *buffer;
+                ($($c::buffer_chunk($b)),*)
+            }
+
+            unsafe fn scatter(storage: &Self::Storage, guids: &[usize], buffer: &mut Self::Buffer){
+                let ($(ref $s),*) = *storage;
+                let ($(ref mut $b),*) = *buffer;
+                $($c::scatter($s, guids, $b);)*
+            }
+        }
+    )
+}
+
+mod combined_chunked{
+    use super::{ChunkedData, ChunkRef};
+    use ::World;
+    use ::Bitmask;
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3, C4, s4, b4);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3, C4, s4, b4, C5, s5, b5);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3, C4, s4, b4, C5, s5, b5, C6, s6, b6);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3, C4, s4, b4, C5, s5, b5, C6, s6, b6, C7, s7, b7);
+    impl_combined_chunked!(C1, s1, b1, C2, s2, b2, C3, s3, b3, C4, s4, b4, C5, s5, b5, C6, s6, b6, C7, s7, b7, C8, s8, b8);
+}
+
+pub(crate) fn for_each_chunk<'a, S, F>(world: &'a World, max_len: usize, mut f: F)
+    where S: ChunkedData<'a>,
+          F: for<'b> FnMut(&'b [Entity], <S as ChunkRef<'b>>::Chunk)
+{
+    assert!(max_len > 0, "Trying to iterate in chunks of 0 elements");
+    let ids = world.entities_for_mask(S::components_mask(world));
+    let storage = S::storage(world);
+    let mut buffer = S::Buffer::default();
+    for guids in ids.index.chunks(max_len) {
+        unsafe{
+            // Entity is repr(transparent) over it's guid
+            let entities = slice::from_raw_parts(guids.as_ptr() as *const Entity, guids.len());
+            let contiguous = guids.windows(2)
+                .all(|pair| S::contiguous(&storage, pair[0], pair[1]));
+            if contiguous {
+                f(entities, S::slice(&storage, guids[0], guids.len()));
+            }else{
+                S::gather(&storage, guids, &mut buffer);
+                f(entities, S::buffer_chunk(&mut buffer));
+                S::scatter(&storage, guids, &mut buffer);
+            }
+        }
+    }
+}
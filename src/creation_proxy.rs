@@ -3,26 +3,58 @@ use component::{ComponentSync, ComponentThreadLocal,
     OneToNComponentSync, OneToNComponentThreadLocal};
 
 use entity::{Entity, EntityBuilder, EntitiesCreation};
-use storage::{UnorderedDataLocal, OrderedDataLocal};
+use storage::{UnorderedDataLocal, OrderedDataLocal, HierarchicalDataLocal, Traversal, TraversalIter};
 use sync::{NodePtr, Ptr, PtrMut, NodePtrMut};
 use world::World;
+use relation::{Relation, Hierarchy};
+use storage::ChildrenMode;
 
 pub trait CreationProxy {
     fn iter_for<'e, S: UnorderedDataLocal<'e> + 'e>(&'e self) -> <S as UnorderedDataLocal<'e>>::Iter;
     fn ordered_iter_for<'e, S: OrderedDataLocal<'e> + 'e>(&'e self) -> <S as OrderedDataLocal<'e>>::Iter;
+    fn traverse_for<'e, S: HierarchicalDataLocal<'e> + 'e>(&'e self, traversal: Traversal) -> TraversalIter<'e, <S as OrderedDataLocal<'e>>::ComponentsRef, <S as OrderedDataLocal<'e>>::Storage>
+        where <S as OrderedDataLocal<'e>>::Storage: ::StorageRef<'e, <S as OrderedDataLocal<'e>>::ComponentsRef>;
     fn component_for<C: ::Component>(&self, entity: &Entity) -> Option<Ptr<C>> ;
     fn component_for_mut<C: ::Component>(&self, entity: &Entity) -> Option<PtrMut<C>> ;
     fn tree_node_for<'e, C: ::Component>(&'e self, entity: &Entity) -> Option<NodePtr<'e, C>>
         where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
     fn tree_node_for_mut<'e, C: ::Component>(&'e self, entity: &Entity) -> Option<NodePtrMut<'e, C>>
         where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn parent_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn children_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn ancestors_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn descendants_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
     fn create_entity(&mut self) -> EntityBuilder;
     fn add_component_to<C: ComponentSync>(&mut self, entity: &Entity, component: C);
     fn add_component_to_thread_local<C: ComponentThreadLocal>(&mut self, entity: &Entity, component: C);
     fn add_slice_component_to<C: OneToNComponentSync>(&mut self, entity: &Entity, component: &[C]);
     fn add_slice_component_to_thread_local<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]);
+    fn push_component_to<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: C);
+    fn remove_component_at<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, index: usize) -> C;
+    fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, len: usize);
+    fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]);
+    fn add_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity, relation: R);
+    fn remove_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity) -> Option<R>;
     fn remove_component_from<C: ::Component>(&mut self, entity: &::Entity);
     fn remove_entity(&mut self, entity: &::Entity);
+    fn remove_component_from_hierarchy<'e, C: ::Component>(&mut self, entity: &Entity, mode: ChildrenMode)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn remove_entity_recursive<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn set_parent<'e, C: ::Component>(&mut self, entity: &Entity, parent: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn make_root<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn detach<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>;
+    fn set_parent_in<H: Hierarchy>(&mut self, entity: &Entity, parent: &Entity);
+    fn make_root_in<H: Hierarchy>(&mut self, entity: &Entity);
+    fn remove_from_hierarchy<H: Hierarchy>(&mut self, entity: &Entity);
+    fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>;
 }
 
 impl<'a> CreationProxy for EntitiesCreation<'a>{
@@ -34,6 +66,12 @@ impl<'a> CreationProxy for EntitiesCreation<'a>{
         self.ordered_iter_for::<S>()
     }
 
+    fn traverse_for<'e, S: HierarchicalDataLocal<'e> + 'e>(&'e self, traversal: Traversal) -> TraversalIter<'e, <S as OrderedDataLocal<'e>>::ComponentsRef, <S as OrderedDataLocal<'e>>::Storage>
+        where <S as OrderedDataLocal<'e>>::Storage: ::StorageRef<'e, <S as OrderedDataLocal<'e>>::ComponentsRef>
+    {
+        self.traverse_for::<S>(traversal)
+    }
+
     fn component_for<C: ::Component>(&self, entity: &Entity) -> Option<Ptr<C>> {
         self.component_for::<C>(entity)
     }
@@ -54,6 +92,30 @@ impl<'a> CreationProxy for EntitiesCreation<'a>{
         self.tree_node_for_mut::<C>(entity)
     }
 
+    fn parent_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.parent_of::<C>(entity)
+    }
+
+    fn children_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.children_of::<C>(entity)
+    }
+
+    fn ancestors_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.ancestors_of::<C>(entity)
+    }
+
+    fn descendants_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.descendants_of::<C>(entity)
+    }
+
     fn create_entity(&mut self) -> EntityBuilder{
         self.create_entity()
     }
@@ -74,6 +136,30 @@ impl<'a> CreationProxy for EntitiesCreation<'a>{
         self.add_slice_component_to_thread_local(entity, component)
     }
 
+    fn push_component_to<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: C){
+        self.push_component_to(entity, component)
+    }
+
+    fn remove_component_at<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, index: usize) -> C{
+        self.remove_component_at::<C>(entity, index)
+    }
+
+    fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, len: usize){
+        self.truncate_slice_component_of::<C>(entity, len)
+    }
+
+    fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]){
+        self.replace_slice_component_of(entity, component)
+    }
+
+    fn add_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity, relation: R){
+        self.add_relation(source, target, relation)
+    }
+
+    fn remove_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity) -> Option<R>{
+        self.remove_relation::<R>(source, target)
+    }
+
     fn remove_component_from<C: ::Component>(&mut self, entity: &::Entity){
         self.remove_component_from::<C>(entity)
     }
@@ -81,6 +167,52 @@ impl<'a> CreationProxy for EntitiesCreation<'a>{
     fn remove_entity(&mut self, entity: &::Entity){
         self.remove_entity(entity)
     }
+
+    fn remove_component_from_hierarchy<'e, C: ::Component>(&mut self, entity: &Entity, mode: ChildrenMode)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.remove_component_from_hierarchy::<C>(entity, mode)
+    }
+
+    fn remove_entity_recursive<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.remove_entity_recursive::<C>(entity)
+    }
+
+    fn set_parent<'e, C: ::Component>(&mut self, entity: &Entity, parent: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.set_parent::<C>(entity, parent)
+    }
+
+    fn make_root<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.make_root::<C>(entity)
+    }
+
+    fn detach<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.detach::<C>(entity)
+    }
+
+    fn set_parent_in<H: Hierarchy>(&mut self, entity: &Entity, parent: &Entity){
+        self.set_parent_in::<H>(entity, parent)
+    }
+
+    fn make_root_in<H: Hierarchy>(&mut self, entity: &Entity){
+        self.make_root_in::<H>(entity)
+    }
+
+    fn remove_from_hierarchy<H: Hierarchy>(&mut self, entity: &Entity){
+        self.remove_from_hierarchy::<H>(entity)
+    }
+
+    fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        self.parent_in::<H>(entity)
+    }
 }
 
 
@@ -94,6 +226,12 @@ impl CreationProxy for World{
         self.entities_thread_local().ordered_iter_for::<S>()
     }
 
+    fn traverse_for<'e, S: HierarchicalDataLocal<'e> + 'e>(&'e self, traversal: Traversal) -> TraversalIter<'e, <S as OrderedDataLocal<'e>>::ComponentsRef, <S as OrderedDataLocal<'e>>::Storage>
+        where <S as OrderedDataLocal<'e>>::Storage: ::StorageRef<'e, <S as OrderedDataLocal<'e>>::ComponentsRef>
+    {
+        self.entities_thread_local().traverse_for::<S>(traversal)
+    }
+
     fn component_for<C: ::Component>(&self, entity: &Entity) -> Option<Ptr<C>> {
         self.entities_thread_local().component_for::<C>(entity)
     }
@@ -114,6 +252,30 @@ impl CreationProxy for World{
         self.entities_thread_local().tree_node_for_mut::<C>(entity)
     }
 
+    fn parent_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.parent_of::<C>(entity)
+    }
+
+    fn children_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.children_of::<C>(entity)
+    }
+
+    fn ancestors_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.ancestors_of::<C>(entity)
+    }
+
+    fn descendants_of<'e, C: ::Component>(&'e self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.descendants_of::<C>(entity)
+    }
+
     fn create_entity(&mut self) -> EntityBuilder{
         self.create_entity()
     }
@@ -134,6 +296,30 @@ impl CreationProxy for World{
         self.add_slice_component_to_thread_local(entity, component)
     }
 
+    fn push_component_to<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: C){
+        self.push_component_to(entity, component)
+    }
+
+    fn remove_component_at<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, index: usize) -> C{
+        self.remove_component_at::<C>(entity, index)
+    }
+
+    fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, len: usize){
+        self.truncate_slice_component_of::<C>(entity, len)
+    }
+
+    fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]){
+        self.replace_slice_component_of(entity, component)
+    }
+
+    fn add_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity, relation: R){
+        self.add_relation(source, target, relation)
+    }
+
+    fn remove_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity) -> Option<R>{
+        self.remove_relation::<R>(source, target)
+    }
+
     fn remove_component_from<C: ::Component>(&mut self, entity: &::Entity){
         self.remove_component_from::<C>(entity)
     }
@@ -141,4 +327,50 @@ impl CreationProxy for World{
     fn remove_entity(&mut self, entity: &::Entity){
         self.remove_entity(entity)
     }
+
+    fn remove_component_from_hierarchy<'e, C: ::Component>(&mut self, entity: &Entity, mode: ChildrenMode)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.remove_component_from_hierarchy::<C>(entity, mode)
+    }
+
+    fn remove_entity_recursive<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.remove_entity_recursive::<C>(entity)
+    }
+
+    fn set_parent<'e, C: ::Component>(&mut self, entity: &Entity, parent: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.set_parent::<C>(entity, parent)
+    }
+
+    fn make_root<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.make_root::<C>(entity)
+    }
+
+    fn detach<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.detach::<C>(entity)
+    }
+
+    fn set_parent_in<H: Hierarchy>(&mut self, entity: &Entity, parent: &Entity){
+        self.set_parent_in::<H>(entity, parent)
+    }
+
+    fn make_root_in<H: Hierarchy>(&mut self, entity: &Entity){
+        self.make_root_in::<H>(entity)
+    }
+
+    fn remove_from_hierarchy<H: Hierarchy>(&mut self, entity: &Entity){
+        self.remove_from_hierarchy::<H>(entity)
+    }
+
+    fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        self.parent_in::<H>(entity)
+    }
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use std::slice;
 use std::usize;
 use std::mem;
 
-use storage::{Storage, IntoIter, IntoIterMut};
+use storage::{Storage, SliceStorage, IntoIter, IntoIterMut};
 use sync::{ReadGuardRef, WriteGuardRef, ReadGuard, WriteGuard};
 use densevec::DenseVec;
 
@@ -47,11 +47,44 @@ impl<'a, T: 'a> Storage<'a, T> for DenseVec<T>{
     }
 }
 
+impl<'a, T: 'a> SliceStorage<'a, T> for DenseVec<T>{
+    #[inline]
+    fn as_slice(&self) -> &[T]{
+        self.values().as_slice()
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [T]{
+        self.values_mut().into_slice()
+    }
+
+    // Position of the component for guid in the packed values. Zero sized
+    // types can't be located this way so they always report the first position
+    // which makes chunked iteration fall back to gathering them
+    #[inline]
+    unsafe fn position(&self, guid: usize) -> usize{
+        let size = mem::size_of::<T>();
+        if size == 0 {
+            0
+        }else{
+            let first = self.values().as_slice().as_ptr() as usize;
+            let component = self.get_unchecked(guid) as *const T as usize;
+            (component - first) / size
+        }
+    }
+}
+
 pub struct DenseIter<'a, T: 'a>{
     _guard: ReadGuardRef<'a, DenseVec<T>>,
     iter: slice::Iter<'a, T>
 }
 
+impl<'a, T: 'a> DenseIter<'a, T>{
+    pub fn as_slice(&self) -> &[T]{
+        self.iter.as_slice()
+    }
+}
+
 impl<'a, T: 'a> Iterator for DenseIter<'a, T>{
     type Item = &'a T;
     #[inline]
@@ -1,36 +1,342 @@
 use libloading as lib;
 use tempfile;
 use fxhash::FxHashMap as HashMap;
+use fxhash::FxHashSet as HashSet;
 #[cfg(unix)]
 use libloading::os::unix as libimp;
 #[cfg(windows)]
 use libloading::os::windows as libimp;
 use notify::{self, Watcher};
 
+use abi::{AbiFingerprint, AbiHeader, ComponentLayout, SystemKind, SystemManifest, layout_hash, version_hash};
 use system::{System, SystemThreadLocal, SystemWithData, SystemWithDataThreadLocal, CreationSystem, CreationSystemWithData};
 use ::Entities;
 use ::Resources;
 use ::EntitiesThreadLocal;
 use ::ResourcesThreadLocal;
 use ::EntitiesCreation;
+use ::Error;
 
 use std::process::Command;
-use std::error::Error;
+use std::error::Error as StdError;
 use std::sync::mpsc::{channel, Receiver};
-use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
 use std::collections::hash_map::Entry;
 use std::cell::UnsafeCell;
 use std::thread;
-use std::env::current_dir;
+use std::env::{self, current_dir};
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::ffi::CString;
 use std::time::Duration;
 use std::mem;
+use std::fmt;
 use std::os::raw::c_void;
 
 
+// File naming of the dynamic libraries, Platform uses the prefix and
+// extension of the platform the host is compiled for (lib*.so, lib*.dylib, *.dll)
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum LibraryNaming{
+    Platform,
+    Custom{ prefix: String, suffix: String },
+}
+
+impl LibraryNaming{
+    pub fn file_name(&self, library: &str) -> String{
+        match *self{
+            LibraryNaming::Platform =>
+                format!("{}{}{}", env::consts::DLL_PREFIX, library, env::consts::DLL_SUFFIX),
+            LibraryNaming::Custom{ ref prefix, ref suffix } =>
+                format!("{}{}{}", prefix, library, suffix),
+        }
+    }
+}
+
+// Background reloads from an updater thread as soon as files change, Poll
+// only processes file events when the loader is polled, World::run_once
+// polls at the start of every frame
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ReloadMode{
+    Background,
+    Poll,
+}
+
+// Where dynamic libraries are built and where their sources live. The
+// default follows cargo: CARGO_TARGET_DIR or target, debug or release
+// depending on the host build and sources in src/<lib> or src/systems/<lib>
+#[derive(Clone)]
+pub struct DynamicSystemsConfig{
+    pub target_dir: PathBuf,
+    pub profile: String,
+    pub library_naming: LibraryNaming,
+    pub source_roots: Vec<PathBuf>,
+    pub builder: Arc<SystemBuilder>,
+    pub reload_mode: ReloadMode,
+}
+
+// The builder is a trait object so it's left out
+impl fmt::Debug for DynamicSystemsConfig{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        f.debug_struct("DynamicSystemsConfig")
+            .field("target_dir", &self.target_dir)
+            .field("profile", &self.profile)
+            .field("library_naming", &self.library_naming)
+            .field("source_roots", &self.source_roots)
+            .field("reload_mode", &self.reload_mode)
+            .finish()
+    }
+}
+
+impl Default for DynamicSystemsConfig{
+    fn default() -> DynamicSystemsConfig{
+        let target_dir = env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("target"));
+        let profile = if cfg!(debug_assertions) { "debug" } else { "release" };
+        DynamicSystemsConfig{
+            target_dir,
+            profile: profile.to_owned(),
+            library_naming: LibraryNaming::Platform,
+            source_roots: vec![PathBuf::from("src"), Path::new("src").join("systems")],
+            builder: Arc::new(CargoBuilder::new()),
+            reload_mode: ReloadMode::Background,
+        }
+    }
+}
+
+impl DynamicSystemsConfig{
+    pub fn new() -> DynamicSystemsConfig{
+        DynamicSystemsConfig::default()
+    }
+
+    pub fn target_dir<P: Into<PathBuf>>(mut self, target_dir: P) -> DynamicSystemsConfig{
+        self.target_dir = target_dir.into();
+        self
+    }
+
+    pub fn profile(mut self, profile: &str) -> DynamicSystemsConfig{
+        self.profile = profile.to_owned();
+        self
+    }
+
+    pub fn library_naming(mut self, library_naming: LibraryNaming) -> DynamicSystemsConfig{
+        self.library_naming = library_naming;
+        self
+    }
+
+    pub fn source_roots<P: Into<PathBuf>, I: IntoIterator<Item = P>>(mut self, source_roots: I) -> DynamicSystemsConfig{
+        self.source_roots = source_roots.into_iter().map(|root| root.into()).collect();
+        self
+    }
+
+    pub fn builder<B: SystemBuilder + 'static>(mut self, builder: B) -> DynamicSystemsConfig{
+        self.builder = Arc::new(builder);
+        self
+    }
+
+    pub fn reload_mode(mut self, reload_mode: ReloadMode) -> DynamicSystemsConfig{
+        self.reload_mode = reload_mode;
+        self
+    }
+
+    // cargo outputs the dev and test profiles to debug and bench to release
+    pub fn library_dir(&self) -> PathBuf{
+        let profile_dir = match self.profile.as_str(){
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            profile => profile,
+        };
+        self.target_dir.join(profile_dir)
+    }
+
+    pub fn library_path(&self, library: &str) -> PathBuf{
+        self.library_dir().join(self.library_naming.file_name(library))
+    }
+
+    // First source root that contains a folder for the library
+    pub fn source_path(&self, library: &str) -> Option<PathBuf>{
+        self.source_roots.iter()
+            .map(|root| root.join(library))
+            .find(|source_path| source_path.exists())
+    }
+
+    fn owns_source(&self, library: &str, path: &Path) -> bool{
+        let current_dir = current_dir().unwrap();
+        self.source_roots.iter()
+            .any(|root| path.starts_with(current_dir.join(root).join(library)))
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BuildError{
+    pub diagnostics: Vec<Diagnostic>,
+    pub output: String,
+}
+
+// Builds dynamic libraries so they can be found at config.library_path
+// before they are first loaded, when preloading and when their sources change
+pub trait SystemBuilder: Send + Sync{
+    fn build(&self, config: &DynamicSystemsConfig, libraries: &[&str]) -> Result<(), BuildError>;
+}
+
+// Runs cargo build -p for every library with the config's profile and target
+// dir. The command can be replaced by any program accepting cargo's arguments
+pub struct CargoBuilder{
+    command: PathBuf,
+}
+
+impl CargoBuilder{
+    pub fn new() -> CargoBuilder{
+        CargoBuilder::with_command("cargo")
+    }
+
+    pub fn with_command<P: Into<PathBuf>>(command: P) -> CargoBuilder{
+        CargoBuilder{
+            command: command.into(),
+        }
+    }
+
+    fn args(&self, config: &DynamicSystemsConfig, libraries: &[&str]) -> Vec<String>{
+        let mut args = vec!["build".to_owned()];
+        match config.profile.as_str(){
+            "debug" | "dev" => (),
+            "release" => args.push("--release".to_owned()),
+            profile => {
+                args.push("--profile".to_owned());
+                args.push(profile.to_owned());
+            }
+        }
+        args.push("--target-dir".to_owned());
+        args.push(config.target_dir.to_string_lossy().into_owned());
+        for library in libraries {
+            args.push("-p".to_owned());
+            args.push((*library).to_owned());
+        }
+        args
+    }
+}
+
+impl SystemBuilder for CargoBuilder{
+    fn build(&self, config: &DynamicSystemsConfig, libraries: &[&str]) -> Result<(), BuildError>{
+        match Command::new(&self.command)
+            .args(&self.args(config, libraries))
+            .output()
+        {
+            Ok(output) => {
+                // the output is returned to the caller with the diagnostics
+                // through ReloadEvent::Failed
+                let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+                if output.status.success() {
+                    Ok(())
+                }else{
+                    Err(BuildError{
+                        diagnostics: parse_diagnostics(&stderr),
+                        output: stderr,
+                    })
+                }
+            }
+            Err(err) => {
+                Err(BuildError{
+                    diagnostics: vec![],
+                    output: err.description().to_owned(),
+                })
+            }
+        }
+    }
+}
+
+// For libraries that are built outside of the application
+pub struct Prebuilt;
+
+impl SystemBuilder for Prebuilt{
+    fn build(&self, _config: &DynamicSystemsConfig, _libraries: &[&str]) -> Result<(), BuildError>{
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DiagnosticLevel{
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Diagnostic{
+    pub level: DiagnosticLevel,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+// Progress of rebuilding and reloading dynamic libraries, drained with
+// World::reload_events or received in a Vec<ReloadEvent> resource
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ReloadEvent{
+    Started{ libraries: Vec<String> },
+    Succeeded{ libraries: Vec<String> },
+    Failed{ libraries: Vec<String>, diagnostics: Vec<Diagnostic>, output: String },
+    SymbolMissing{ library: String, system: String },
+    AbiMismatch{ library: String, system: String, reason: String },
+    Reloaded{ library: String },
+    NeedsData{ library: String, system: String },
+    Removed{ library: String, system: String },
+}
+
+// Extracts errors and warnings with their location from the compiler's
+// human readable output:
+//
+// error[E0425]: cannot find value `x` in this scope
+//  --> src/lib.rs:3:5
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic>{
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    for line in output.lines() {
+        let level = if line.starts_with("error") {
+            Some(DiagnosticLevel::Error)
+        }else if line.starts_with("warning") {
+            Some(DiagnosticLevel::Warning)
+        }else{
+            None
+        };
+
+        if let Some(level) = level {
+            if let Some(pos) = line.find(": ") {
+                let message = &line[pos + 2..];
+                let summary = message.starts_with("aborting due to")
+                    || message.starts_with("could not compile")
+                    || message.starts_with("build failed")
+                    || message.ends_with("emitted");
+                if !summary {
+                    diagnostics.push(Diagnostic{
+                        level,
+                        message: message.to_owned(),
+                        file: None,
+                        line: None,
+                        column: None,
+                    });
+                }
+            }
+        }else if line.trim_start().starts_with("--> ") {
+            if let Some(diagnostic) = diagnostics.last_mut().filter(|d| d.file.is_none()) {
+                let location = &line.trim_start()[4..];
+                let mut parts = location.rsplitn(3, ':');
+                let column = parts.next().and_then(|c| c.parse().ok());
+                let line = parts.next().and_then(|l| l.parse().ok());
+                match (parts.next(), line, column) {
+                    (Some(file), Some(line), Some(column)) => {
+                        diagnostic.file = Some(PathBuf::from(file));
+                        diagnostic.line = Some(line);
+                        diagnostic.column = Some(column);
+                    }
+                    _ => diagnostic.file = Some(PathBuf::from(location)),
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 struct SystemPath{
     library: String,
@@ -38,7 +344,7 @@ struct SystemPath{
 }
 
 impl SystemPath{
-    fn new(system_path: &str) -> Result<SystemPath, String>{
+    fn new(system_path: &str) -> Result<SystemPath, Error>{
         let mut system_parts = system_path.split("::");
         let lib_name = system_parts.next().expect("Empty system path");
         let system_name = system_parts.next()
@@ -49,12 +355,13 @@ impl SystemPath{
                 system: system_name.to_owned(),
             })
         }else{
-            Err("System path has more than two elements, correct format is: library::system".to_string())
+            Err(Error::DynamicSystems("System path has more than two elements, correct format is: library::system".to_string()))
         }
     }
 }
 
 struct Data{
+    config: DynamicSystemsConfig,
     library_names_index: HashMap<PathBuf, String>, //TODO: this can probably be a vector or use src path as index instead
     systems: DynamicSystemLoader<fn(Entities, Resources)>,
     systems_thread_local: DynamicSystemLoader<fn(EntitiesThreadLocal, ResourcesThreadLocal)>,
@@ -67,6 +374,9 @@ struct Data{
     source_watcher: notify::RecommendedWatcher,
     libs_rx: Receiver<notify::DebouncedEvent>,
     libs_watcher: notify::RecommendedWatcher,
+    layouts: HashMap<String, ComponentLayout>,
+    pending_reloads: Vec<PathBuf>,
+    events: Vec<ReloadEvent>,
     done: bool,
 }
 
@@ -78,7 +388,9 @@ pub struct DynamicSystemsLoader{
 impl Drop for DynamicSystemsLoader{
     fn drop(&mut self){
         self.data.lock().unwrap().done = true;
-        self.updater.take().unwrap().join().unwrap();
+        if let Some(updater) = self.updater.take() {
+            updater.join().unwrap();
+        }
         let mut data = self.data.lock().unwrap();
         data.systems.clear();
         data.systems_thread_local.clear();
@@ -90,189 +402,279 @@ impl Drop for DynamicSystemsLoader{
 }
 
 impl DynamicSystemsLoader{
-    pub fn new() -> Result<DynamicSystemsLoader,String>{
-        let data = Arc::new(Mutex::new(Data::new()?));
-        let loader_data = data.clone();
-        let updater = thread::spawn(move ||{
-            DynamicSystemsLoader::update(data);
-        });
+    pub fn new() -> Result<DynamicSystemsLoader, Error>{
+        DynamicSystemsLoader::with_config(DynamicSystemsConfig::default())
+    }
+
+    pub fn with_config(config: DynamicSystemsConfig) -> Result<DynamicSystemsLoader, Error>{
+        let reload_mode = config.reload_mode;
+        let data = Arc::new(Mutex::new(Data::new(config)?));
+        let updater = match reload_mode {
+            ReloadMode::Background => {
+                let data = data.clone();
+                Some(thread::spawn(move ||{
+                    DynamicSystemsLoader::update(data);
+                }))
+            }
+            ReloadMode::Poll => None,
+        };
         Ok(DynamicSystemsLoader{
-            data: loader_data,
-            updater: Some(updater),
+            data,
+            updater,
         })
     }
 
-    pub fn new_system(&mut self, system_path: &str) -> Result<DynamicSystem, String>{
+    fn lock_data(&self) -> Result<MutexGuard<Data>, Error>{
         self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_system(system_path)
+            .map_err(|e| Error::LockPoisoned(e.description().to_owned()))
     }
 
-    pub fn new_system_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemThreadLocal, String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_system_thread_local(system_path)
+    pub fn reload_mode(&self) -> ReloadMode{
+        if self.updater.is_some() { ReloadMode::Background } else { ReloadMode::Poll }
     }
 
-    pub fn new_system_with_data(&mut self, system_path: &str) -> Result<DynamicSystemWithData, String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_system_with_data(system_path)
+    // Processes pending source and library changes on the calling thread,
+    // returns the libraries that were reloaded
+    pub fn poll(&mut self) -> Result<Vec<String>, Error>{
+        let mut data = self.lock_data()?;
+        let data = &mut *data;
+        // build failures are reported through ReloadEvent::Failed
+        for library in data.changed_sources() {
+            let _ = DynamicSystemsLoader::recompile(&data.config, &[library.as_str()], &mut data.events);
+        }
+        data.update_libs();
+        Ok(data.reload_pending())
     }
 
-    pub fn new_system_with_data_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemWithDataThreadLocal, String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_system_with_data_thread_local(system_path)
+    // Swaps the libraries that changed since the last call, has to be
+    // called when no dynamic system is running
+    pub fn apply_reloads(&mut self) -> Result<Vec<String>, Error>{
+        self.lock_data()
+            .map(|mut data| data.reload_pending())
     }
 
-    pub fn new_creation_system(&mut self, system_path: &str) -> Result<DynamicCreationSystem, String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_creation_system(system_path)
+    // Systems exported by the library, loading it if needed
+    pub fn manifest(&mut self, library: &str) -> Result<Vec<SystemManifest>, Error>{
+        self.lock_data()?
+            .manifest(library)
     }
 
-    pub fn new_creation_system_with_data(&mut self, system_path: &str) -> Result<DynamicCreationSystemWithData, String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
-            .new_creation_system_with_data(system_path)
+    // Queues a reload of an already loaded library without waiting for
+    // it to change on disk
+    pub fn reload_library(&mut self, library: &str) -> Result<(), Error>{
+        self.lock_data()?
+            .queue_reload(library)
     }
 
-    pub fn start(&mut self) -> Result<(), String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
+    // Whether the system is loaded, systems removed from the manifest of
+    // their library are dropped by the loader when it's reloaded
+    pub fn contains_system(&mut self, system_path: &str) -> Result<bool, Error>{
+        self.lock_data()?
+            .contains_system(system_path, false)
+    }
+
+    // Whether a with data version of the system was created, which is only
+    // possible by passing it's data
+    pub fn contains_system_with_data(&mut self, system_path: &str) -> Result<bool, Error>{
+        self.lock_data()?
+            .contains_system(system_path, true)
+    }
+
+    pub fn new_system(&mut self, system_path: &str) -> Result<DynamicSystem, Error>{
+        self.lock_data()?
+            .new_system(system_path, false)
+    }
+
+    pub fn new_system_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemThreadLocal, Error>{
+        self.lock_data()?
+            .new_system_thread_local(system_path, false)
+    }
+
+    pub fn new_system_with_data(&mut self, system_path: &str) -> Result<DynamicSystemWithData, Error>{
+        self.lock_data()?
+            .new_system_with_data(system_path, false)
+    }
+
+    pub fn new_system_with_data_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemWithDataThreadLocal, Error>{
+        self.lock_data()?
+            .new_system_with_data_thread_local(system_path, false)
+    }
+
+    pub fn new_creation_system(&mut self, system_path: &str) -> Result<DynamicCreationSystem, Error>{
+        self.lock_data()?
+            .new_creation_system(system_path, false)
+    }
+
+    pub fn new_creation_system_with_data(&mut self, system_path: &str) -> Result<DynamicCreationSystemWithData, Error>{
+        self.lock_data()?
+            .new_creation_system_with_data(system_path, false)
+    }
+
+    // Systems added from a library manifest are removed when they disappear
+    // from it, systems added by path stay loaded until the library stops
+    // exporting them
+    pub fn new_manifest_system(&mut self, system_path: &str) -> Result<DynamicSystem, Error>{
+        self.lock_data()?
+            .new_system(system_path, true)
+    }
+
+    pub fn new_manifest_system_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemThreadLocal, Error>{
+        self.lock_data()?
+            .new_system_thread_local(system_path, true)
+    }
+
+    pub fn new_manifest_creation_system(&mut self, system_path: &str) -> Result<DynamicCreationSystem, Error>{
+        self.lock_data()?
+            .new_creation_system(system_path, true)
+    }
+
+    pub fn push_reload_event(&mut self, event: ReloadEvent) -> Result<(), Error>{
+        self.lock_data()
+            .map(|mut data| data.events.push(event))
+    }
+
+    pub fn start(&mut self) -> Result<(), Error>{
+        self.lock_data()?
             .start()
     }
 
-    pub fn preload_libraries(&mut self, libs: &[&str]) -> Result<(), String>{
-        self.data.lock()
-            .map_err(|e| format!("Couldn't lock dynamic system loader: {}", e.description()))?
+    pub fn preload_libraries(&mut self, libs: &[&str]) -> Result<(), Error>{
+        self.lock_data()?
             .preload_libraries(libs)
     }
 
-    fn recompile(libraries: &[&str]) {
-        println!("Recompiling {:?}", libraries);
+    // Layouts of the host components that libraries abi fingerprints are
+    // checked against
+    pub fn register_component_layout(&mut self, layout: ComponentLayout) -> Result<(), Error>{
+        self.lock_data()
+            .map(|mut data| { data.layouts.insert(layout.name.clone(), layout); })
+    }
 
-        let mut args = vec!["build"];
-        #[cfg(not(debug_assertions))]
-        args.push("--release");
+    pub fn reload_events(&mut self) -> Result<Vec<ReloadEvent>, Error>{
+        self.lock_data()
+            .map(|mut data| mem::replace(&mut data.events, vec![]))
+    }
 
-        for library in libraries {
-            args.push("-p");
-            args.push(library);
-        }
+    fn recompile(config: &DynamicSystemsConfig, libraries: &[&str], events: &mut Vec<ReloadEvent>) -> Result<(), Error> {
+        let libraries_names = libraries.iter().map(|l| (*l).to_owned()).collect::<Vec<_>>();
+        events.push(ReloadEvent::Started{ libraries: libraries_names.clone() });
 
-        match Command::new("cargo")
-            .args(&args)
-            .output()
-        {
-            Ok(output) => {
-                let stderr = CString::new(output.stderr).unwrap().into_string().unwrap();
-                let stdout = CString::new(output.stdout).unwrap().into_string().unwrap();
-                if output.status.success() {
-                    println!("Built succesfully {}\n{}", stdout, stderr);
-                }else{
-                    println!("Error building system {}\n{}", stdout, stderr);
-                }
+        match config.builder.build(config, libraries) {
+            Ok(()) => {
+                events.push(ReloadEvent::Succeeded{ libraries: libraries_names });
+                Ok(())
+            }
+            Err(BuildError{ diagnostics, output }) => {
+                events.push(ReloadEvent::Failed{
+                    libraries: libraries_names.clone(),
+                    diagnostics,
+                    output: output.clone(),
+                });
+                Err(Error::BuildFailed{
+                    libraries: libraries_names,
+                    output,
+                })
             }
-            Err(err) => println!("Error building system {}", err.description()),
         }
     }
 
+    // The build runs without holding the lock so run_once can keep applying
+    // reloads while a library compiles
     fn update(data: Arc<Mutex<Data>>){
         loop {
-            {
+            let (config, changed) = {
                 let mut data = data.lock().unwrap();
                 if data.done {
                     return;
                 }
+                (data.config.clone(), data.changed_sources())
+            };
+
+            let mut events = vec![];
+            for library in changed.iter() {
+                let _ = DynamicSystemsLoader::recompile(&config, &[library.as_str()], &mut events);
+            }
 
-                data.update_source();
+            {
+                let mut data = data.lock().unwrap();
+                data.events.extend(events);
                 data.update_libs();
             }
             thread::sleep(Duration::from_millis(16));
-
         }
     }
 }
 
-fn temporary_library<P: AsRef<Path>>(lib_path: P) -> Result<(lib::Library, tempfile::TempPath), String>{
+fn temporary_library(lib_path: &Path) -> Result<(lib::Library, tempfile::TempPath), Error>{
+    let load_error = |reason: String| Error::LibraryLoad{
+        library: lib_path.to_string_lossy().into_owned(),
+        reason,
+    };
     let mut templib = tempfile::NamedTempFile::new()
-        .map_err(|e| format!("Couldn't create temporary library: {}", e.description()))?;
+        .map_err(|e| load_error(format!("Couldn't create temporary library: {}", e.description())))?;
     let mut originallib = File::open(lib_path)
-        .map_err(|e| format!("Couldn't open library: {}", e.description()))?;
+        .map_err(|e| load_error(format!("Couldn't open library: {}", e.description())))?;
     let mut buf = vec![];
     originallib.read_to_end(&mut buf)
-        .map_err(|e| format!("Couldn't read library for temporary copy: {}", e.description()))?;
+        .map_err(|e| load_error(format!("Couldn't read library for temporary copy: {}", e.description())))?;
     templib.write_all(&buf)
-        .map_err(|e| format!("Couldn't write temporary library copy: {}", e.description()))?;
+        .map_err(|e| load_error(format!("Couldn't write temporary library copy: {}", e.description())))?;
 
     lib::Library::new(templib.path())
-        .map_err(|e| e.description().to_owned())
+        .map_err(|e| load_error(e.description().to_owned()))
         .map(|l| (l, templib.into_temp_path()))
 }
 
 impl Data{
-    fn new() -> Result<Data, String>{
+    fn new(config: DynamicSystemsConfig) -> Result<Data, Error>{
         let (tx, libs_rx) = channel();
         let libs_watcher: notify::RecommendedWatcher =
             notify::Watcher::new(tx, Duration::from_secs(1))
-                .map_err(|e| format!("Error creating watcher: {}", e.description()))?;
+                .map_err(|e| Error::DynamicSystems(format!("Error creating watcher: {}", e.description())))?;
         let (tx, source_rx) = channel();
         let source_watcher = notify::Watcher::new(tx, Duration::from_secs(1))
-            .map_err(|e| format!("Error creating watcher: {}", e.description()))?;
+            .map_err(|e| Error::DynamicSystems(format!("Error creating watcher: {}", e.description())))?;
 
         Ok(Data{
-            systems: DynamicSystemLoader::new(),
-            systems_thread_local: DynamicSystemLoader::new(),
-            systems_with_data: DynamicSystemLoader::new(),
-            systems_with_data_thread_local: DynamicSystemLoader::new(),
-            creation_systems: DynamicSystemLoader::new(),
-            creation_systems_with_data: DynamicSystemLoader::new(),
+            config,
+            systems: DynamicSystemLoader::new(SystemKind::Send),
+            systems_thread_local: DynamicSystemLoader::new(SystemKind::ThreadLocal),
+            systems_with_data: DynamicSystemLoader::new(SystemKind::SendWithData),
+            systems_with_data_thread_local: DynamicSystemLoader::new(SystemKind::ThreadLocalWithData),
+            creation_systems: DynamicSystemLoader::new(SystemKind::Creation),
+            creation_systems_with_data: DynamicSystemLoader::new(SystemKind::CreationWithData),
             libraries: HashMap::default(),
             library_names_index: HashMap::default(),
             source_watcher,
             libs_watcher,
             source_rx,
             libs_rx,
+            layouts: HashMap::default(),
+            pending_reloads: vec![],
+            events: vec![],
             done: false,
         })
     }
 
-    fn start(&mut self) -> Result<(), String>{
-        // let mut lib_path = PathBuf::from("target");constructor_name
-
-        // #[cfg(debug_assertions)]
-        // lib_path.push("debug");
-
-        // #[cfg(not(debug_assertions))]
-        // lib_path.push("release");
-
-        let exe_path = ::std::env::current_exe()
-            .map_err(|e| format!("Error trying to figure out dynamic library folder: {}", e.description()))?;
-        let lib_path = exe_path.parent().unwrap();
+    fn start(&mut self) -> Result<(), Error>{
+        let lib_path = self.config.library_dir();
         self.libs_watcher.watch(&lib_path, notify::RecursiveMode::NonRecursive)
-            .map_err(|e| format!("Error adding lib watch for {:?}: {}", lib_path, e.description()))
-    }
-
-    fn library_path(library: &str) -> PathBuf {
-        let lib_file = "lib".to_owned() + library + ".so";
-        let mut lib_path = PathBuf::from("target");
-        #[cfg(not(debug_assertions))]
-        lib_path.push("release");
-        #[cfg(debug_assertions)]
-        lib_path.push("debug");
-        lib_path.push(&lib_file);
-        lib_path
+            .map_err(|e| Error::DynamicSystems(format!("Error adding lib watch for {:?}: {}", lib_path, e.description())))
     }
 
-    pub fn preload_libraries(&mut self, libs: &[&str]) -> Result<(), String>{
-        DynamicSystemsLoader::recompile(libs);
+    pub fn preload_libraries(&mut self, libs: &[&str]) -> Result<(), Error>{
+        // a failed build only fails the libraries that weren't built before
+        let built = DynamicSystemsLoader::recompile(&self.config, libs, &mut self.events);
         for library in libs {
-            let lib_path = Self::library_path(library);
+            let lib_path = self.config.library_path(library);
             match self.libraries.entry(lib_path.clone()){
                 Entry::Occupied(lib) => (),
                 Entry::Vacant(vacant) => {
+                    if let Err(ref err) = built {
+                        if !lib_path.exists() {
+                            return Err(err.clone());
+                        }
+                    }
                     self.library_names_index.entry(lib_path.clone())
                         .or_insert((*library).to_owned());
 
@@ -286,14 +688,20 @@ impl Data{
         Ok(())
     }
 
-    fn load_library(&mut self, lib_name: &str) -> Result<DynamicLibrary, String>{
-        let lib_path = Self::library_path(lib_name);
+    fn load_library(&mut self, lib_name: &str) -> Result<DynamicLibrary, Error>{
+        let lib_path = self.config.library_path(lib_name);
 
         let library = match self.libraries.entry(lib_path.clone()){
             Entry::Occupied(lib) => lib.into_mut(),
             Entry::Vacant(vacant) => {
-                // Recompile library before first use to ensure that it's up to date
-                DynamicSystemsLoader::recompile(&[lib_name]);
+                // Recompile library before first use to ensure that it's up
+                // to date, if it fails a previous build is still loaded
+                let built = DynamicSystemsLoader::recompile(&self.config, &[lib_name], &mut self.events);
+                if let Err(err) = built {
+                    if !lib_path.exists() {
+                        return Err(err);
+                    }
+                }
 
                 self.library_names_index.entry(lib_path.clone())
                     .or_insert(lib_name.to_owned());
@@ -308,154 +716,128 @@ impl Data{
         Ok(library.clone())
     }
 
-    fn watch_source(&mut self, system_path: &SystemPath) -> Result<(), String>{
-        let mut source_path = PathBuf::from("src");
-        source_path.push(&system_path.library);
-        if source_path.exists() {
+    fn watch_source(&mut self, system_path: &SystemPath) -> Result<(), Error>{
+        if let Some(source_path) = self.config.source_path(&system_path.library) {
             self.source_watcher.watch(&source_path, notify::RecursiveMode::Recursive)
-                .map_err(|e| format!("Error adding source watch for {:?}: {}", source_path, e.description()))?;
+                .map_err(|e| Error::DynamicSystems(format!("Error adding source watch for {:?}: {}", source_path, e.description())))?;
         }else{
-            let mut source_path = PathBuf::from("src");
-            source_path.push("systems");
-            source_path.push(&system_path.library);
-            if source_path.exists() {
-                self.source_watcher.watch(&source_path, notify::RecursiveMode::Recursive)
-                    .map_err(|e| format!("Error adding source watch for {:?}: {}", source_path, e.description()))?;
-            }else{
-                println!("Error: couldn't find source for dynamic system {:?}", source_path) // TODO: Panic?
-            }
+            println!("Error: couldn't find source for dynamic system {} in {:?}", system_path.library, self.config.source_roots) // TODO: Panic?
         }
         Ok(())
     }
 
-    fn new_system(&mut self, system_path: &str) -> Result<DynamicGSystem<fn(Entities, Resources)>, String>{
+    fn new_system(&mut self, system_path: &str, removable: bool) -> Result<DynamicGSystem<fn(Entities, Resources)>, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.systems.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.systems.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn new_system_with_data(&mut self, system_path: &str) -> Result<DynamicSystemWithData, String>{
+    fn new_system_with_data(&mut self, system_path: &str, removable: bool) -> Result<DynamicSystemWithData, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.systems_with_data.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.systems_with_data.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn new_system_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemThreadLocal, String>{
+    fn new_system_thread_local(&mut self, system_path: &str, removable: bool) -> Result<DynamicSystemThreadLocal, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.systems_thread_local.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.systems_thread_local.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn new_system_with_data_thread_local(&mut self, system_path: &str) -> Result<DynamicSystemWithDataThreadLocal, String>{
+    fn new_system_with_data_thread_local(&mut self, system_path: &str, removable: bool) -> Result<DynamicSystemWithDataThreadLocal, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.systems_with_data_thread_local.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.systems_with_data_thread_local.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn new_creation_system(&mut self, system_path: &str) -> Result<DynamicCreationSystem, String>{
+    fn new_creation_system(&mut self, system_path: &str, removable: bool) -> Result<DynamicCreationSystem, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.creation_systems.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.creation_systems.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn new_creation_system_with_data(&mut self, system_path: &str) -> Result<DynamicCreationSystemWithData, String>{
+    fn new_creation_system_with_data(&mut self, system_path: &str, removable: bool) -> Result<DynamicCreationSystemWithData, Error>{
         let system_path = SystemPath::new(system_path)?;
 
         let library = self.load_library(&system_path.library)?;
-        let system = self.creation_systems_with_data.new_system(&library, &system_path)?;
+        let lib_path = self.config.library_path(&system_path.library);
+        let system = self.creation_systems_with_data.new_system(&library, &lib_path, &system_path, removable, &self.layouts)?;
 
         self.watch_source(&system_path)?;
 
         Ok(system)
     }
 
-    fn update_source(&mut self){
+    // Libraries with source changes since the last call, each only once
+    fn changed_sources(&mut self) -> Vec<String>{
+        let mut changed: Vec<String> = vec![];
         for e in self.source_rx.try_iter() {
             match e {
-                notify::DebouncedEvent::Write(lib_path) |
-                notify::DebouncedEvent::Create(lib_path) => {
+                notify::DebouncedEvent::Write(source_path) |
+                notify::DebouncedEvent::Create(source_path) => {
+                    let config = &self.config;
                     let library = self.library_names_index
-                        .iter()
-                        .map(|(_, lib_name)| {
-                            let mut source_path1 = current_dir().unwrap();
-                            source_path1.push("src");
-                            source_path1.push(lib_name);
-                            let mut source_path2 = current_dir().unwrap();
-                            source_path2.push("src");
-                            source_path2.push("systems");
-                            source_path2.push(lib_name);
-                            (source_path1, source_path2, lib_name)
-                        })
-                        .find(|&(ref source_path1, ref source_path2, _lib_name)| {
-                            println!("checking {:?} with {:?}", lib_path, source_path1);
-                            lib_path.starts_with(source_path1) || lib_path.starts_with(source_path2)
-                        })
-                        .map(|(_, _, lib_name)|{
-                            lib_name
-                        })
-                        .unwrap();
-                    DynamicSystemsLoader::recompile(&[library]);
+                        .values()
+                        .find(|lib_name| config.owns_source(lib_name, &source_path));
+                    // sources that don't belong to any loaded library are ignored
+                    if let Some(library) = library {
+                        if !changed.contains(library) {
+                            changed.push(library.clone());
+                        }
+                    }
                 }
 
                 _ => println!("System notify, other event"),
             }
         }
+        changed
     }
 
+    // Library changes are only queued here, they are applied by
+    // reload_pending between frames so systems never see a library swapped
+    // in the middle of a run
     fn update_libs(&mut self) {
         for e in self.libs_rx.try_iter() {
             match e {
                 notify::DebouncedEvent::Write(lib_path) |
                 notify::DebouncedEvent::Create(lib_path) => {
                     if let Some(lib_path) = self.libraries
-                        .iter()
-                        .find(|&(path, _)| lib_path.ends_with(path))
-                        .map(|(path, _)| path.clone())
+                        .keys()
+                        .find(|path| lib_path.ends_with(path))
+                        .cloned()
                     {
-
-                        let library = self.libraries.get_mut(&lib_path).unwrap();
-                        let mut old_library = library.write().unwrap();
-                        let mut unloaded_library = old_library.unload_library();
-
-                        if let Ok((library, templib)) = temporary_library(&lib_path) {
-                            let mut new_library = unloaded_library.load(library);
-
-                            self.systems.update(&lib_path, &new_library);
-                            self.systems_with_data.update(&lib_path, &new_library);
-                            self.systems_thread_local.update(&lib_path, &new_library);
-                            self.systems_with_data_thread_local.update(&lib_path, &new_library);
-                            self.creation_systems.update(&lib_path, &new_library);
-                            self.creation_systems_with_data.update(&lib_path, &new_library);
-
-                            new_library.set_new_library_tempfile(templib);
-                        }else{
-                            println!("Error: Couldn't reload library {:?}, trying to reload previous library", lib_path);
-                            unloaded_library.reload().unwrap();
+                        if !self.pending_reloads.contains(&lib_path) {
+                            self.pending_reloads.push(lib_path);
                         }
                     }
                 }
@@ -464,89 +846,407 @@ impl Data{
             }
         }
     }
+
+    fn queue_reload(&mut self, library: &str) -> Result<(), Error>{
+        let lib_path = self.config.library_path(library);
+        if !self.libraries.contains_key(&lib_path) {
+            return Err(Error::DynamicSystems(format!("Library {} is not loaded", library)));
+        }
+        if !self.pending_reloads.contains(&lib_path) {
+            self.pending_reloads.push(lib_path);
+        }
+        Ok(())
+    }
+
+    fn reload_pending(&mut self) -> Vec<String>{
+        mem::replace(&mut self.pending_reloads, vec![]).into_iter()
+            .filter_map(|lib_path| self.reload_library(lib_path))
+            .collect()
+    }
+
+    fn contains_system(&self, system_path: &str, with_data: bool) -> Result<bool, Error>{
+        let system_path = SystemPath::new(system_path)?;
+        let with_data_contains = self.systems_with_data.contains(&system_path)
+            || self.systems_with_data_thread_local.contains(&system_path)
+            || self.creation_systems_with_data.contains(&system_path);
+        if with_data {
+            Ok(with_data_contains)
+        }else{
+            Ok(with_data_contains
+                || self.systems.contains(&system_path)
+                || self.systems_thread_local.contains(&system_path)
+                || self.creation_systems.contains(&system_path))
+        }
+    }
+
+    fn manifest(&mut self, lib_name: &str) -> Result<Vec<SystemManifest>, Error>{
+        let library = self.load_library(lib_name)?;
+        let manifest = read_manifest(library.read().unwrap().library(), lib_name)?;
+        manifest.ok_or_else(|| Error::LibraryLoad{
+            library: lib_name.to_owned(),
+            reason: "Library doesn't export a manifest".to_owned(),
+        })
+    }
+
+    fn reload_library(&mut self, lib_path: PathBuf) -> Option<String>{
+        let library = self.library_names_index.get(&lib_path)
+            .cloned()
+            .unwrap_or_else(|| lib_path.to_string_lossy().into_owned());
+
+        // The new library is loaded next to the old one and checked
+        // before swapping so a failed reload keeps the old code running
+        let (new_library, templib) = match temporary_library(&lib_path) {
+            Ok(new_library) => new_library,
+            Err(err) => {
+                self.events.push(ReloadEvent::Failed{
+                    libraries: vec![library],
+                    diagnostics: vec![],
+                    output: err.to_string(),
+                });
+                return None;
+            }
+        };
+
+        // Systems no longer in the manifest are removed instead of reloaded
+        let manifest = match read_manifest(&new_library, &library) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                self.events.push(ReloadEvent::Failed{
+                    libraries: vec![library],
+                    diagnostics: vec![],
+                    output: err.to_string(),
+                });
+                return None;
+            }
+        };
+        let manifest = manifest.as_ref().map(|manifest| &manifest[..]);
+
+        let mut errors = vec![];
+        let layouts = &self.layouts;
+        self.systems.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        self.systems_with_data.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        self.systems_thread_local.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        self.systems_with_data_thread_local.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        self.creation_systems.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        self.creation_systems_with_data.check(&lib_path, &new_library, manifest, layouts, &mut errors);
+        if !errors.is_empty() {
+            self.events.extend(errors);
+            return None;
+        }
+
+        {
+            let library = self.libraries.get_mut(&lib_path).unwrap();
+            let old_library = library.write().unwrap();
+
+            let mut states = HashMap::default();
+            self.systems.unload(&lib_path, &old_library, &mut states);
+            self.systems_with_data.unload(&lib_path, &old_library, &mut states);
+            self.systems_thread_local.unload(&lib_path, &old_library, &mut states);
+            self.systems_with_data_thread_local.unload(&lib_path, &old_library, &mut states);
+            self.creation_systems.unload(&lib_path, &old_library, &mut states);
+            self.creation_systems_with_data.unload(&lib_path, &old_library, &mut states);
+
+            let mut new_library = old_library.unload_library().load(new_library);
+
+            let events = &mut self.events;
+            self.systems.update(&lib_path, &new_library, manifest, &mut states, events);
+            self.systems_with_data.update(&lib_path, &new_library, manifest, &mut states, events);
+            self.systems_thread_local.update(&lib_path, &new_library, manifest, &mut states, events);
+            self.systems_with_data_thread_local.update(&lib_path, &new_library, manifest, &mut states, events);
+            self.creation_systems.update(&lib_path, &new_library, manifest, &mut states, events);
+            self.creation_systems_with_data.update(&lib_path, &new_library, manifest, &mut states, events);
+
+            new_library.set_new_library_tempfile(templib);
+        }
+
+        self.events.push(ReloadEvent::Reloaded{ library: library.clone() });
+        Some(library)
+    }
 }
 
 
 pub struct DynamicSystemLoader<S>{
+    kind: SystemKind,
     systems: HashMap<SystemPath, DynamicGSystem<S>>,
     systems_per_library: HashMap<PathBuf, Vec<SystemPath>>,
+    // Systems added by path, the world keeps running them so they can't be
+    // removed with the manifest
+    pinned: HashSet<SystemPath>,
 }
 
 unsafe impl<S> Send for DynamicSystemLoader<S>{}
 
 impl<S> DynamicSystemLoader<S> {
-    pub fn new() -> DynamicSystemLoader<S> {
+    pub fn new(kind: SystemKind) -> DynamicSystemLoader<S> {
         DynamicSystemLoader{
+            kind,
             systems: HashMap::default(),
             systems_per_library: HashMap::default(),
+            pinned: HashSet::default(),
         }
     }
 
-    fn library_path(library: &str) -> PathBuf {
-        let lib_file = "lib".to_owned() + library + ".so";
-        let mut lib_path = PathBuf::from("target");
-        #[cfg(not(debug_assertions))]
-        lib_path.push("release");
-        #[cfg(debug_assertions)]
-        lib_path.push("debug");
-        lib_path.push(&lib_file);
-        lib_path
-    }
-
-    fn new_system(&mut self, library: &DynamicLibrary, system_path: &SystemPath) -> Result<DynamicGSystem<S>, String> {
+    fn new_system(&mut self, library: &DynamicLibrary, lib_path: &Path, system_path: &SystemPath, removable: bool, layouts: &HashMap<String, ComponentLayout>) -> Result<DynamicGSystem<S>, Error> {
         if let Some(system) = self.systems.get(system_path) {
+            if !removable {
+                self.pinned.insert(system_path.clone());
+            }
             return Ok(system.clone());
         }
 
-        let lib_path = Self::library_path(&system_path.library);
-
+        check_abi(library.read().unwrap().library(), system_path, self.kind, layouts)?;
         let system = library.load_generic_system(system_path)?;
 
+        if !removable {
+            self.pinned.insert(system_path.clone());
+        }
+
         self.systems.insert(system_path.clone(), system.clone());
 
-        self.systems_per_library.entry(lib_path.clone())
+        self.systems_per_library.entry(lib_path.to_owned())
             .or_insert(vec![])
             .push(system_path.clone());
 
         Ok(system)
     }
 
-    fn update(&mut self, lib_path: &PathBuf, new_library: &DynamicLibraryWriteGuard){
+    fn contains(&self, system_path: &SystemPath) -> bool{
+        self.systems.contains_key(system_path)
+    }
+
+    fn check(&self, lib_path: &PathBuf, new_library: &lib::Library, manifest: Option<&[SystemManifest]>, layouts: &HashMap<String, ComponentLayout>, errors: &mut Vec<ReloadEvent>){
+        if let Some(systems) = self.systems_per_library.get(lib_path){
+            for system_path in systems.iter().filter(|s| self.kept(manifest, s)) {
+                let system: lib::Result<lib::Symbol<S>> = unsafe{ new_library.get(system_path.system.as_bytes()) };
+                if system.is_err() {
+                    errors.push(ReloadEvent::SymbolMissing{
+                        library: system_path.library.clone(),
+                        system: system_path.system.clone(),
+                    });
+                }else if let Some(reason) = abi_mismatch(new_library, system_path, self.kind, layouts) {
+                    errors.push(ReloadEvent::AbiMismatch{
+                        library: system_path.library.clone(),
+                        system: system_path.system.clone(),
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+
+    // Lets the old library tear down its systems and serialize their data
+    // before it's unloaded
+    fn unload(&mut self, lib_path: &PathBuf, old_library: &DynamicLibraryWriteGuard, states: &mut SystemStates){
+        if let Some(systems) = self.systems_per_library.get(lib_path){
+            for system_path in systems {
+                let on_unload: Option<libimp::Symbol<OnUnload>> = hook(old_library, system_path, "on_unload");
+                if let Some(on_unload) = on_unload {
+                    let system = &self.systems[system_path];
+                    let system_states = states.entry(system_path.clone()).or_insert(vec![]);
+                    system.for_each_data(|data| system_states.push((data, (*on_unload)(data))));
+                }
+            }
+        }
+    }
+
+    // The new library receives the state serialized by the old one in
+    // migrate and can do any setup in on_load
+    fn update(&mut self, lib_path: &PathBuf, new_library: &DynamicLibraryWriteGuard, manifest: Option<&[SystemManifest]>, states: &mut SystemStates, events: &mut Vec<ReloadEvent>){
+        let pinned = &self.pinned;
         if let Some(systems) = self.systems_per_library.get_mut(lib_path){
+            for system_path in systems.iter().filter(|s| !pinned.contains(*s) && !in_manifest(manifest, &s.system)) {
+                self.systems.remove(system_path);
+                events.push(ReloadEvent::Removed{
+                    library: system_path.library.clone(),
+                    system: system_path.system.clone(),
+                });
+            }
+            systems.retain(|s| pinned.contains(s) || in_manifest(manifest, &s.system));
+
             println!("Reloading {:?} {:?}", lib_path, systems);
             for system_path in systems {
                 if let Ok(system) = unsafe{ new_library.get(system_path.system.as_bytes()) } {
                     println!("{}::{} reloaded", system_path.library, system_path.system);
-                    self.systems.get_mut(system_path).unwrap().set(system);
+                    let dynamic_system = self.systems.get_mut(system_path).unwrap();
+                    dynamic_system.set(system);
+
+                    let migrate: Option<libimp::Symbol<Migrate>> = hook(new_library, system_path, "migrate");
+                    let on_load: Option<libimp::Symbol<OnLoad>> = hook(new_library, system_path, "on_load");
+                    let mut system_states = states.remove(system_path).unwrap_or(vec![]);
+                    dynamic_system.for_each_data(|data| {
+                        if let Some(ref migrate) = migrate {
+                            let state = system_states.iter()
+                                .position(|&(state_data, _)| state_data == data)
+                                .map(|pos| system_states.swap_remove(pos).1)
+                                .unwrap_or(vec![]);
+                            (**migrate)(data, state);
+                        }
+                        if let Some(ref on_load) = on_load {
+                            (**on_load)(data);
+                        }
+                    });
                 }else{
                     println!("Error: {:?} reloaded but couldn't find system {}", lib_path, system_path.system);
+                    events.push(ReloadEvent::SymbolMissing{
+                        library: system_path.library.clone(),
+                        system: system_path.system.clone(),
+                    });
                 }
             }
         }
     }
 
+    fn kept(&self, manifest: Option<&[SystemManifest]>, system_path: &SystemPath) -> bool{
+        self.pinned.contains(system_path) || in_manifest(manifest, &system_path.system)
+    }
+
     fn clear(&mut self){
         self.systems.clear();
         self.systems_per_library.clear();
+        self.pinned.clear();
+    }
+}
+
+// Optional symbols exported next to a system as <system>_on_unload,
+// <system>_migrate and <system>_on_load. They receive a pointer to the data
+// of with data systems and are never called for systems without data
+type OnUnload = fn(*mut c_void) -> Vec<u8>;
+type Migrate = fn(*mut c_void, Vec<u8>);
+type OnLoad = fn(*mut c_void);
+
+// Systems can export their fingerprint as <system>_abi_header and
+// <system>_abi or the library can export rinecs_abi_header and rinecs_abi
+// for all of its systems as generated by dynamic_systems!. The header goes
+// through the C abi and is checked first, the component names are only read
+// once it's known the library uses the same rinecs. Libraries without
+// fingerprint are refused
+fn check_abi(library: &lib::Library, system_path: &SystemPath, kind: SystemKind, layouts: &HashMap<String, ComponentLayout>) -> Result<(), Error>{
+    match abi_mismatch(library, system_path, kind, layouts) {
+        Some(reason) => Err(Error::AbiMismatch{
+            library: system_path.library.clone(),
+            system: system_path.system.clone(),
+            reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+// Reason why the system can't be loaded from the library if any
+fn abi_mismatch(library: &lib::Library, system_path: &SystemPath, kind: SystemKind, layouts: &HashMap<String, ComponentLayout>) -> Option<String>{
+    let header = match abi_header(library, &system_path.system) {
+        Some(header) => header,
+        None => return Some("Doesn't export an abi fingerprint".to_owned()),
+    };
+    if let Err(reason) = header.check(kind) {
+        return Some(reason);
+    }
+    if header.components == 0 && header.layout_hash == layout_hash(&[]) {
+        return None;
+    }
+
+    let fingerprint = unsafe{
+        let system_abi: lib::Result<lib::Symbol<fn() -> AbiFingerprint>> =
+            library.get(format!("{}_abi", system_path.system).as_bytes());
+        let library_abi: lib::Result<lib::Symbol<fn(&str) -> Option<AbiFingerprint>>> =
+            library.get(b"rinecs_abi");
+        match (system_abi, library_abi) {
+            (Ok(abi), _) => Some(abi()),
+            (Err(_), Ok(abi)) => abi(&system_path.system),
+            (Err(_), Err(_)) => None,
+        }
+    };
+
+    match fingerprint {
+        Some(ref fingerprint) if fingerprint.layout_hash == header.layout_hash => fingerprint.check(kind, layouts).err(),
+        Some(_) => Some("Abi header and fingerprint differ".to_owned()),
+        None => Some("Doesn't export the components in its abi fingerprint".to_owned()),
+    }
+}
+
+fn abi_header(library: &lib::Library, system: &str) -> Option<AbiHeader>{
+    unsafe{
+        let system_abi: lib::Result<lib::Symbol<extern "C" fn() -> AbiHeader>> =
+            library.get(format!("{}_abi_header", system).as_bytes());
+        let library_abi: lib::Result<lib::Symbol<extern "C" fn(*const u8, usize, *mut AbiHeader) -> bool>> =
+            library.get(b"rinecs_abi_header");
+        match (system_abi, library_abi) {
+            (Ok(abi), _) => Some(abi()),
+            (Err(_), Ok(abi)) => {
+                let mut header = AbiHeader::default();
+                if abi(system.as_ptr(), system.len(), &mut header) {
+                    Some(header)
+                }else{
+                    None
+                }
+            }
+            (Err(_), Err(_)) => None,
+        }
     }
 }
 
+// The manifest crosses the library boundary as rust types so it's only read
+// once rinecs_version_hash shows the library uses the same rinecs
+fn read_manifest(library: &lib::Library, lib_name: &str) -> Result<Option<Vec<SystemManifest>>, Error>{
+    unsafe{
+        let manifest: lib::Result<lib::Symbol<fn() -> Vec<SystemManifest>>> = library.get(b"rinecs_manifest");
+        let manifest = match manifest {
+            Ok(manifest) => manifest,
+            Err(_) => return Ok(None),
+        };
+        let version: lib::Result<lib::Symbol<extern "C" fn() -> u64>> = library.get(b"rinecs_version_hash");
+        match version {
+            Ok(ref version) if version() == version_hash() => Ok(Some(manifest())),
+            Ok(_) => Err(Error::LibraryLoad{
+                library: lib_name.to_owned(),
+                reason: format!("Manifest built with a different rinecs than the host {}", env!("CARGO_PKG_VERSION")),
+            }),
+            Err(_) => Err(Error::LibraryLoad{
+                library: lib_name.to_owned(),
+                reason: "Manifest exported without rinecs_version_hash".to_owned(),
+            }),
+        }
+    }
+}
+
+// Libraries without manifest keep all their systems
+fn in_manifest(manifest: Option<&[SystemManifest]>, system: &str) -> bool{
+    manifest.map(|manifest| manifest.iter().any(|s| s.name == system)).unwrap_or(true)
+}
+
+type SystemStates = HashMap<SystemPath, Vec<(*mut c_void, Vec<u8>)>>;
+
+fn hook<T>(library: &DynamicLibraryWriteGuard, system_path: &SystemPath, name: &str) -> Option<libimp::Symbol<T>>{
+    let symbol = format!("{}_{}", system_path.system, name);
+    unsafe{ library.get(symbol.as_bytes()).ok() }
+}
+
 pub struct DynamicGSystem<S>{
     library: DynamicLibrary,
-    system: Arc<UnsafeCell<libimp::Symbol<S>>> // TODO: do we need a mutex here?
-                                                // probably only if we allow to run the
-                                                // system from outside the world
+    // Only replaced between frames while holding the library write lock
+    system: Arc<UnsafeCell<libimp::Symbol<S>>>,
+    // Data of every instance of this system, registered the first time
+    // each instance runs so reload hooks can access it
+    data: Arc<Mutex<Vec<*mut c_void>>>,
+    registered_data: Option<*mut c_void>,
 }
 
 impl<S> Clone for DynamicGSystem<S>{
     fn clone(&self) -> DynamicGSystem<S>{
         DynamicGSystem {
             library: self.library.clone(),
-            system: self.system.clone()
+            system: self.system.clone(),
+            data: self.data.clone(),
+            registered_data: None,
         }
     }
 }
 
+impl<S> Drop for DynamicGSystem<S>{
+    fn drop(&mut self){
+        self.unregister_data();
+    }
+}
+
 impl<S> DynamicGSystem<S>{
     fn set(&mut self, system: libimp::Symbol<S>){
         unsafe{
@@ -554,6 +1254,31 @@ impl<S> DynamicGSystem<S>{
             mem::forget(old_system);
         }
     }
+
+    fn register_data(&mut self, data: *mut c_void){
+        if self.registered_data != Some(data) {
+            self.unregister_data();
+            self.data.lock().unwrap().push(data);
+            self.registered_data = Some(data);
+        }
+    }
+
+    fn unregister_data(&mut self){
+        if let Some(registered) = self.registered_data.take() {
+            let mut data = self.data.lock().unwrap();
+            if let Some(pos) = data.iter().position(|d| *d == registered) {
+                data.swap_remove(pos);
+            }
+        }
+    }
+
+    // Systems without data or that haven't run yet have nothing registered
+    // and are skipped
+    fn for_each_data<F: FnMut(*mut c_void)>(&self, mut f: F){
+        for data in self.data.lock().unwrap().iter() {
+            f(*data)
+        }
+    }
 }
 
 unsafe impl<S> Send for DynamicGSystem<S>{}
@@ -582,19 +1307,28 @@ impl DynamicLibrary{
         self.0.write().map(|g| DynamicLibraryWriteGuard(g) )
     }
 
-    fn load_generic_system<S>(&self, system_path: &SystemPath) -> Result<DynamicGSystem<S>, String>{
+    fn load_generic_system<S>(&self, system_path: &SystemPath) -> Result<DynamicGSystem<S>, Error>{
         let system: libimp::Symbol<S> = unsafe{
             self.read().unwrap().get(system_path.system.as_bytes())
-                .map_err(|e| format!("Error loading symbol from {}::{}: {}", system_path.library, system_path.system, e.description()))?
+                .map_err(|_| Error::SymbolMissing{
+                    library: system_path.library.clone(),
+                    system: system_path.system.clone(),
+                })?
         };
         Ok(DynamicGSystem{
             library: self.clone(),
             system: Arc::new(UnsafeCell::new(system)),
+            data: Arc::new(Mutex::new(vec![])),
+            registered_data: None,
         })
     }
 }
 
 impl<'a> DynamicLibraryReadGuard<'a>{
+    fn library(&self) -> &lib::Library{
+        &(self.0).0
+    }
+
     unsafe fn get<T>(&self, symbol: &[u8]) -> lib::Result<libimp::Symbol<T>>{
         (self.0).0.get(symbol).map(|s: lib::Symbol<T>| s.into_raw())
     }
@@ -639,11 +1373,6 @@ impl<'a> DynamicLibraryWriteGuardUnloaded<'a>{
         mem::forget(self);
         DynamicLibraryWriteGuard(guard)
     }
-
-    fn reload(self) -> lib::Result<DynamicLibraryWriteGuard<'a>>{
-        let library = lib::Library::new((self.0).1.as_os_str())?;
-        Ok(self.load(library))
-    }
 }
 
 impl<'a> System<'a> for DynamicSystem{
@@ -655,8 +1384,10 @@ impl<'a> System<'a> for DynamicSystem{
 
 impl<'a, D: Send + 'static> SystemWithData<'a, D> for DynamicSystemWithData{
     fn run(&mut self, data: &mut D, entities: Entities, resources: Resources) {
+        let data = data as *mut D as *mut c_void;
+        self.register_data(data);
         let _lib_lock = self.library.read().unwrap();
-        unsafe{(*self.system.get())(data as *mut D as *mut c_void, entities, resources)}
+        unsafe{(*self.system.get())(data, entities, resources)}
     }
 }
 
@@ -669,8 +1400,10 @@ impl<'a> SystemThreadLocal<'a> for DynamicSystemThreadLocal{
 
 impl<'a, D: 'static> SystemWithDataThreadLocal<'a, D> for DynamicSystemWithDataThreadLocal{
     fn run(&mut self, data: &mut D, entities: EntitiesThreadLocal, resources: ResourcesThreadLocal) {
+        let data = data as *mut D as *mut c_void;
+        self.register_data(data);
         let _lib_lock = self.library.read().unwrap();
-        unsafe{(*self.system.get())(data as *mut D as *mut c_void, entities, resources)}
+        unsafe{(*self.system.get())(data, entities, resources)}
     }
 }
 
@@ -683,7 +1416,9 @@ impl<'a> CreationSystem<'a> for DynamicCreationSystem{
 
 impl<'a, D: 'static> CreationSystemWithData<'a, D> for DynamicCreationSystemWithData{
     fn run(&mut self, data: &mut D, entities: EntitiesCreation, resources: ResourcesThreadLocal) {
+        let data = data as *mut D as *mut c_void;
+        self.register_data(data);
         let _lib_lock = self.library.read().unwrap();
-        unsafe{(*self.system.get())(data as *mut D as *mut c_void, entities, resources)}
+        unsafe{(*self.system.get())(data, entities, resources)}
     }
 }
\ No newline at end of file
@@ -1,20 +1,40 @@
+use fxhash::FxHashMap as HashMap;
+use rayon::prelude::*;
+
 use ::World;
 use ::UnorderedData;
 use ::UnorderedDataLocal;
 use ::OrderedData;
 use ::OrderedDataLocal;
+use ::ParOrderedData;
+use ::ParComponentsRef;
+use ::HierarchicalData;
+use ::HierarchicalDataLocal;
+use ::Traversal;
 use ::Storage;
 use ::OneToNStorage;
 use ::HierarchicalStorage;
 use ::HierarchicalOneToNStorage;
+use ::ChunkedData;
+use ::ChunkRef;
+use ::ChildrenMode;
+use ::StorageRef;
+use query::Query;
+use storage::TraversalIter;
+use relation::{Relation, RelationsFrom, RelationsTo, Hierarchy};
+use chunked;
 use component::{Component, ComponentSync, ComponentThreadLocal,
     OneToNComponentSync, OneToNComponentThreadLocal,
     HierarchicalOneToNComponent, HierarchicalOneToNComponentSync, HierarchicalOneToNComponentThreadLocal};
 use sync::{ReadGuardRef, ReadGuard, WriteGuardRef, WriteGuard, Ptr, PtrMut, NodePtr, NodePtrMut};
 use boolinator::Boolinator;
 use ::MaskType;
+use ::Forest;
+use oneton_forest::{HierarchyIter, HierarchyAndParentIter, HierarchyAndParentIterMut};
+use ::Error;
 
 #[derive(Clone,Copy,Eq,PartialEq,Debug)]
+#[repr(transparent)]
 pub struct Entity {
     guid: usize,
 }
@@ -170,6 +190,29 @@ impl<'a, T:HierarchicalOneToNComponent> HierarchyBuilder<'a, T>{
     pub fn append_child(&mut self, parent: ::NodeId, t: T) -> ::NodeId {
         unsafe{ self.storage.insert_child(parent, t).id() }
     }
+
+    // The trees built so far as ReadHierarchy, ReadHierarchyAndParent and
+    // WriteHierarchyAndParent return them, None until a node is added
+    pub fn hierarchy(&self) -> Option<HierarchyIter<T>>{
+        let entity = self.entity;
+        self.storage.contains(entity)
+            .as_some_from(|| unsafe{ self.storage.hierarchy(entity) })
+    }
+
+    pub fn hierarchy_and_parent(&self) -> Option<HierarchyAndParentIter<T>>{
+        let entity = self.entity;
+        self.storage.contains(entity)
+            .as_some_from(|| unsafe{ self.storage.hierarchy_and_parent(entity) })
+    }
+
+    pub fn hierarchy_and_parent_mut(&mut self) -> Option<HierarchyAndParentIterMut<T>>{
+        let entity = self.entity;
+        if self.storage.contains(entity) {
+            Some(unsafe{ self.storage.hierarchy_and_parent_mut(entity) })
+        }else{
+            None
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -190,10 +233,64 @@ impl<'a> Entities<'a>{
         S::into_iter(self.world)
     }
 
+    pub fn try_iter_for<S: UnorderedData<'a> + 'a>(&self) -> Result<<S as UnorderedData<'a>>::Iter, Error>{
+        S::try_components_mask(self.world)?;
+        Ok(S::into_iter(self.world))
+    }
+
     pub fn ordered_iter_for<S: OrderedData<'a> + 'a>(&self) -> <S as OrderedData<'a>>::Iter{
         S::into_iter(self.world)
     }
 
+    // Iterates a hierarchy as specified by traversal returning the depth
+    // of each entity along with it's components
+    pub fn traverse_for<S: HierarchicalData<'a> + 'a>(&self, traversal: Traversal) -> TraversalIter<'a, <S as OrderedData<'a>>::ComponentsRef, <S as OrderedData<'a>>::Storage>
+        where <S as OrderedData<'a>>::Storage: StorageRef<'a, <S as OrderedData<'a>>::ComponentsRef>
+    {
+        let ids = S::traversal_ids(self.world, S::components_mask(self.world), &traversal);
+        TraversalIter::new(ids, S::storage(self.world))
+    }
+
+    pub fn storage_for<C: ::ComponentSync>(&self) -> ReadGuardRef<'a, <C as ::Component>::Storage> {
+        let storage = self.world.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        ReadGuardRef::new(ReadGuard::Sync(storage))
+    }
+
+    // Marks all the components of a gpu storage as uploaded. The rest of
+    // the storage is only accessible for reading through storage_for so the
+    // entities masks can't get out of sync with it
+    pub fn clear_dirty_for<C: ::GpuComponent + Send>(&self) {
+        self.world.storage_mut::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()))
+            .clear_dirty();
+    }
+
+    pub fn compact_for<C: ::OneToNComponentSync + Clone>(&self) {
+        self.world.storage_mut::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()))
+            .compact();
+    }
+
+    pub fn set_compaction_threshold_for<C: ::OneToNComponentSync + Clone>(&self, threshold: f32) {
+        self.world.storage_mut::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()))
+            .set_compaction_threshold(threshold);
+    }
+
+    pub fn query_for<S: UnorderedData<'a> + 'a>(&self) -> Query<'a, <S as UnorderedData<'a>>::ComponentsRef, <S as UnorderedData<'a>>::Storage, S>
+        where <S as UnorderedData<'a>>::Storage: StorageRef<'a, <S as UnorderedData<'a>>::ComponentsRef>
+    {
+        Query::new(self.world.entities_ref(), S::components_mask(self.world), S::storage(self.world))
+    }
+
+    pub fn for_each_chunk_for<S, F>(&self, max_len: usize, f: F)
+        where S: ChunkedData<'a> + 'a,
+              F: for<'b> FnMut(&'b [Entity], <S as ChunkRef<'b>>::Chunk)
+    {
+        chunked::for_each_chunk::<S,F>(self.world, max_len, f)
+    }
+
     pub fn component_for<C: ::ComponentSync>(&self, entity: &Entity) -> Option<Ptr<'a,C>> {
         let storage = self.world.storage::<C>()
             .expect(&format!("Trying to use non registered type {}", C::type_name()));
@@ -226,6 +323,135 @@ impl<'a> Entities<'a>{
             .as_some_from(|| NodePtrMut::new(storage, entity.clone()))
     }
 
+    pub fn parent_of<C: ::ComponentSync>(&self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        let storage = self.world.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.world.parent_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn children_of<C: ::ComponentSync>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        let storage = self.world.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.world.children_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn ancestors_of<C: ::ComponentSync>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        let storage = self.world.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.world.ancestors_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn descendants_of<C: ::ComponentSync>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        let storage = self.world.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.world.descendants_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        self.world.parent_in::<H>(entity)
+    }
+
+    pub fn relations_from<R: Relation + Send + Sync>(&self, source: &Entity) -> RelationsFrom<'a, R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsFrom::new(storage, source)
+    }
+
+    pub fn relations_to<R: Relation + Send + Sync>(&self, target: &Entity) -> RelationsTo<'a, R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsTo::new(storage, target)
+    }
+
+    // The entity must already have the component, adding it changes the
+    // entity's mask so it can only be done through EntitiesCreation
+    pub fn push_component_to<C: OneToNComponentSync>(&self, entity: &Entity, component: C){
+        self.one_to_n_storage_mut::<C>(entity).push(entity.guid(), component)
+    }
+
+    pub fn remove_component_at<C: OneToNComponentSync>(&self, entity: &Entity, index: usize) -> C{
+        unsafe{ self.one_to_n_storage_mut::<C>(entity).remove_at(entity.guid(), index) }
+    }
+
+    pub fn truncate_slice_component_of<C: OneToNComponentSync>(&self, entity: &Entity, len: usize){
+        unsafe{ self.one_to_n_storage_mut::<C>(entity).truncate(entity.guid(), len) }
+    }
+
+    pub fn replace_slice_component_of<C: OneToNComponentSync>(&self, entity: &Entity, component: &[C]){
+        self.one_to_n_storage_mut::<C>(entity).replace_slice(entity.guid(), component)
+    }
+
+    fn one_to_n_storage_mut<C: OneToNComponentSync>(&self, entity: &Entity) -> WriteGuardRef<'a, <C as Component>::Storage>{
+        let storage = self.world.storage_mut::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        if !storage.contains(entity.guid()){
+            panic!("Trying to modify non existing component {}", C::type_name());
+        }
+        WriteGuardRef::new(WriteGuard::Sync(storage))
+    }
+
+    pub fn par_ordered_for_each<S, F>(&self, f: F)
+        where S: ParOrderedData<'a> + 'a,
+              F: for<'b> Fn(<S as ParComponentsRef<'b>>::ComponentsRef) + Sync
+    {
+        S::par_for_each(self.world, f)
+    }
+
+    // Updates G for every entity in the hierarchy of L, in hierarchical order,
+    // combining the G of the parent with the L of the entity. Only the subtrees
+    // of entities whose L changed since the last call are updated. The new
+    // values of each tree are computed in parallel and then written back
+    pub fn propagate_hierarchy<L, G, F>(&self, f: F)
+        where L: ComponentSync<Storage = Forest<L>> + Sync,
+              G: ComponentSync + Sync,
+              for<'b> <G as Component>::Storage: Storage<'b, G, Get = &'b G, GetMut = &'b mut G>,
+              <G as Component>::Storage: Sync,
+              F: Fn(Option<&G>, &L) -> G + Sync
+    {
+        let mut local = self.world.storage_mut::<L>()
+            .expect(&format!("Trying to use non registered type {}", L::type_name()));
+        let mut global = self.world.storage_mut::<G>()
+            .expect(&format!("Trying to use non registered type {}", G::type_name()));
+        let updates = {
+            let global = &*global;
+            local.changed_subtrees().into_par_iter().map(|nodes| {
+                // new values of this tree and their position by guid, parents
+                // that were updated are read from here
+                let mut values: Vec<(usize, G)> = Vec::with_capacity(nodes.len());
+                let mut positions = HashMap::default();
+                for (guid, parent, l) in nodes {
+                    if global.contains(guid){
+                        let value = {
+                            let parent = parent.and_then(|parent| match positions.get(&parent){
+                                Some(&pos) => Some(&values[pos].1),
+                                None => global.contains(parent)
+                                    .as_some_from(|| unsafe{ global.get(parent) }),
+                            });
+                            f(parent, l)
+                        };
+                        positions.insert(guid, values.len());
+                        values.push((guid, value));
+                    }
+                }
+                values
+            }).collect::<Vec<_>>()
+        };
+        for values in updates {
+            for (guid, value) in values {
+                unsafe{ *global.get_mut(guid) = value }
+            }
+        }
+        local.clear_changed();
+    }
+
     // TODO: Is this useful? as it is it's not safe as there's no guard for the storage being kept
     // for the lifetime of the reference
     // pub fn get<S: UnorderedData<'a> + 'a>(&self, entity: &Entity) -> <S as UnorderedData<'a>>::ComponentsRef
@@ -249,10 +475,28 @@ impl<'a> EntitiesThreadLocal<'a>{
         S::into_iter(self.world)
     }
 
+    pub fn try_iter_for<S: UnorderedDataLocal<'a> + 'a>(&self) -> Result<<S as UnorderedDataLocal<'a>>::Iter, Error>{
+        S::try_components_mask(self.world)?;
+        Ok(S::into_iter(self.world))
+    }
+
     pub fn ordered_iter_for<S: OrderedDataLocal<'a> + 'a>(&self) -> <S as OrderedDataLocal<'a>>::Iter{
         S::into_iter(self.world)
     }
 
+    pub fn traverse_for<S: HierarchicalDataLocal<'a> + 'a>(&self, traversal: Traversal) -> TraversalIter<'a, <S as OrderedDataLocal<'a>>::ComponentsRef, <S as OrderedDataLocal<'a>>::Storage>
+        where <S as OrderedDataLocal<'a>>::Storage: StorageRef<'a, <S as OrderedDataLocal<'a>>::ComponentsRef>
+    {
+        let ids = S::traversal_ids(self.world, S::components_mask(self.world), &traversal);
+        TraversalIter::new(ids, S::storage(self.world))
+    }
+
+    pub fn query_for<S: UnorderedDataLocal<'a> + 'a>(&self) -> Query<'a, <S as UnorderedDataLocal<'a>>::ComponentsRef, <S as UnorderedDataLocal<'a>>::Storage, S>
+        where <S as UnorderedDataLocal<'a>>::Storage: StorageRef<'a, <S as UnorderedDataLocal<'a>>::ComponentsRef>
+    {
+        Query::new(self.world.entities_ref(), S::components_mask(self.world), S::storage(self.world))
+    }
+
     pub fn component_for<C: ::Component>(&self, entity: &Entity) -> Option<Ptr<'a,C>> {
         let storage = self.world.storage_thread_local::<C>()
             .expect(&format!("Trying to use non registered type {}", C::type_name()));
@@ -285,6 +529,73 @@ impl<'a> EntitiesThreadLocal<'a>{
             .as_some_from(|| NodePtrMut::new(storage, entity.clone()))
     }
 
+    pub fn parent_of<C: ::Component>(&self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        self.world.parent_of::<C>(entity)
+    }
+
+    pub fn children_of<C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        self.world.children_of::<C>(entity)
+    }
+
+    pub fn ancestors_of<C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        self.world.ancestors_of::<C>(entity)
+    }
+
+    pub fn descendants_of<C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'a, C>
+    {
+        self.world.descendants_of::<C>(entity)
+    }
+
+    pub fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        self.world.parent_in::<H>(entity)
+    }
+
+    pub fn relations_from<R: Relation + Send + Sync>(&self, source: &Entity) -> RelationsFrom<'a, R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsFrom::new(storage, source)
+    }
+
+    pub fn relations_to<R: Relation + Send + Sync>(&self, target: &Entity) -> RelationsTo<'a, R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsTo::new(storage, target)
+    }
+
+    // The entity must already have the component, adding it changes the
+    // entity's mask so it can only be done through EntitiesCreation
+    pub fn push_component_to<C: OneToNComponentThreadLocal>(&self, entity: &Entity, component: C){
+        self.one_to_n_storage_mut::<C>(entity).push(entity.guid(), component)
+    }
+
+    pub fn remove_component_at<C: OneToNComponentThreadLocal>(&self, entity: &Entity, index: usize) -> C{
+        unsafe{ self.one_to_n_storage_mut::<C>(entity).remove_at(entity.guid(), index) }
+    }
+
+    pub fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&self, entity: &Entity, len: usize){
+        unsafe{ self.one_to_n_storage_mut::<C>(entity).truncate(entity.guid(), len) }
+    }
+
+    pub fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&self, entity: &Entity, component: &[C]){
+        self.one_to_n_storage_mut::<C>(entity).replace_slice(entity.guid(), component)
+    }
+
+    fn one_to_n_storage_mut<C: OneToNComponentThreadLocal>(&self, entity: &Entity) -> WriteGuardRef<'a, <C as Component>::Storage>{
+        let storage = self.world.storage_thread_local_mut::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        if !storage.contains(entity.guid()){
+            panic!("Trying to modify non existing component {}", C::type_name());
+        }
+        storage
+    }
+
     // TODO: Is this useful? as it is it's not safe as there's no guard for the storage being kept
     // for the lifetime of the reference
     // pub fn get<S: UnorderedData<'a> + 'a>(&self, entity: &Entity) -> <S as UnorderedData<'a>>::ComponentsRef
@@ -310,10 +621,22 @@ impl<'a> EntitiesCreation<'a>{
         S::into_iter( self.world )
     }
 
+    pub fn try_iter_for<'e, S: UnorderedDataLocal<'e> + 'a>(&'e self) -> Result<<S as UnorderedDataLocal<'e>>::Iter, Error>{
+        S::try_components_mask( self.world )?;
+        Ok(S::into_iter( self.world ))
+    }
+
     pub fn ordered_iter_for<'e, S: OrderedDataLocal<'e> + 'a>(&'e self) -> <S as OrderedDataLocal<'e>>::Iter{
         S::into_iter( self.world )
     }
 
+    pub fn traverse_for<'e, S: HierarchicalDataLocal<'e> + 'a>(&'e self, traversal: Traversal) -> TraversalIter<'e, <S as OrderedDataLocal<'e>>::ComponentsRef, <S as OrderedDataLocal<'e>>::Storage>
+        where <S as OrderedDataLocal<'e>>::Storage: StorageRef<'e, <S as OrderedDataLocal<'e>>::ComponentsRef>
+    {
+        let ids = S::traversal_ids(self.world, S::components_mask(self.world), &traversal);
+        TraversalIter::new(ids, S::storage(self.world))
+    }
+
     pub fn component_for<C: ::Component>(&self, entity: &Entity) -> Option<Ptr<C>> {
         // let world = unsafe{ mem::transmute::<&mut World, &mut World>(self.world) };
         let storage = self.world.storage_thread_local::<C>()
@@ -347,6 +670,42 @@ impl<'a> EntitiesCreation<'a>{
             .as_some_from(|| NodePtrMut::new(storage, entity.clone()))
     }
 
+    pub fn parent_of<'e, C: ::Component>(&self, entity: &Entity) -> Option<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.parent_of::<C>(entity)
+    }
+
+    pub fn children_of<'e, C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.children_of::<C>(entity)
+    }
+
+    pub fn ancestors_of<'e, C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.ancestors_of::<C>(entity)
+    }
+
+    pub fn descendants_of<'e, C: ::Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.descendants_of::<C>(entity)
+    }
+
+    pub fn relations_from<R: Relation + Send + Sync>(&self, source: &Entity) -> RelationsFrom<R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsFrom::new(storage, source)
+    }
+
+    pub fn relations_to<R: Relation + Send + Sync>(&self, target: &Entity) -> RelationsTo<R>{
+        let storage = self.world.relation_storage::<R>()
+            .expect(&format!("Trying to use non registered relation {}", R::type_name()));
+        RelationsTo::new(storage, target)
+    }
+
     pub fn create_entity(&mut self) -> EntityBuilder{
         self.world.create_entity()
     }
@@ -355,6 +714,10 @@ impl<'a> EntitiesCreation<'a>{
         self.world.add_component_to(entity, component)
     }
 
+    pub fn try_add_component_to<C: ComponentSync>(&mut self, entity: &Entity, component: C) -> Result<(), Error>{
+        self.world.try_add_component_to(entity, component)
+    }
+
     pub fn add_component_to_thread_local<C: ComponentThreadLocal>(&mut self, entity: &Entity, component: C){
         self.world.add_component_to_thread_local(entity, component)
     }
@@ -367,10 +730,84 @@ impl<'a> EntitiesCreation<'a>{
         self.world.add_slice_component_to_thread_local(entity, component)
     }
 
+    pub fn push_component_to<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: C){
+        self.world.push_component_to(entity, component)
+    }
+
+    pub fn remove_component_at<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, index: usize) -> C{
+        self.world.remove_component_at::<C>(entity, index)
+    }
+
+    pub fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, len: usize){
+        self.world.truncate_slice_component_of::<C>(entity, len)
+    }
+
+    pub fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]){
+        self.world.replace_slice_component_of(entity, component)
+    }
+
+    pub fn add_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity, relation: R){
+        self.world.add_relation(source, target, relation)
+    }
+
+    pub fn remove_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity) -> Option<R>{
+        self.world.remove_relation::<R>(source, target)
+    }
+
     pub fn remove_component_from<C: ::Component>(&mut self, entity: &::Entity){
         self.world.remove_component_from::<C>(entity)
     }
 
+    pub fn try_remove_component_from<C: ::Component>(&mut self, entity: &::Entity) -> Result<(), Error>{
+        self.world.try_remove_component_from::<C>(entity)
+    }
+
+    pub fn remove_component_from_hierarchy<'e, C: ::Component>(&mut self, entity: &Entity, mode: ChildrenMode)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.remove_component_from_hierarchy::<C>(entity, mode)
+    }
+
+    pub fn remove_entity_recursive<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.remove_entity_recursive::<C>(entity)
+    }
+
+    pub fn set_parent<'e, C: ::Component>(&mut self, entity: &Entity, parent: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.set_parent::<C>(entity, parent)
+    }
+
+    pub fn make_root<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.make_root::<C>(entity)
+    }
+
+    pub fn detach<'e, C: ::Component>(&mut self, entity: &Entity)
+        where <C as ::Component>::Storage: ::HierarchicalStorage<'e, C>
+    {
+        self.world.detach::<C>(entity)
+    }
+
+    pub fn set_parent_in<H: Hierarchy>(&mut self, entity: &Entity, parent: &Entity){
+        self.world.set_parent_in::<H>(entity, parent)
+    }
+
+    pub fn make_root_in<H: Hierarchy>(&mut self, entity: &Entity){
+        self.world.make_root_in::<H>(entity)
+    }
+
+    pub fn remove_from_hierarchy<H: Hierarchy>(&mut self, entity: &Entity){
+        self.world.remove_from_hierarchy::<H>(entity)
+    }
+
+    pub fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        self.world.parent_in::<H>(entity)
+    }
+
     pub fn remove_entity(&mut self, entity: &::Entity){
         self.world.remove_entity(entity)
     }
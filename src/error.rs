@@ -0,0 +1,66 @@
+use std::fmt;
+use std::error;
+
+// Returned by the try_ variants of the World and Entities methods, the
+// rest of methods panic with the same message
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Error{
+    // Type name of the component, relation or hierarchy
+    AlreadyRegistered(String),
+    NotRegistered(String),
+    MissingComponent{ component: String, entity: usize },
+    // A component used by a system in a library manifest isn't registered
+    // in the world
+    SystemComponentNotRegistered{ library: String, system: String, component: String },
+    // A thread panicked while holding the dynamic systems loader lock
+    LockPoisoned(String),
+    LibraryLoad{ library: String, reason: String },
+    SymbolMissing{ library: String, system: String },
+    AbiMismatch{ library: String, system: String, reason: String },
+    BuildFailed{ libraries: Vec<String>, output: String },
+    DynamicSystems(String),
+}
+
+impl fmt::Display for Error{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self {
+            Error::AlreadyRegistered(ref name) =>
+                write!(f, "{} already registered or not unique component id", name),
+            Error::NotRegistered(ref name) =>
+                write!(f, "Trying to use component {} before registering", name),
+            Error::MissingComponent{ ref component, entity } =>
+                write!(f, "Entity {} doesn't have component {}", entity, component),
+            Error::SystemComponentNotRegistered{ ref library, ref system, ref component } =>
+                write!(f, "{}::{} uses component {} which is not registered", library, system, component),
+            Error::LockPoisoned(ref err) =>
+                write!(f, "Couldn't lock dynamic system loader: {}", err),
+            Error::LibraryLoad{ ref library, ref reason } =>
+                write!(f, "Couldn't load library {}: {}", library, reason),
+            Error::SymbolMissing{ ref library, ref system } =>
+                write!(f, "Library {} doesn't export system {}", library, system),
+            Error::AbiMismatch{ ref library, ref system, ref reason } =>
+                write!(f, "{}::{}: {}", library, system, reason),
+            Error::BuildFailed{ ref libraries, ref output } =>
+                write!(f, "Error building {:?}\n{}", libraries, output),
+            Error::DynamicSystems(ref err) =>
+                write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error{
+    fn description(&self) -> &str{
+        match *self {
+            Error::AlreadyRegistered(_) => "already registered",
+            Error::NotRegistered(_) => "not registered",
+            Error::MissingComponent{..} => "missing component",
+            Error::SystemComponentNotRegistered{..} => "system component not registered",
+            Error::LockPoisoned(_) => "lock poisoned",
+            Error::LibraryLoad{..} => "library load",
+            Error::SymbolMissing{..} => "symbol missing",
+            Error::AbiMismatch{..} => "abi mismatch",
+            Error::BuildFailed{..} => "build failed",
+            Error::DynamicSystems(ref err) => err,
+        }
+    }
+}
@@ -1,18 +1,33 @@
 use std::mem;
+use std::ptr;
 use std::sync::{RwLockReadGuard, RwLockWriteGuard};
-use std::cell::UnsafeCell;
+use std::ops::Range;
+use rayon::prelude::*;
 
 use idtree;
 use densevec::DenseVec;
-use sync::{ReadGuardRef, WriteGuardRef, ReadGuard, WriteGuard};
-use storage::{Storage, IntoIter, IntoIterMut, HierarchicalStorage, IntoOrderedIter, IntoOrderedIterMut};
+use sync::{ReadGuardRef, WriteGuardRef, ReadGuard, WriteGuard, SyncPtr};
+use storage::{Storage, IntoIter, IntoIterMut, HierarchicalStorage, IntoOrderedIter, IntoOrderedIterMut, ChildrenMode};
 
 pub struct Forest<T>{
     arena: idtree::Arena<T>,
     roots: Vec<idtree::NodeId>,
     index: DenseVec<idtree::NodeId>,
     reverse_index: DenseVec<usize>,
-    ordered_ids: UnsafeCell<Vec<usize>>,
+    // Guids in depth first order with their depth, a subtree is always the
+    // contiguous range after it's root with a greater depth.
+    // Adding a root is O(1) but inserting a child, removing or reparenting
+    // an entity shifts and updates the positions of everything after it in
+    // the order, so it's O(n) in the worst case: the first tree of a big
+    // forest. See bench_insert_remove_child in hierarchical_benches
+    ordered_ids: Vec<usize>,
+    depths: Vec<usize>,
+    // Position of each guid in ordered_ids
+    positions: Vec<usize>,
+    // Entities accessed mutably, moved in the hierarchy or inserted since
+    // the last call to clear_changed, indexed by guid
+    changed: Vec<bool>,
+    all_changed: bool,
 }
 
 impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
@@ -25,7 +40,11 @@ impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
             roots: Vec::new(),
             index: DenseVec::new(),
             reverse_index: DenseVec::new(),
-            ordered_ids: UnsafeCell::new(vec![]),
+            ordered_ids: vec![],
+            depths: vec![],
+            positions: vec![],
+            changed: vec![],
+            all_changed: false,
         }
     }
 
@@ -35,7 +54,11 @@ impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
             roots: Vec::with_capacity(capacity),
             index: DenseVec::with_capacity(capacity),
             reverse_index: DenseVec::with_capacity(capacity),
-            ordered_ids: UnsafeCell::new(Vec::with_capacity(capacity)),
+            ordered_ids: Vec::with_capacity(capacity),
+            depths: Vec::with_capacity(capacity),
+            positions: Vec::with_capacity(capacity),
+            changed: Vec::with_capacity(capacity),
+            all_changed: false,
         }
     }
 
@@ -44,20 +67,13 @@ impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
         self.index.insert(guid, node_id.id());
         self.reverse_index.insert(node_id.id().id(), guid);
         self.roots.push(node_id.id());
-        unsafe{ (*self.ordered_ids.get()).clear() };
+        self.inserted(guid);
+        let end = self.ordered_ids.len();
+        self.insert_ordered(end, guid, 0);
     }
 
     fn remove(&mut self, guid: usize){
-        {
-            let id = unsafe{ self.index.get_unchecked(guid) };
-            self.arena.remove(*id);
-            if let Some(pos) = self.roots.iter().position(|i| i == id){
-                self.roots.remove(pos);
-            }
-            self.reverse_index.remove(id.id());
-        }
-        self.index.remove(guid);
-        unsafe{ (*self.ordered_ids.get()).clear() };
+        unsafe{ self.remove_node(guid, ChildrenMode::ToGrandparent) }
     }
 
     unsafe fn get(&'a self, guid: usize) -> &'a T{
@@ -66,6 +82,7 @@ impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
     }
 
     unsafe fn get_mut(&'a mut self, guid: usize) -> &'a mut T{
+        *self.changed.get_unchecked_mut(guid) = true;
         let node_id = self.index.get_unchecked(guid);
         &mut self.arena[*node_id]
     }
@@ -75,6 +92,203 @@ impl<'a, T: 'a> Storage<'a, T> for Forest<T>{
     }
 }
 
+impl<T> Forest<T>{
+    pub fn is_changed(&self, guid: usize) -> bool{
+        self.all_changed || self.changed.get(guid).cloned().unwrap_or(false)
+    }
+
+    pub fn clear_changed(&mut self){
+        for changed in self.changed.iter_mut(){
+            *changed = false;
+        }
+        self.all_changed = false;
+    }
+
+    // Returns, per tree and computed in parallel, the guid of every changed
+    // entity and all it's descendants, their parent's guid and their value
+    // in hierarchical order
+    pub(crate) fn changed_subtrees(&self) -> Vec<Vec<(usize, Option<usize>, &T)>>
+        where T: Sync
+    {
+        let arena = &self.arena;
+        let reverse_index = &self.reverse_index;
+        let changed = &self.changed;
+        let all_changed = self.all_changed;
+        self.roots.par_iter().map(|root| {
+            let mut nodes = vec![];
+            // ancestors of the current node and if they were updated
+            let mut stack: Vec<(idtree::NodeId, bool)> = vec![];
+            for id in root.descendants(arena){
+                let parent = arena[id].parent();
+                while stack.last().map(|&(ancestor, _)| Some(ancestor) != parent).unwrap_or(false){
+                    stack.pop();
+                }
+                let guid = unsafe{ *reverse_index.get_unchecked(id.id()) };
+                let parent_changed = stack.last().map(|&(_, changed)| changed).unwrap_or(false);
+                let node_changed = parent_changed || all_changed || changed[guid];
+                if node_changed {
+                    let parent_guid = parent.map(|parent| unsafe{ *reverse_index.get_unchecked(parent.id()) });
+                    nodes.push((guid, parent_guid, &arena[id].data));
+                }
+                stack.push((id, node_changed));
+            }
+            nodes
+        }).filter(|nodes| !nodes.is_empty()).collect()
+    }
+
+    // Calls f with every node, in parallel for each tree and in hierarchical
+    // order inside each tree
+    pub(crate) fn par_for_each_mut<F>(&mut self, f: F)
+        where F: for<'b> Fn(&'b mut T) + Sync,
+              T: Send + Sync
+    {
+        self.par_for_each_tree(|t, _| f(t))
+    }
+
+    pub(crate) fn par_for_each_and_parent_mut<F>(&mut self, f: F)
+        where F: for<'b> Fn(&'b mut T, Option<&'b T>) + Sync,
+              T: Send + Sync
+    {
+        self.par_for_each_tree(f)
+    }
+
+    fn par_for_each_tree<F>(&mut self, f: F)
+        where F: for<'b> Fn(&'b mut T, Option<&'b T>) + Sync,
+              T: Send + Sync
+    {
+        self.all_changed = true;
+        let trees = {
+            let arena = &self.arena;
+            self.roots.iter()
+                .map(|root| root.descendants(arena)
+                    .map(|id| (id, arena[id].parent()))
+                    .collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+
+        // Pointers to the data of every node, by node index, all taken from
+        // the same mutable borrow of the arena so they don't alias
+        let mut data = vec![];
+        for node in self.arena.all_nodes_mut(){
+            let index = node.id().id();
+            if data.len() <= index {
+                data.resize(index + 1, ptr::null_mut());
+            }
+            data[index] = &mut node.data as *mut T;
+        }
+
+        // nodes can only reach other nodes in their own tree so each task
+        // only accesses it's own nodes mutably and parents are visited before
+        // their children
+        let trees = trees.into_iter()
+            .map(|tree| tree.into_iter()
+                .map(|(id, parent)| (
+                    SyncPtr(data[id.id()]),
+                    parent.map(|parent| SyncPtr(data[parent.id()]))
+                ))
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        trees.par_iter().for_each(|tree| {
+            for &(ref node, ref parent) in tree {
+                unsafe{ f(&mut *node.0, parent.as_ref().map(|parent| &*parent.0)) }
+            }
+        });
+    }
+
+    fn inserted(&mut self, guid: usize){
+        if self.changed.len() <= guid {
+            self.changed.resize(guid + 1, false);
+        }
+        self.changed[guid] = true;
+    }
+
+    fn mark_changed(&mut self, id: idtree::NodeId){
+        let guid = unsafe{ *self.reverse_index.get_unchecked(id.id()) };
+        self.changed[guid] = true;
+    }
+
+    fn remove_root(&mut self, id: idtree::NodeId){
+        if let Some(pos) = self.roots.iter().position(|root| *root == id){
+            self.roots.remove(pos);
+        }
+    }
+
+    // Moves the children of a node to it's parent in the node's position or,
+    // for roots or when promoting, to the roots right after the node's tree
+    fn release_children(&mut self, id: idtree::NodeId, mode: ChildrenMode){
+        let children = id.children(&self.arena).collect::<Vec<_>>();
+        for c in children.iter(){
+            self.mark_changed(*c);
+        }
+        if self.arena[id].parent().is_some() && mode == ChildrenMode::ToGrandparent{
+            for c in children.into_iter().rev(){
+                id.insert_after(c, &mut self.arena);
+            }
+        }else{
+            for c in children.iter(){
+                c.detach(&mut self.arena);
+            }
+            let root = id.ancestors(&self.arena).last().unwrap();
+            let pos = self.roots.iter().position(|r| *r == root)
+                .map(|pos| pos + 1)
+                .unwrap_or(self.roots.len());
+            self.roots.splice(pos..pos, children);
+        }
+    }
+
+    fn subtree_range(&self, guid: usize) -> Range<usize>{
+        let start = self.positions[guid];
+        let depth = self.depths[start];
+        let end = self.depths[start + 1 ..].iter()
+            .position(|d| *d <= depth)
+            .map(|len| start + 1 + len)
+            .unwrap_or(self.depths.len());
+        start .. end
+    }
+
+    fn subtree_end(&self, guid: usize) -> usize{
+        self.subtree_range(guid).end
+    }
+
+    fn update_positions(&mut self, from: usize){
+        for (pos, guid) in self.ordered_ids.iter().enumerate().skip(from){
+            self.positions[*guid] = pos;
+        }
+    }
+
+    fn insert_ordered(&mut self, pos: usize, guid: usize, depth: usize){
+        if self.positions.len() <= guid {
+            self.positions.resize(guid + 1, 0);
+        }
+        self.ordered_ids.insert(pos, guid);
+        self.depths.insert(pos, depth);
+        self.update_positions(pos);
+    }
+
+    fn remove_ordered(&mut self, pos: usize){
+        self.ordered_ids.remove(pos);
+        self.depths.remove(pos);
+        self.update_positions(pos);
+    }
+
+    // Moves a range of the ordered ids to pos, counted once the range has
+    // been removed, changing their depth by the passed offset
+    fn move_ordered<F>(&mut self, range: Range<usize>, pos: F, depth_offset: isize)
+        where F: FnOnce(&Self) -> usize
+    {
+        let start = range.start;
+        let ids = self.ordered_ids.drain(range.clone()).collect::<Vec<_>>();
+        let depths = self.depths.drain(range)
+            .map(|d| (d as isize + depth_offset) as usize)
+            .collect::<Vec<_>>();
+        self.update_positions(start);
+        let pos = pos(&*self);
+        self.ordered_ids.splice(pos..pos, ids);
+        self.depths.splice(pos..pos, depths);
+        self.update_positions(pos);
+    }
+}
+
 pub struct Iter<'a, T: 'a>{
     _guard: ReadGuardRef<'a, Forest<T>>,
     it: idtree::AllNodes<'a, T>,
@@ -121,6 +335,7 @@ impl<'a, T: 'a> Iterator for IterMut<'a, T>{
 impl<'a, T> IntoIterMut for WriteGuardRef<'a, Forest<T>>{
     type IterMut = IterMut<'a, T>;
     fn into_iter_mut(mut self) -> IterMut<'a, T>{
+        self.all_changed = true;
         IterMut{
             it: unsafe{mem::transmute::<idtree::AllNodesMut<T>, idtree::AllNodesMut<T>>(self.arena.all_nodes_mut())},
             _guard: self,
@@ -142,7 +357,11 @@ impl<'a,T: 'a> HierarchicalStorage<'a,T> for Forest<T>{
         let node_id = self.arena.get_mut(parent_id).append_new(value);
         self.index.insert(guid, node_id.id());
         self.reverse_index.insert(node_id.id().id(), guid);
-        (*self.ordered_ids.get()).clear();
+        self.inserted(guid);
+        let parent_pos = self.positions[parent_guid];
+        let depth = self.depths[parent_pos] + 1;
+        let end = self.subtree_end(parent_guid);
+        self.insert_ordered(end, guid, depth);
     }
 
     unsafe fn get_node(&self, guid: usize) -> idtree::NodeRef<T>{
@@ -151,31 +370,126 @@ impl<'a,T: 'a> HierarchicalStorage<'a,T> for Forest<T>{
     }
 
     unsafe fn get_node_mut(&mut self, guid: usize) -> idtree::NodeRefMut<T>{
+        *self.changed.get_unchecked_mut(guid) = true;
         let node_id = self.index.get_unchecked(guid);
         self.arena.get_mut(*node_id)
     }
 
-    //TODO: this is terribly unoptimized
-    fn ordered_ids(&self) -> &[usize]{
-        if unsafe{(*self.ordered_ids.get()).is_empty()}{
-            let iter_tree = ForestHierarchicalIdsIter{
-                forest: self,
-                current: if self.roots.is_empty(){ None } else { Some(0) },
-                iter: if self.roots.is_empty(){ None } else { Some(self.roots[0].descendants(&self.arena)) }
-            };
-            unsafe{ (*self.ordered_ids.get()).extend(iter_tree
-                //.map(|id| self.index.iter_ids().find(|&(pos, id2)| *id2 == id).map(|(pos, id)| pos).unwrap())
-                .map(|id| self.reverse_index.get_unchecked(id.id()) )
-            )};
+    // Moves the entity and all it's descendants under parent
+    unsafe fn set_parent(&mut self, guid: usize, parent_guid: usize){
+        let id = *self.index.get_unchecked(guid);
+        let parent_id = *self.index.get_unchecked(parent_guid);
+        if parent_id.ancestors(&self.arena).any(|ancestor| ancestor == id){
+            panic!("Trying to set a node as child of itself or one of it's descendants");
+        }
+        self.remove_root(id);
+        parent_id.append(id, &mut self.arena);
+        self.changed[guid] = true;
+        let range = self.subtree_range(guid);
+        let parent_depth = self.depths[self.positions[parent_guid]];
+        let depth_offset = parent_depth as isize + 1 - self.depths[range.start] as isize;
+        self.move_ordered(range, |forest| forest.subtree_end(parent_guid), depth_offset);
+    }
+
+    // Moves the entity and all it's descendants to a new tree
+    unsafe fn make_root(&mut self, guid: usize){
+        let id = *self.index.get_unchecked(guid);
+        if self.arena[id].parent().is_some(){
+            id.detach(&mut self.arena);
+            self.roots.push(id);
+            self.changed[guid] = true;
+            let range = self.subtree_range(guid);
+            let depth_offset = -(self.depths[range.start] as isize);
+            self.move_ordered(range, |forest| forest.ordered_ids.len(), depth_offset);
+        }
+    }
+
+    // Removes the entity from the hierarchy leaving it as a root without
+    // children, it's children take it's place in the parent or become roots
+    unsafe fn detach(&mut self, guid: usize){
+        let id = *self.index.get_unchecked(guid);
+        // the children stay in place one level up
+        let range = self.subtree_range(guid);
+        for depth in self.depths[range.start + 1 .. range.end].iter_mut(){
+            *depth -= 1;
+        }
+        self.release_children(id, ChildrenMode::ToGrandparent);
+        if self.arena[id].parent().is_some(){
+            id.detach(&mut self.arena);
+            self.roots.push(id);
+            self.changed[guid] = true;
+            let depth_offset = -(self.depths[range.start] as isize);
+            self.move_ordered(range.start .. range.start + 1, |forest| forest.ordered_ids.len(), depth_offset);
+        }
+    }
+
+    unsafe fn remove_node(&mut self, guid: usize, mode: ChildrenMode){
+        let id = *self.index.get_unchecked(guid);
+        let range = self.subtree_range(guid);
+        let depth = self.depths[range.start];
+        if depth > 0 && mode == ChildrenMode::ToRoots{
+            // the children's subtrees go after the node's tree as new roots
+            let root_pos = self.depths[.. range.start].iter().rposition(|d| *d == 0).unwrap();
+            let root_guid = self.ordered_ids[root_pos];
+            self.move_ordered(range.start + 1 .. range.end, |forest| forest.subtree_end(root_guid), -(depth as isize + 1));
+        }else{
+            // the children stay in place one level up
+            for depth in self.depths[range.start + 1 .. range.end].iter_mut(){
+                *depth -= 1;
+            }
         }
-        unsafe{ &(*self.ordered_ids.get()) }
+        self.remove_ordered(range.start);
+        self.release_children(id, mode);
+        self.remove_root(id);
+        self.arena.remove(id);
+        self.reverse_index.remove(id.id());
+        self.index.remove(guid);
+        self.changed[guid] = false;
+    }
+
+    unsafe fn parent_id(&self, guid: usize) -> Option<usize>{
+        let id = *self.index.get_unchecked(guid);
+        self.arena[id].parent().map(|parent| *self.reverse_index.get_unchecked(parent.id()))
+    }
+
+    unsafe fn children_ids(&self, guid: usize) -> Vec<usize>{
+        let id = *self.index.get_unchecked(guid);
+        id.children(&self.arena)
+            .map(|id| *self.reverse_index.get_unchecked(id.id()))
+            .collect()
+    }
+
+    unsafe fn ancestors_ids(&self, guid: usize) -> Vec<usize>{
+        let id = *self.index.get_unchecked(guid);
+        id.ancestors(&self.arena)
+            .skip(1)
+            .map(|id| *self.reverse_index.get_unchecked(id.id()))
+            .collect()
+    }
+
+    unsafe fn descendants_ids(&self, guid: usize) -> Vec<usize>{
+        let id = *self.index.get_unchecked(guid);
+        id.descendants(&self.arena)
+            .map(|id| *self.reverse_index.get_unchecked(id.id()))
+            .collect()
+    }
+
+    fn ordered_ids(&self) -> &[usize]{
+        &self.ordered_ids
+    }
+
+    fn ordered_depths(&self) -> &[usize]{
+        &self.depths
+    }
+
+    unsafe fn ordered_subtree(&self, guid: usize) -> Range<usize>{
+        self.subtree_range(guid)
     }
 }
 
 impl<'a, T> IntoOrderedIter for ReadGuardRef<'a, Forest<T>>{
     type OrderedIter = ForestHierarchicalIter<'a,T>;
     fn into_ordered_iter(self) -> Self::OrderedIter{
-        self.ordered_ids();
         ForestHierarchicalIter{
             // current: if self.roots.is_empty(){ None } else { Some(0) },
             //iter: if self.roots.is_empty(){ None } else { Some(self.roots[0].descendants(unsafe{mem::transmute::<&idtree::Arena<T>, &idtree::Arena<T>>(&self.arena)})) },
@@ -192,50 +506,6 @@ impl<'a, T> IntoOrderedIter for RwLockReadGuard<'a, Forest<T>>{
     }
 }
 
-struct ForestHierarchicalIdsIter<'a, T: 'a>{
-    forest: &'a Forest<T>,
-    current: Option<usize>,
-    iter: Option<idtree::Descendants<'a, T>>,
-}
-
-impl<'a, T: 'a> Iterator for ForestHierarchicalIdsIter<'a, T>{
-    type Item = idtree::NodeId;
-    fn next(&mut self) -> Option<idtree::NodeId>{
-        let iter = if let Some(ref mut iter) = self.iter{
-            let next = iter.next();
-            if let Some(next) = next{
-                return Some(self.forest.arena.get(next).id());
-            }else{
-                if let Some(current) = self.current{
-                    if current + 1 < self.forest.roots.len(){
-                        self.current = Some(current + 1);
-                        Some(self.forest.roots[current + 1].descendants(unsafe{mem::transmute::<&idtree::Arena<T>, &idtree::Arena<T>>(&self.forest.arena)}))
-                    }else{
-                        self.current = None;
-                        None
-                    }
-                }else{
-                    None
-                }
-            }
-        }else{
-            None
-        };
-
-        if let Some(mut iter) = iter{
-            let next = iter.next().map(|id| self.forest.arena.get(id).id() );
-            self.iter = Some(iter);
-            next
-        }else{
-            None
-        }
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>){
-        (self.forest.arena.len(), Some(self.forest.arena.len()))
-    }
-}
-
 pub struct ForestHierarchicalIter<'a, T: 'a>{
     forest: ReadGuardRef<'a, Forest<T>>,
     // current: Option<usize>,
@@ -246,10 +516,10 @@ pub struct ForestHierarchicalIter<'a, T: 'a>{
 impl<'a, T: 'a> Iterator for ForestHierarchicalIter<'a, T>{
     type Item = idtree::NodeRef<'a,T>;
     fn next(&mut self) -> Option<idtree::NodeRef<'a,T>>{
-        if self.next == unsafe{(*self.forest.ordered_ids.get()).len()}{
+        if self.next == self.forest.ordered_ids.len(){
             None
         }else{
-            let next = unsafe{ *(*self.forest.ordered_ids.get()).get_unchecked(self.next) };
+            let next = unsafe{ *self.forest.ordered_ids.get_unchecked(self.next) };
             let node = unsafe{ self.forest.get_node(next) };
             self.next += 1;
             let node = unsafe{ mem::transmute::<idtree::NodeRef<T>, idtree::NodeRef<T>>(node) };
@@ -265,7 +535,6 @@ impl<'a, T: 'a> Iterator for ForestHierarchicalIter<'a, T>{
 impl<'a, T> IntoOrderedIterMut for WriteGuardRef<'a, Forest<T>>{
     type OrderedIterMut = ForestHierarchicalIterMut<'a,T>;
     fn into_ordered_iter_mut(self) -> Self::OrderedIterMut{
-        self.ordered_ids();
         ForestHierarchicalIterMut{
             // current: if self.roots.is_empty(){ None } else { Some(0) },
             // iter: if self.roots.is_empty(){ None } else { Some(self.roots[0].descendants(unsafe{mem::transmute::<&idtree::Arena<T>, &idtree::Arena<T>>(&self.arena)})) },
@@ -292,10 +561,10 @@ pub struct ForestHierarchicalIterMut<'a, T: 'a>{
 impl<'a, T: 'a> Iterator for ForestHierarchicalIterMut<'a, T>{
     type Item = idtree::NodeRefMut<'a,T>;
     fn next(&mut self) -> Option<idtree::NodeRefMut<'a,T>>{
-        if self.next == unsafe{(*self.forest.ordered_ids.get()).len()}{
+        if self.next == self.forest.ordered_ids.len(){
             None
         }else{
-            let next = unsafe{ *(*self.forest.ordered_ids.get()).get_unchecked(self.next) };
+            let next = unsafe{ *self.forest.ordered_ids.get_unchecked(self.next) };
             let node = unsafe{ self.forest.get_node_mut(next) };
             self.next += 1;
             let node = unsafe{ mem::transmute::<idtree::NodeRefMut<T>, idtree::NodeRefMut<T>>(node) };
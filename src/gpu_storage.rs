@@ -0,0 +1,246 @@
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::ops::Range;
+use std::slice;
+use std::mem;
+use std::iter;
+
+use densevec::DenseVec;
+use storage::{Storage, IntoIter, IntoIterMut};
+use sync::{ReadGuardRef, WriteGuardRef, ReadGuard, WriteGuard};
+use ::Component;
+
+pub trait GpuComponent: Component<Storage = GpuStorage<Self>>{}
+
+// Types that can be read as plain bytes. Only implement it for #[repr(C)]
+// types without padding, padding bytes are uninitialized and reading them
+// through as_bytes is undefined behaviour
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t: ty),*) => ($(
+        unsafe impl Pod for $t {}
+    )*)
+}
+
+impl_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+macro_rules! impl_pod_array {
+    ($($n: expr),*) => ($(
+        unsafe impl<T: Pod> Pod for [T; $n] {}
+    )*)
+}
+
+impl_pod_array!(1, 2, 3, 4, 8, 9, 16);
+
+// Packed storage meant to be uploaded to the gpu as is. Every mutable access
+// marks the element as dirty so only the modified spans need to be uploaded
+pub struct GpuStorage<T>{
+    data: Vec<T>,
+    dirty: Vec<bool>,
+    dirty_count: usize,
+    index: DenseVec<usize>,
+    ids: Vec<usize>,
+}
+
+impl<T> GpuStorage<T>{
+    pub fn len(&self) -> usize{
+        self.data.len()
+    }
+
+    pub fn as_slice(&self) -> &[T]{
+        &self.data
+    }
+
+    // Ranges of elements modified since the last call to clear_dirty,
+    // multiply by size_of::<T>() to get the ranges in as_bytes()
+    pub fn dirty_ranges(&self) -> Vec<Range<usize>>{
+        let mut ranges = vec![];
+        let mut start = None;
+        for (i, dirty) in self.dirty.iter().enumerate(){
+            match (*dirty, start){
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    ranges.push(s .. i);
+                    start = None;
+                }
+                _ => ()
+            }
+        }
+        if let Some(s) = start{
+            ranges.push(s .. self.dirty.len());
+        }
+        ranges
+    }
+
+    pub fn is_dirty(&self) -> bool{
+        self.dirty_count > 0
+    }
+
+    pub fn clear_dirty(&mut self){
+        for dirty in self.dirty.iter_mut(){
+            *dirty = false;
+        }
+        self.dirty_count = 0;
+    }
+
+    fn set_dirty(&mut self, pos: usize){
+        mark_dirty(&mut self.dirty[pos], &mut self.dirty_count);
+    }
+}
+
+fn mark_dirty(dirty: &mut bool, dirty_count: &mut usize){
+    if !*dirty {
+        *dirty = true;
+        *dirty_count += 1;
+    }
+}
+
+impl<T: Pod> GpuStorage<T>{
+    pub fn as_bytes(&self) -> &[u8]{
+        unsafe{
+            slice::from_raw_parts(
+                self.data.as_ptr() as *const u8,
+                self.data.len() * mem::size_of::<T>())
+        }
+    }
+}
+
+impl<'a, T: 'a> Storage<'a, T> for GpuStorage<T>{
+    type Get = &'a T;
+    type GetMut = &'a mut T;
+
+    fn new() -> GpuStorage<T>{
+        GpuStorage{
+            data: vec![],
+            dirty: vec![],
+            dirty_count: 0,
+            index: DenseVec::new(),
+            ids: vec![],
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> GpuStorage<T>{
+        GpuStorage{
+            data: Vec::with_capacity(capacity),
+            dirty: Vec::with_capacity(capacity),
+            dirty_count: 0,
+            index: DenseVec::with_capacity(capacity),
+            ids: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn insert(&mut self, guid: usize, t: T){
+        if self.index.contains_key(guid){
+            let pos = unsafe{ *self.index.get_unchecked(guid) };
+            self.data[pos] = t;
+            self.set_dirty(pos);
+        }else{
+            self.index.insert(guid, self.data.len());
+            self.data.push(t);
+            self.dirty.push(true);
+            self.dirty_count += 1;
+            self.ids.push(guid);
+        }
+    }
+
+    // The last element is moved to the removed position so it's marked as dirty
+    fn remove(&mut self, guid: usize){
+        if let Some(pos) = self.index.remove(guid){
+            self.data.swap_remove(pos);
+            if self.dirty.swap_remove(pos){
+                self.dirty_count -= 1;
+            }
+            self.ids.swap_remove(pos);
+            if pos < self.data.len(){
+                let moved = self.ids[pos];
+                *self.index.get_mut(moved).unwrap() = pos;
+                self.set_dirty(pos);
+            }
+        }
+    }
+
+    unsafe fn get(&'a self, guid: usize) -> &'a T{
+        let pos = *self.index.get_unchecked(guid);
+        self.data.get_unchecked(pos)
+    }
+
+    unsafe fn get_mut(&'a mut self, guid: usize) -> &'a mut T{
+        let pos = *self.index.get_unchecked(guid);
+        mark_dirty(self.dirty.get_unchecked_mut(pos), &mut self.dirty_count);
+        self.data.get_unchecked_mut(pos)
+    }
+
+    fn contains(&self, guid: usize) -> bool{
+        self.index.contains_key(guid)
+    }
+}
+
+pub struct Iter<'a, T: 'a>{
+    _guard: ReadGuardRef<'a, GpuStorage<T>>,
+    iter: slice::Iter<'a, T>
+}
+
+impl<'a, T: 'a> Iterator for Iter<'a, T>{
+    type Item = &'a T;
+    #[inline]
+    fn next(&mut self) -> Option<&'a T>{
+        self.iter.next()
+    }
+}
+
+pub struct IterMut<'a, T: 'a>{
+    _guard: WriteGuardRef<'a, GpuStorage<T>>,
+    iter: iter::Zip<slice::IterMut<'a, T>, slice::IterMut<'a, bool>>,
+    dirty_count: &'a mut usize,
+}
+
+impl<'a, T: 'a> Iterator for IterMut<'a, T>{
+    type Item = &'a mut T;
+    #[inline]
+    fn next(&mut self) -> Option<&'a mut T>{
+        let dirty_count = &mut *self.dirty_count;
+        self.iter.next().map(|(t, dirty)| {
+            mark_dirty(dirty, dirty_count);
+            t
+        })
+    }
+}
+
+impl<'a, T> IntoIter for ReadGuardRef<'a, GpuStorage<T>>{
+    type Iter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T>{
+        Iter{
+            iter: unsafe{ mem::transmute::<slice::Iter<T>, slice::Iter<T>>(self.data.iter()) },
+            _guard: self,
+        }
+    }
+}
+
+impl<'a, T> IntoIter for RwLockReadGuard<'a, GpuStorage<T>>{
+    type Iter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T>{
+        ReadGuardRef::new(ReadGuard::Sync(self)).into_iter()
+    }
+}
+
+impl<'a, T> IntoIterMut for WriteGuardRef<'a, GpuStorage<T>>{
+    type IterMut = IterMut<'a, T>;
+    fn into_iter_mut(mut self) -> IterMut<'a, T>{
+        let (iter, dirty_count) = unsafe{
+            let storage = mem::transmute::<&mut GpuStorage<T>, &mut GpuStorage<T>>(&mut *self);
+            (storage.data.iter_mut().zip(storage.dirty.iter_mut()), &mut storage.dirty_count)
+        };
+        IterMut{
+            iter,
+            dirty_count,
+            _guard: self,
+        }
+    }
+}
+
+impl<'a, T> IntoIterMut for RwLockWriteGuard<'a, GpuStorage<T>>{
+    type IterMut = IterMut<'a, T>;
+    fn into_iter_mut(self) -> IterMut<'a, T>{
+        WriteGuardRef::new(WriteGuard::Sync(self)).into_iter_mut()
+    }
+}
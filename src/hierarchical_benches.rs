@@ -104,3 +104,47 @@ fn bench_update(b: &mut Bencher) {
         }
     });
 }
+
+// Spawns and removes a child in the first of N_POS trees which shifts the
+// ordered ids of every other tree, the worst case for Forest's insert_child
+// and remove
+#[bench]
+fn bench_insert_remove_child(b: &mut Bencher) {
+    let mut world = ::World::new();
+    world.register::<Position>();
+    let roots = (0..N_POS).map(|_| world.create_entity()
+        .add(Position { x: 0.0, y: 0.0 })
+        .build()
+    ).collect::<Vec<_>>();
+    for root in roots.iter() {
+        world.create_entity()
+            .add_child(root, Position { x: 0.0, y: 0.0 })
+            .build();
+    }
+
+    b.iter(||{
+        let child = world.create_entity()
+            .add_child(&roots[0], Position { x: 0.0, y: 0.0 })
+            .build();
+        world.remove_entity(&child);
+    });
+}
+
+// Spawns and removes a child in the last tree, only the positions after it
+// in the order are updated
+#[bench]
+fn bench_insert_remove_child_last(b: &mut Bencher) {
+    let mut world = ::World::new();
+    world.register::<Position>();
+    let roots = (0..N_POS).map(|_| world.create_entity()
+        .add(Position { x: 0.0, y: 0.0 })
+        .build()
+    ).collect::<Vec<_>>();
+
+    b.iter(||{
+        let child = world.create_entity()
+            .add_child(&roots[N_POS - 1], Position { x: 0.0, y: 0.0 })
+            .build();
+        world.remove_entity(&child);
+    });
+}
@@ -19,16 +19,22 @@ extern crate libloading;
 extern crate notify;
 #[cfg(feature="dynamic_systems")]
 extern crate tempfile;
+#[cfg(feature="dynamic_systems")]
+extern crate rinecs_derive;
 
 use sync::*;
 use storage::*;
 use bitmask::*;
 pub use storage::{Read, Write, Not, ReadNot, ReadOr, ReadOption,
-    Storage, IntoIter, IntoIterMut,
+    Storage, IntoIter, IntoIterMut, ReadOnlyStorage,
     ReadEntities,
-    ReadHierarchical, WriteHierarchical, HierarchicalStorage,
+    ReadHierarchical, WriteHierarchical, HierarchicalStorage, ChildrenMode,
     IntoOrderedIter, IntoOrderedIterMut, ReadAndParent, WriteAndParent,
-    HierarchicalOneToNStorage,
+    HierarchicalOneToNStorage, SliceStorage,
+    ReadHierarchy, ReadHierarchyAndParent, WriteHierarchyAndParent,
+    ReadOneToN, WriteOneToN, ParOrderedData, ParComponentsRef,
+    Traversal, TraversalOrder, HierarchicalData, HierarchicalDataLocal,
+    OrderedBy, ReadAndParentIn, WriteAndParentIn,
 };
 pub use entity::{Entity, Entities, EntitiesThreadLocal, EntityBuilder, EntitiesCreation};
 pub use component::{Component, ComponentSync, ComponentThreadLocal,
@@ -44,8 +50,20 @@ pub use assoc_vec::AssocVec;
 pub use hashmap::HashMapStorage;
 pub use idtree::{NodeRef, NodeRefMut, NodeId};
 pub use sync::Ptr;
-pub use oneton_forest::OneToNForest;
+pub use oneton_forest::{OneToNForest, HierarchyIter, HierarchyAndParentIter, HierarchyAndParentIterMut};
 pub use creation_proxy::CreationProxy;
+pub use chunked::{ChunkedData, ChunkRef};
+pub use query::{Query, QueryRef};
+pub use relation::{Relation, RelationsFrom, RelationsTo, Hierarchy};
+pub use gpu_storage::{GpuStorage, GpuComponent, Pod};
+pub use error::Error;
+pub use abi::{AbiFingerprint, AbiHeader, ComponentLayout, SystemKind, SystemManifest, layout_hash, version_hash};
+#[cfg(feature="dynamic_systems")]
+pub use rinecs_derive::dynamic_system;
+#[cfg(feature="dynamic_systems")]
+pub use dynamic_system_loader::{DynamicSystemsConfig, LibraryNaming, ReloadMode,
+    ReloadEvent, Diagnostic, DiagnosticLevel,
+    SystemBuilder, CargoBuilder, Prebuilt, BuildError};
 
 
 mod sync;
@@ -65,6 +83,13 @@ mod assoc_vec;
 mod hashmap;
 mod bitmask;
 mod creation_proxy;
+mod chunked;
+mod query;
+mod relation;
+mod gpu_storage;
+mod error;
+#[macro_use]
+mod abi;
 
 #[cfg(feature="dynamic_systems")]
 mod dynamic_system_loader;
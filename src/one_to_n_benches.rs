@@ -107,3 +107,72 @@ fn bench_update(b: &mut Bencher) {
         }
     });
 }
+
+// Storage operations
+const N_ENTITIES: usize = 1000;
+const N_ELEMENTS: usize = 8;
+
+fn build_slices() -> ::World {
+    let mut world = ::World::new();
+    world.register::<Position>();
+    let slice = [Position { x: 0.0, y: 0.0 }; N_ELEMENTS];
+    for _ in 0..N_ENTITIES {
+        world.create_entity()
+            .add_slice(&slice)
+            .build();
+    }
+    world
+}
+
+#[bench]
+fn bench_insert_slices(b: &mut Bencher) {
+    b.iter(build_slices);
+}
+
+#[bench]
+fn bench_append_first(b: &mut Bencher) {
+    let mut world = build_slices();
+    let first = world.entities().iter_for::<::ReadEntities>().next().unwrap();
+    b.iter(|| {
+        world.push_component_to(&first, Position { x: 1.0, y: 1.0 });
+    });
+}
+
+#[bench]
+fn bench_append_all(b: &mut Bencher) {
+    let mut world = build_slices();
+    let entities = world.entities().iter_for::<::ReadEntities>().collect::<Vec<_>>();
+    b.iter(|| {
+        for entity in entities.iter() {
+            world.push_component_to(entity, Position { x: 1.0, y: 1.0 });
+        }
+    });
+}
+
+#[bench]
+fn bench_remove(b: &mut Bencher) {
+    b.iter(|| {
+        let mut world = build_slices();
+        let entities = world.entities().iter_for::<::ReadEntities>().collect::<Vec<_>>();
+        for entity in entities.iter().step_by(2) {
+            world.remove_entity(entity);
+        }
+        world
+    });
+}
+
+#[bench]
+fn bench_iterate_after_remove(b: &mut Bencher) {
+    let mut world = build_slices();
+    let entities = world.entities().iter_for::<::ReadEntities>().collect::<Vec<_>>();
+    for entity in entities.iter().step_by(2) {
+        world.remove_entity(entity);
+    }
+    b.iter(|| {
+        for pos in world.entities().iter_for::<::WriteOneToN<Position>>() {
+            for pos in pos {
+                pos.x += 1.0;
+            }
+        }
+    });
+}
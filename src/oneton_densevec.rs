@@ -2,43 +2,222 @@ use std::sync::{RwLockReadGuard, RwLockWriteGuard};
 use std::marker;
 use std::mem;
 use std::slice;
+use std::ptr;
+use std::cmp;
 
 use storage::{Storage, OneToNStorage, IntoIter, IntoIterMut};
 use ::DenseVec;
 use sync::{ReadGuardRef, WriteGuardRef, ReadGuard, WriteGuard};
 
+// Ratio of unused slots in the buffer over which it's compacted automatically
+const DEFAULT_COMPACTION_THRESHOLD: f32 = 0.5;
+const MIN_GROUP_CAPACITY: usize = 4;
+
 #[derive(Clone, Copy)]
 struct Group{
     first_index: usize,
     len: usize,
+    capacity: usize,
 }
 
+// Every entity owns a chunk of the buffer with spare capacity so appending
+// to it doesn't need to move the rest of the elements. When a chunk is full
+// it's moved to the end of the buffer leaving a gap that is reclaimed when
+// the unused slots go over the compaction threshold or by calling compact()
 pub struct DenseOneToNVec<T>{
-    vec: Vec<T>,
+    // Always has len 0, elements are written and dropped by hand and the
+    // vec is only used to own the allocation
+    buffer: Vec<T>,
+    end: usize,
+    unused: usize,
     index: DenseVec<Group>,
-    ids: Vec<usize>,
+    compaction_threshold: f32,
+}
+
+impl<T> DenseOneToNVec<T>{
+    // Moves every chunk to the start of the buffer removing the gaps left by
+    // removed or moved entities and shrinks the chunks to their length
+    pub fn compact(&mut self){
+        self.relayout(0, false);
+    }
+
+    pub fn set_compaction_threshold(&mut self, threshold: f32){
+        self.compaction_threshold = threshold;
+    }
+
+    pub fn compaction_threshold(&self) -> f32{
+        self.compaction_threshold
+    }
+
+    // Number of slots in the buffer not used by any entity
+    pub fn unused(&self) -> usize{
+        self.unused
+    }
+
+    #[inline]
+    unsafe fn slot(&self, index: usize) -> *mut T{
+        (self.buffer.as_ptr() as *mut T).offset(index as isize)
+    }
+
+    unsafe fn group_slice(&self, group: &Group) -> &[T]{
+        slice::from_raw_parts(self.slot(group.first_index), group.len)
+    }
+
+    unsafe fn group_slice_mut(&mut self, group: &Group) -> &mut [T]{
+        slice::from_raw_parts_mut(self.slot(group.first_index), group.len)
+    }
+
+    unsafe fn set_len(&mut self, guid: usize, len: usize){
+        self.index.get_unchecked_mut(guid).len = len;
+    }
+
+    // Makes sure the chunk for guid, created if it doesn't exist yet, has
+    // space for additional elements and returns it
+    fn reserve_group(&mut self, guid: usize, additional: usize) -> Group{
+        if !self.index.contains_key(guid){
+            let end = self.end;
+            self.index.insert(guid, Group{first_index: end, len: 0, capacity: 0});
+        }
+        let mut group = unsafe{ *self.index.get_unchecked(guid) };
+        let needed = group.len + additional;
+        if needed <= group.capacity {
+            return group;
+        }
+
+        let capacity = cmp::max(needed.next_power_of_two(), MIN_GROUP_CAPACITY);
+        let at_end = group.first_index + group.capacity == self.end;
+        let required = if at_end { capacity - group.capacity } else { capacity };
+        if self.end + required > self.buffer.capacity() {
+            let buffer_capacity = cmp::max(self.buffer.capacity() * 2, self.end - self.unused + capacity);
+            self.relayout(buffer_capacity, true);
+            group = unsafe{ *self.index.get_unchecked(guid) };
+        }
+
+        if group.first_index + group.capacity == self.end {
+            // the last chunk can grow in place
+            group.capacity = capacity;
+            self.end = group.first_index + capacity;
+            unsafe{ *self.index.get_unchecked_mut(guid) = group };
+        }else{
+            let end = self.end;
+            unsafe{ ptr::copy_nonoverlapping(self.slot(group.first_index), self.slot(end), group.len) };
+            self.release(group);
+            group.first_index = end;
+            group.capacity = capacity;
+            self.end = end + capacity;
+            unsafe{ *self.index.get_unchecked_mut(guid) = group };
+            if self.should_compact() {
+                let capacity = self.buffer.capacity();
+                self.relayout(capacity, true);
+                group = unsafe{ *self.index.get_unchecked(guid) };
+            }
+        }
+        group
+    }
+
+    fn release(&mut self, group: Group){
+        if group.first_index + group.capacity == self.end {
+            self.end = group.first_index;
+        }else{
+            self.unused += group.capacity;
+        }
+    }
+
+    fn should_compact(&self) -> bool{
+        self.unused > 0 && self.unused as f32 > self.end as f32 * self.compaction_threshold
+    }
+
+    // Copies every chunk, in index order, to a new buffer of at least
+    // capacity elements
+    fn relayout(&mut self, capacity: usize, keep_spare: bool){
+        let needed = self.index.values()
+            .map(|group| if keep_spare { group.capacity } else { group.len })
+            .sum::<usize>();
+        let mut buffer = Vec::with_capacity(cmp::max(capacity, needed));
+        let src = self.buffer.as_ptr();
+        let dst = buffer.as_mut_ptr();
+        let mut end = 0;
+        for group in self.index.values_mut(){
+            unsafe{
+                ptr::copy_nonoverlapping(
+                    src.offset(group.first_index as isize),
+                    dst.offset(end as isize),
+                    group.len);
+            }
+            group.first_index = end;
+            if !keep_spare {
+                group.capacity = group.len;
+            }
+            end += group.capacity;
+        }
+        // The old buffer has len 0 so this only frees it's memory
+        self.buffer = buffer;
+        self.end = end;
+        self.unused = 0;
+    }
+}
+
+impl<T> Drop for DenseOneToNVec<T>{
+    fn drop(&mut self){
+        let groups = self.index.values().cloned().collect::<Vec<_>>();
+        for group in groups{
+            unsafe{ ptr::drop_in_place(self.group_slice_mut(&group) as *mut [T]) };
+        }
+    }
 }
 
 impl<'a,T: 'a> OneToNStorage<'a,T> for DenseOneToNVec<T>{
     fn insert_slice(&mut self, guid: usize, t: &[T]) where T: Clone{
-        let group = self.index.entry(guid)
-            .or_insert(Group{first_index: self.vec.len(), len: 0});
-        let prev_len = self.vec.len();
-        let rest = self.vec.split_off(group.first_index + group.len);
-        self.vec.extend_from_slice(t);
-        self.vec.extend(rest);
-        self.ids.extend(prev_len..self.vec.len());
-        group.len += t.len();
+        let group = self.reserve_group(guid, t.len());
+        for (i, t) in t.iter().enumerate(){
+            unsafe{ ptr::write(self.slot(group.first_index + group.len + i), t.clone()) };
+        }
+        unsafe{ self.set_len(guid, group.len + t.len()) };
     }
 
     unsafe fn get_slice(&self, guid: usize) -> &[T]{
-        let slice = self.index.get_unchecked(guid);
-        &self.vec[slice.first_index..slice.first_index + slice.len]
+        let group = *self.index.get_unchecked(guid);
+        self.group_slice(&group)
     }
 
     unsafe fn get_slice_mut(&mut self, guid: usize) -> &mut [T]{
-        let slice = self.index.get_unchecked(guid);
-        &mut self.vec[slice.first_index..slice.first_index + slice.len]
+        let group = *self.index.get_unchecked(guid);
+        self.group_slice_mut(&group)
+    }
+
+    fn push(&mut self, guid: usize, t: T){
+        let group = self.reserve_group(guid, 1);
+        unsafe{
+            ptr::write(self.slot(group.first_index + group.len), t);
+            self.set_len(guid, group.len + 1);
+        }
+    }
+
+    unsafe fn remove_at(&mut self, guid: usize, index: usize) -> T{
+        let group = *self.index.get_unchecked(guid);
+        assert!(index < group.len, "Trying to remove index {} in slice of len {}", index, group.len);
+        let slot = self.slot(group.first_index + index);
+        let t = ptr::read(slot);
+        ptr::copy(slot.offset(1), slot, group.len - index - 1);
+        self.set_len(guid, group.len - 1);
+        t
+    }
+
+    unsafe fn truncate(&mut self, guid: usize, len: usize){
+        let group = *self.index.get_unchecked(guid);
+        if len < group.len {
+            // update the len first in case any drop panics
+            self.set_len(guid, len);
+            let removed = slice::from_raw_parts_mut(self.slot(group.first_index + len), group.len - len);
+            ptr::drop_in_place(removed as *mut [T]);
+        }
+    }
+
+    fn replace_slice(&mut self, guid: usize, t: &[T]) where T: Clone{
+        if self.index.contains_key(guid){
+            unsafe{ self.truncate(guid, 0) };
+        }
+        self.insert_slice(guid, t);
     }
 }
 
@@ -50,58 +229,45 @@ impl<'a, T: 'a> Storage<'a, T> for DenseOneToNVec<T>{
 
     fn new() -> DenseOneToNVec<T>{
         DenseOneToNVec{
-            vec: vec![],
+            buffer: vec![],
+            end: 0,
+            unused: 0,
             index: DenseVec::new(),
-            ids: vec![],
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
         }
     }
 
     fn with_capacity(capacity: usize) -> DenseOneToNVec<T>{
         DenseOneToNVec{
-            vec: Vec::with_capacity(capacity),
+            buffer: Vec::with_capacity(capacity),
+            end: 0,
+            unused: 0,
             index: DenseVec::new(),
-            ids: vec![],
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
         }
     }
 
     fn insert(&mut self, guid: usize, t: T){
-        let group = self.index.entry(guid)
-            .or_insert(Group{first_index: self.vec.len(), len: 0});
-        self.vec.insert(group.first_index + group.len, t);
-        group.len += 1;
-        self.ids.push(self.vec.len() - 1);
+        self.push(guid, t)
     }
 
     fn remove(&mut self, guid: usize){
-        let group = unsafe{ *self.index.get_unchecked(guid) };
-        self.vec.drain(group.first_index .. group.first_index + group.len).count();
-        for i in group.first_index .. group.first_index + group.len{
-            if let Some(i) = self.ids.iter().position(|id| *id == i){
-                self.ids.remove(i);
-            }
-        }
-        self.index.remove(guid);
-        for other_group in self.index.values_mut() {
-            if other_group.first_index > group.first_index {
-                other_group.first_index -= group.len;
+        if let Some(group) = self.index.remove(guid){
+            unsafe{ ptr::drop_in_place(self.group_slice_mut(&group) as *mut [T]) };
+            self.release(group);
+            if self.should_compact() {
+                let capacity = self.buffer.capacity();
+                self.relayout(capacity, true);
             }
         }
     }
 
     unsafe fn get(&'a self, guid: usize) -> &'a [T]{
-        let slice = self.index.get_unchecked(guid);
-        &self.vec[slice.first_index..slice.first_index + slice.len]
-
-        // let ptr = self.vec.as_ptr().offset(slice.first_index as isize);
-        // slice::from_raw_parts(ptr, slice.len)
+        self.get_slice(guid)
     }
 
     unsafe fn get_mut(&'a mut self, guid: usize) -> &'a mut [T]{
-        let slice = self.index.get_unchecked(guid);
-        &mut self.vec[slice.first_index..slice.first_index + slice.len]
-
-        // let ptr = self.vec.as_mut_ptr().offset(slice.first_index as isize);
-        // slice::from_raw_parts_mut(ptr, slice.len)
+        self.get_slice_mut(guid)
     }
 
     fn contains(&self, guid: usize) -> bool{
@@ -119,7 +285,7 @@ impl<'a, T: 'a> Iterator for OneToNDenseIter<'a, T>{
     type Item = &'a [T];
     fn next(&mut self) -> Option<&'a [T]>{
         self.it.next().map(|group| {
-            let v = &self.storage.vec[group.first_index..group.first_index + group.len];
+            let v = unsafe{ self.storage.group_slice(group) };
             unsafe{mem::transmute::<&[T], &[T]>(v)}
         })
     }
@@ -156,7 +322,7 @@ impl<'a, T: 'a> Iterator for OneToNDenseIterMut<'a, T>{
     type Item = &'a mut [T];
     fn next(&mut self) -> Option<&'a mut [T]>{
         self.it.next().map(|group| {
-            let v = &mut self.storage.vec[group.first_index..group.first_index + group.len];
+            let v = unsafe{ self.storage.group_slice_mut(group) };
             unsafe{mem::transmute::<&mut [T], &mut [T]>(v)}
         })
     }
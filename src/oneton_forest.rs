@@ -5,6 +5,8 @@ use sync::{ReadGuardRef, ReadGuard, WriteGuardRef, WriteGuard};
 
 use std::slice;
 use std::mem;
+use std::vec;
+use std::marker;
 use std::sync::{RwLockReadGuard, RwLockWriteGuard};
 
 pub struct OneToNForest<T>{
@@ -38,10 +40,12 @@ impl<'a, T: 'a> Storage<'a, T> for OneToNForest<T>{
     }
 
     fn remove(&mut self, guid: usize){
-        for id in unsafe{ self.entities_roots.get_unchecked(guid).iter() }{
-            self.arena.remove_tree(*id);
+        if let Some(roots) = self.entities_roots.remove(guid){
+            for id in roots{
+                self.arena.remove_tree(id);
+            }
         }
-        self.entities_roots.remove(guid);
+        self.reverse_index.remove(guid);
     }
 
     unsafe fn get(&'a self, guid: usize) -> RootsIter<'a,T>{
@@ -81,6 +85,114 @@ impl<'a, T: 'a> HierarchicalOneToNStorage<'a,T> for OneToNForest<T>{
 }
 
 
+impl<T> OneToNForest<T>{
+    unsafe fn hierarchy_ids(&self, guid: usize) -> HierarchyIds<T>{
+        HierarchyIds{
+            roots: self.entities_roots.get_unchecked(guid).iter(),
+            descendants: None,
+            arena: &self.arena,
+        }
+    }
+
+    pub(crate) unsafe fn hierarchy(&self, guid: usize) -> HierarchyIter<T>{
+        HierarchyIter{
+            ids: self.hierarchy_ids(guid),
+            arena: &self.arena,
+        }
+    }
+
+    pub(crate) unsafe fn hierarchy_and_parent(&self, guid: usize) -> HierarchyAndParentIter<T>{
+        HierarchyAndParentIter{
+            ids: self.hierarchy_ids(guid),
+            arena: &self.arena,
+        }
+    }
+
+    pub(crate) unsafe fn hierarchy_and_parent_mut(&mut self, guid: usize) -> HierarchyAndParentIterMut<T>{
+        let ids = self.hierarchy_ids(guid).collect::<Vec<_>>();
+        HierarchyAndParentIterMut{
+            ids: ids.into_iter(),
+            arena: &mut self.arena,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+// Depth first traversal of all the trees of an entity, parents are always
+// visited before their children
+struct HierarchyIds<'a, T: 'a>{
+    roots: slice::Iter<'a, idtree::NodeId>,
+    descendants: Option<idtree::Descendants<'a, T>>,
+    arena: &'a idtree::Arena<T>,
+}
+
+impl<'a, T: 'a> Iterator for HierarchyIds<'a, T>{
+    type Item = idtree::NodeId;
+
+    fn next(&mut self) -> Option<idtree::NodeId>{
+        loop{
+            if let Some(ref mut descendants) = self.descendants{
+                if let Some(next) = descendants.next(){
+                    return Some(next);
+                }
+            }
+            match self.roots.next(){
+                Some(root) => self.descendants = Some(root.descendants(self.arena)),
+                None => return None,
+            }
+        }
+    }
+}
+
+pub struct HierarchyIter<'a, T: 'a>{
+    ids: HierarchyIds<'a, T>,
+    arena: &'a idtree::Arena<T>,
+}
+
+impl<'a, T: 'a> Iterator for HierarchyIter<'a, T>{
+    type Item = idtree::NodeRef<'a,T>;
+
+    fn next(&mut self) -> Option<idtree::NodeRef<'a, T>>{
+        let arena = self.arena;
+        self.ids.next().map(|id| arena.get(id))
+    }
+}
+
+pub struct HierarchyAndParentIter<'a, T: 'a>{
+    ids: HierarchyIds<'a, T>,
+    arena: &'a idtree::Arena<T>,
+}
+
+impl<'a, T: 'a> Iterator for HierarchyAndParentIter<'a, T>{
+    type Item = (&'a T, Option<&'a T>);
+
+    fn next(&mut self) -> Option<(&'a T, Option<&'a T>)>{
+        let arena = self.arena;
+        self.ids.next().map(|id| {
+            let parent = arena[id].parent().map(|parent| &arena[parent].data);
+            (&arena[id].data, parent)
+        })
+    }
+}
+
+pub struct HierarchyAndParentIterMut<'a, T: 'a>{
+    ids: vec::IntoIter<idtree::NodeId>,
+    arena: *mut idtree::Arena<T>,
+    _marker: marker::PhantomData<&'a mut T>,
+}
+
+impl<'a, T: 'a> Iterator for HierarchyAndParentIterMut<'a, T>{
+    type Item = (&'a mut T, Option<&'a T>);
+
+    fn next(&mut self) -> Option<(&'a mut T, Option<&'a T>)>{
+        let arena = self.arena;
+        self.ids.next().map(|id| unsafe{
+            let parent = (*arena)[id].parent().map(|parent| &(*arena)[parent].data);
+            (&mut (*arena)[id].data, parent)
+        })
+    }
+}
+
 pub struct RootsIter<'a, T: 'a>{
     iter: slice::Iter<'a, idtree::NodeId>,
     arena: &'a idtree::Arena<T>,
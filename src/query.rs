@@ -0,0 +1,149 @@
+use std::marker;
+use std::mem;
+
+use boolinator::Boolinator;
+use ::Entity;
+use ::Bitmask;
+use ::MaskType;
+use ::StorageRef;
+use ::ReadOnlyStorage;
+use ::{Component, Storage, OneToNComponent};
+use ::{Read, Write, Not, ReadOption, ReadNot, ReadEntities, ReadOneToN, WriteOneToN};
+
+// Random access to the components of a query, the storages are locked once
+// when the query is created and released when it's dropped
+pub struct Query<'a, R, S, D>{
+    entities: &'a [(Entity, MaskType)],
+    mask: Bitmask,
+    storage: S,
+    _marker: marker::PhantomData<(R, fn() -> D)>,
+}
+
+// Components of a query borrowed for 'q instead of for as long as the
+// storages are locked, so mutable components returned by get_many_mut can't
+// outlive the borrow of the query. The components have to be the same type
+// the storage returns except for the lifetime
+pub unsafe trait QueryRef<'q>{
+    type ComponentsRef;
+}
+
+unsafe impl<'a, 'q, T: 'a + Component> QueryRef<'q> for Read<'a,T>{
+    type ComponentsRef = <<T as Component>::Storage as Storage<'q, T>>::Get;
+}
+
+unsafe impl<'a, 'q, T: 'a + Component> QueryRef<'q> for Write<'a,T>{
+    type ComponentsRef = <<T as Component>::Storage as Storage<'q, T>>::GetMut;
+}
+
+unsafe impl<'a, 'q, T: 'a + Component> QueryRef<'q> for ReadOption<'a,T>{
+    type ComponentsRef = Option<<<T as Component>::Storage as Storage<'q, T>>::Get>;
+}
+
+unsafe impl<'a, 'q, T: 'a + Component> QueryRef<'q> for Not<'a,T>{
+    type ComponentsRef = ();
+}
+
+unsafe impl<'a, 'q, T: 'a + Component, N: 'a + Component> QueryRef<'q> for ReadNot<'a,T,N>{
+    type ComponentsRef = <<T as Component>::Storage as Storage<'q, T>>::Get;
+}
+
+unsafe impl<'q> QueryRef<'q> for ReadEntities{
+    type ComponentsRef = Entity;
+}
+
+unsafe impl<'a, 'q, T: 'a + OneToNComponent> QueryRef<'q> for ReadOneToN<'a,T>{
+    type ComponentsRef = &'q [T];
+}
+
+unsafe impl<'a, 'q, T: 'a + OneToNComponent> QueryRef<'q> for WriteOneToN<'a,T>{
+    type ComponentsRef = &'q mut [T];
+}
+
+macro_rules! impl_combined_query_ref {
+    ($($q: ident),*) => (
+        unsafe impl<'q, $($q: QueryRef<'q>),*> QueryRef<'q> for ($($q),*){
+            type ComponentsRef = ($(<$q as QueryRef<'q>>::ComponentsRef),*);
+        }
+    )
+}
+
+impl_combined_query_ref!(Q1, Q2);
+impl_combined_query_ref!(Q1, Q2, Q3);
+impl_combined_query_ref!(Q1, Q2, Q3, Q4);
+impl_combined_query_ref!(Q1, Q2, Q3, Q4, Q5);
+impl_combined_query_ref!(Q1, Q2, Q3, Q4, Q5, Q6);
+impl_combined_query_ref!(Q1, Q2, Q3, Q4, Q5, Q6, Q7);
+impl_combined_query_ref!(Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8);
+
+impl<'a, R, S: StorageRef<'a, R>, D> Query<'a, R, S, D>{
+    pub(crate) fn new(entities: &'a [(Entity, MaskType)], mask: Bitmask, storage: S) -> Query<'a, R, S, D>{
+        Query{
+            entities,
+            mask,
+            storage,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    pub fn contains(&self, entity: &Entity) -> bool{
+        self.entities.get(entity.guid())
+            .map(|&(_, ref entity_mask)| self.mask.check(entity_mask.clone()))
+            .unwrap_or(false)
+    }
+
+    // Only read only queries can get components through a shared borrow,
+    // use get_many_mut for queries that write
+    pub fn get(&self, entity: &Entity) -> Option<R>
+        where S: ReadOnlyStorage
+    {
+        self.contains(entity).as_some_from(|| self.storage.get(entity.guid()))
+    }
+
+    /// Returns None if any of the entities doesn't match the query or
+    /// the same entity is requested more than once. The components borrow
+    /// the query so they have to be dropped before calling it again:
+    ///
+    /// ```compile_fail
+    /// extern crate rinecs;
+    /// use rinecs::{World, Component, DenseVec, Write};
+    ///
+    /// struct Pos(f32);
+    ///
+    /// impl Component for Pos{
+    ///     type Storage = DenseVec<Pos>;
+    ///     fn type_name() -> String{
+    ///         "Pos".to_owned()
+    ///     }
+    /// }
+    ///
+    /// fn main(){
+    ///     let mut world = World::new();
+    ///     world.register::<Pos>();
+    ///     let e = world.create_entity().add(Pos(0.)).build();
+    ///     let entities = world.entities();
+    ///     let mut query = entities.query_for::<Write<Pos>>();
+    ///     let first = query.get_many_mut([e]).unwrap();
+    ///     let second = query.get_many_mut([e]).unwrap();
+    ///     first[0].0 = second[0].0;
+    /// }
+    /// ```
+    pub fn get_many_mut<'q, E: AsRef<[Entity]>>(&'q mut self, entities: E) -> Option<Vec<<D as QueryRef<'q>>::ComponentsRef>>
+        where D: QueryRef<'q>
+    {
+        let entities = entities.as_ref();
+        let valid = entities.iter().enumerate().all(|(i, entity)|
+            self.contains(entity) && !entities[..i].contains(entity)
+        );
+        valid.as_some_from(|| entities.iter()
+            .map(|entity| unsafe{
+                // Same type with the lifetime shortened to the borrow of
+                // the query
+                let components = self.storage.get(entity.guid());
+                debug_assert_eq!(mem::size_of::<R>(), mem::size_of::<<D as QueryRef<'q>>::ComponentsRef>());
+                let components_ref = mem::transmute_copy(&components);
+                mem::forget(components);
+                components_ref
+            })
+            .collect())
+    }
+}
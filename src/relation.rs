@@ -0,0 +1,155 @@
+use std::any::TypeId;
+use std::sync::RwLockReadGuard;
+use std::slice;
+use std::mem;
+
+use densevec::DenseVec;
+use forest::Forest;
+use ::Entity;
+
+pub trait Relation: 'static + Sized {
+    fn type_name() -> String;
+
+    #[inline]
+    fn id() -> TypeId {
+        TypeId::of::<Self>()
+    }
+}
+
+// Marker for a parent relation independent of any component, entities can
+// be part of several hierarchies and any component can be iterated in the
+// order of any of them
+pub trait Hierarchy: 'static + Sized {
+    fn type_name() -> String;
+
+    #[inline]
+    fn id() -> TypeId {
+        TypeId::of::<Self>()
+    }
+}
+
+pub type ParentStorage = Forest<()>;
+
+// Relations are stored as pairs indexed by source with a reverse index by
+// target so both directions can be queried in O(matches)
+pub struct RelationStorage<R>{
+    targets: DenseVec<Vec<(Entity, R)>>,
+    sources: DenseVec<Vec<Entity>>,
+}
+
+impl<R> RelationStorage<R>{
+    pub fn new() -> RelationStorage<R>{
+        RelationStorage{
+            targets: DenseVec::new(),
+            sources: DenseVec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, source: Entity, target: Entity, relation: R){
+        let targets = self.targets.entry(source.guid()).or_insert_with(|| vec![]);
+        if let Some(pos) = targets.iter().position(|&(t, _)| t == target){
+            targets[pos].1 = relation;
+        }else{
+            targets.push((target, relation));
+            self.sources.entry(target.guid()).or_insert_with(|| vec![]).push(source);
+        }
+    }
+
+    pub fn remove(&mut self, source: Entity, target: Entity) -> Option<R>{
+        let relation = remove_where(&mut self.targets, source.guid(), |&(t, _)| t == target)
+            .map(|(_, relation)| relation);
+        if relation.is_some(){
+            remove_where(&mut self.sources, target.guid(), |s| *s == source);
+        }
+        relation
+    }
+
+    pub fn remove_entity(&mut self, guid: usize){
+        if let Some(targets) = self.targets.remove(guid){
+            for (target, _) in targets{
+                remove_where(&mut self.sources, target.guid(), |s| s.guid() == guid);
+            }
+        }
+        if let Some(sources) = self.sources.remove(guid){
+            for source in sources{
+                remove_where(&mut self.targets, source.guid(), |&(t, _)| t.guid() == guid);
+            }
+        }
+    }
+
+    pub fn targets_of(&self, source: usize) -> &[(Entity, R)]{
+        if self.targets.contains_key(source){
+            unsafe{ self.targets.get_unchecked(source) }
+        }else{
+            &[]
+        }
+    }
+
+    pub fn sources_of(&self, target: usize) -> &[Entity]{
+        if self.sources.contains_key(target){
+            unsafe{ self.sources.get_unchecked(target) }
+        }else{
+            &[]
+        }
+    }
+}
+
+fn remove_where<T, F: Fn(&T) -> bool>(index: &mut DenseVec<Vec<T>>, guid: usize, f: F) -> Option<T>{
+    let (removed, empty) = match index.get_mut(guid){
+        Some(values) => {
+            let removed = values.iter().position(f).map(|pos| values.swap_remove(pos));
+            (removed, values.is_empty())
+        }
+        None => (None, false)
+    };
+    if empty {
+        index.remove(guid);
+    }
+    removed
+}
+
+pub struct RelationsFrom<'a, R: 'a>{
+    _guard: RwLockReadGuard<'a, RelationStorage<R>>,
+    iter: slice::Iter<'a, (Entity, R)>,
+}
+
+impl<'a, R: 'a> RelationsFrom<'a, R>{
+    pub(crate) fn new(guard: RwLockReadGuard<'a, RelationStorage<R>>, source: &Entity) -> RelationsFrom<'a, R>{
+        let targets = unsafe{ mem::transmute::<&[(Entity, R)], &[(Entity, R)]>(guard.targets_of(source.guid())) };
+        RelationsFrom{
+            _guard: guard,
+            iter: targets.iter(),
+        }
+    }
+}
+
+impl<'a, R: 'a> Iterator for RelationsFrom<'a, R>{
+    type Item = (Entity, &'a R);
+    #[inline]
+    fn next(&mut self) -> Option<(Entity, &'a R)>{
+        self.iter.next().map(|&(target, ref relation)| (target, relation))
+    }
+}
+
+pub struct RelationsTo<'a, R: 'a>{
+    _guard: RwLockReadGuard<'a, RelationStorage<R>>,
+    iter: slice::Iter<'a, Entity>,
+}
+
+impl<'a, R: 'a> RelationsTo<'a, R>{
+    pub(crate) fn new(guard: RwLockReadGuard<'a, RelationStorage<R>>, target: &Entity) -> RelationsTo<'a, R>{
+        let sources = unsafe{ mem::transmute::<&[Entity], &[Entity]>(guard.sources_of(target.guid())) };
+        RelationsTo{
+            _guard: guard,
+            iter: sources.iter(),
+        }
+    }
+}
+
+impl<'a, R: 'a> Iterator for RelationsTo<'a, R>{
+    type Item = Entity;
+    #[inline]
+    fn next(&mut self) -> Option<Entity>{
+        self.iter.next().map(|e| *e)
+    }
+}
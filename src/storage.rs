@@ -4,8 +4,11 @@ use std::cell::UnsafeCell;
 use std::mem;
 use std::slice;
 use std::iter;
+use std::vec;
+use std::ops::Range;
 
 use sync::{ReadGuardRef, WriteGuardRef};
+use relation::{Hierarchy, ParentStorage};
 use ::Component;
 use ::ComponentSync;
 use ::ComponentThreadLocal;
@@ -13,6 +16,7 @@ use ::World;
 use ::IndexGuard;
 use ::Entity;
 use ::Bitmask;
+use ::Error;
 
 pub trait Storage<'a, T>{
     type Get;
@@ -71,6 +75,14 @@ pub trait StorageRef<'a, T>{
     fn contains(&self, guid: usize) -> bool;
 }
 
+// Storages that only hand out shared references so the same component can
+// be returned more than once through a shared borrow
+pub unsafe trait ReadOnlyStorage{}
+
+unsafe impl<'a, S: Storage<'a,T> + 'a, T: 'a + ComponentSync> ReadOnlyStorage for StorageRead<'a, S, T>{}
+unsafe impl ReadOnlyStorage for (){}
+unsafe impl<'a> ReadOnlyStorage for &'a [(Entity, ::MaskType)]{}
+
 impl<'a, S: Storage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a, <S as Storage<'a,T>>::Get> for StorageRead<'a, S, T>{
     fn get(&self, guid: usize) -> <S as Storage<'a,T>>::Get{
         // unsafe{ mem::transmute::<&T, &T>(self.storage.get(guid)) }
@@ -104,6 +116,9 @@ pub trait UnorderedData<'a>{
     type ComponentsRef;
     type Storage;
     fn components_mask(world: &'a World) -> Bitmask;
+    // Same as components_mask but fails instead of panicking if any of
+    // the components isn't registered
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>;
     fn into_iter(world: &'a ::World) -> Self::Iter;
     fn storage(world: &'a ::World) -> Self::Storage;
 }
@@ -119,6 +134,10 @@ impl<'a, T: 'a + ComponentSync> UnorderedData<'a> for Read<'a,T>
         Bitmask::has(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         world.storage::<T>().unwrap().into_iter()
     }
@@ -142,6 +161,10 @@ impl<'a, T: 'a + ComponentSync> UnorderedData<'a> for Write<'a,T>
         Bitmask::has(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         world.storage_mut::<T>().unwrap().into_iter_mut()
     }
@@ -163,6 +186,10 @@ impl<'a, T: 'a + ComponentSync> UnorderedData<'a> for Not<'a,T> {
         Bitmask::not(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::not)
+    }
+
     fn into_iter(_: &'a ::World) -> Self::Iter{
         iter::repeat(())
     }
@@ -216,6 +243,10 @@ impl<'a, T: 'a + ComponentSync, Not: Component> UnorderedData<'a> for ReadNot<'a
         Bitmask::has_not(world.components_mask::<T>(), world.components_mask::<Not>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        Ok(Bitmask::has_not(world.try_components_mask::<T>()?, world.try_components_mask::<Not>()?))
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter {
         let ids = world.entities_for_mask(<Self as UnorderedData>::components_mask(world));
         ReadNotIter{
@@ -272,6 +303,8 @@ pub struct StorageOption<'a, T, S>
     storage: ::ReadGuardRef<'a, S>,
 }
 
+unsafe impl<'a, T, S: 'a> ::ReadOnlyStorage for StorageOption<'a, T, S>{}
+
 impl<'a, T, S> ::StorageRef<'a, Option<<S as ::Storage<'a,T>>::Get>> for StorageOption<'a, T, S>
     where S: ::Storage<'a, T> + 'a
 {
@@ -297,6 +330,10 @@ impl<'a, T: 'a + ::ComponentSync> ::UnorderedData<'a> for ::ReadOption<'a,T> {
         ::Bitmask::all()
     }
 
+    fn try_components_mask(world: &'a ::World) -> Result<::Bitmask, ::Error>{
+        world.try_components_mask::<T>().map(|_| ::Bitmask::all())
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter {
         let storage = <Self as ::UnorderedData>::storage(world);
         IterOption {
@@ -324,6 +361,10 @@ impl<'a, T: 'a + ::Component> ::UnorderedDataLocal<'a> for ::ReadOption<'a,T> {
         ::Bitmask::all()
     }
 
+    fn try_components_mask(world: &'a ::World) -> Result<::Bitmask, ::Error>{
+        world.try_components_mask::<T>().map(|_| ::Bitmask::all())
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter {
         let storage = <Self as ::UnorderedDataLocal>::storage(world);
         IterOption {
@@ -390,6 +431,8 @@ macro_rules! impl_or_iter {
         )*
     }
 
+    unsafe impl<'a, $($t, $s: 'a),*> ::ReadOnlyStorage for $storage_or<'a, $($t, $s),*>{}
+
     impl<'a, $($t, $s),*> ::StorageRef<'a, ($(Option<<$s as ::Storage<'a,$t>>::Get>),*) > for $storage_or<'a, $($t, $s),*>
         where $($s: ::Storage<'a, $t> + 'a),*
     {
@@ -418,6 +461,10 @@ macro_rules! impl_or_iter {
             ::Bitmask::or($(world.components_mask::<$t>()) | *)
         }
 
+        fn try_components_mask(world: &'a ::World) -> Result<::Bitmask, ::Error>{
+            Ok(::Bitmask::or($(world.try_components_mask::<$t>()?) | *))
+        }
+
         fn into_iter(world: &'a ::World) -> Self::Iter {
             let ids = world.entities_for_mask(<Self as ::UnorderedData>::components_mask(world));
             let storage = <Self as ::UnorderedData>::storage(world);
@@ -449,6 +496,10 @@ macro_rules! impl_or_iter {
             ::Bitmask::or($( world.components_mask::<$t>() ) | *)
         }
 
+        fn try_components_mask(world: &'a ::World) -> Result<::Bitmask, ::Error>{
+            Ok(::Bitmask::or($(world.try_components_mask::<$t>()?) | *))
+        }
+
         fn into_iter(world: &'a ::World) -> Self::Iter {
             let ids = world.entities_for_mask(<Self as ::UnorderedDataLocal>::components_mask(world));
             let storage = <Self as ::UnorderedDataLocal>::storage(world);
@@ -529,6 +580,10 @@ impl<'a> UnorderedData<'a> for ReadEntities {
         Bitmask::all()
     }
 
+    fn try_components_mask(_world: &'a World) -> Result<Bitmask, Error>{
+        Ok(Bitmask::all())
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         EntitiesIter(world.entities_ref().iter())
     }
@@ -549,6 +604,8 @@ pub struct StorageWriteLocal<'a, S: Storage<'a,T> + 'a, T: 'a + ComponentThreadL
     _marker: marker::PhantomData<&'a T>,
 }
 
+unsafe impl<'a, S: Storage<'a,T> + 'a, T: 'a + ComponentThreadLocal> ReadOnlyStorage for StorageReadLocal<'a, S, T>{}
+
 impl<'a, S: Storage<'a,T> + 'a, T: 'a + ComponentThreadLocal> StorageRef<'a, <S as Storage<'a,T>>::Get> for StorageReadLocal<'a, S, T>{
     fn get(&self, guid: usize) -> <S as Storage<'a,T>>::Get{
         //unsafe{ mem::transmute::<&T, &T>(self.storage.get(guid)) }
@@ -579,6 +636,9 @@ pub trait UnorderedDataLocal<'a>{
     type ComponentsRef;
     type Storage;
     fn components_mask(world: &'a World) -> Bitmask;
+    // Same as components_mask but fails instead of panicking if any of
+    // the components isn't registered
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>;
     fn into_iter(world: &'a ::World) -> Self::Iter;
     fn storage(world: &'a ::World) -> Self::Storage;
 }
@@ -594,6 +654,10 @@ impl<'a, T: 'a + ComponentThreadLocal> UnorderedDataLocal<'a> for Read<'a,T>
         Bitmask::has(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         world.storage_thread_local::<T>().unwrap().into_iter()
     }
@@ -617,6 +681,10 @@ impl<'a, T: 'a + ComponentThreadLocal> UnorderedDataLocal<'a> for Write<'a,T>
         Bitmask::has(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         world.storage_thread_local_mut::<T>().unwrap().into_iter_mut()
     }
@@ -639,6 +707,10 @@ impl<'a> UnorderedDataLocal<'a> for ReadEntities {
         Bitmask::all()
     }
 
+    fn try_components_mask(_world: &'a World) -> Result<Bitmask, Error>{
+        Ok(Bitmask::all())
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter{
         EntitiesIter(world.entities_ref().iter())
     }
@@ -658,6 +730,10 @@ impl<'a, T: 'a + ComponentSync> UnorderedDataLocal<'a> for Not<'a,T> {
         Bitmask::not(world.components_mask::<T>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::not)
+    }
+
     fn into_iter(_: &'a ::World) -> Self::Iter{
         iter::repeat(())
     }
@@ -677,6 +753,10 @@ impl<'a, T: 'a + ComponentSync, Not: Component> UnorderedDataLocal<'a> for ReadN
         Bitmask::has_not(world.components_mask::<T>(), world.components_mask::<Not>())
     }
 
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        Ok(Bitmask::has_not(world.try_components_mask::<T>()?, world.try_components_mask::<Not>()?))
+    }
+
     fn into_iter(world: &'a ::World) -> Self::Iter {
         let ids = world.entities_for_mask(<Self as UnorderedDataLocal>::components_mask(world));
         ReadNotIter{
@@ -752,6 +832,8 @@ macro_rules! impl_combined_unordered_iter {
             )*
         }
 
+        unsafe impl<$($s: ::ReadOnlyStorage),*> ::ReadOnlyStorage for $storage_ref<$($s),*>{}
+
         impl<'a, $($t, $s: ::StorageRef<'a, $t>,)*> ::StorageRef<'a, ($($t),*)> for $storage_ref<$($s),*>{
             fn get(&self, guid: usize) -> ($($t),*){
                 ($( self.$s.get(guid) ),*)
@@ -788,6 +870,10 @@ macro_rules! impl_combined_unordered_iter {
                 $($u::components_mask(world)) | *
             }
 
+            fn try_components_mask(world: &'a ::World) -> Result<::bitmask::Bitmask, ::Error>{
+                Ok($($u::try_components_mask(world)?) | *)
+            }
+
             fn into_iter(world: &'a ::World) -> Self::Iter{
                 let ids = world.entities_for_mask(Self::components_mask(world));
                 $iter{
@@ -837,6 +923,10 @@ macro_rules! impl_combined_unordered_iter {
                 $($u::components_mask(world)) | *
             }
 
+            fn try_components_mask(world: &'a ::World) -> Result<::bitmask::Bitmask, ::Error>{
+                Ok($($u::try_components_mask(world)?) | *)
+            }
+
             fn into_iter(world: &'a ::World) -> Self::Iter{
                 let ids = world.entities_for_mask(Self::components_mask(world));
                 $iter{
@@ -887,6 +977,8 @@ mod combined_unordered{
 
 use idtree;
 use forest;
+use oneton_forest::{OneToNForest, HierarchyIter, HierarchyAndParentIter, HierarchyAndParentIterMut};
+use component::{HierarchicalOneToNComponent, HierarchicalOneToNComponentSync};
 
 
 pub trait IntoOrderedIter{
@@ -927,7 +1019,75 @@ pub trait HierarchicalStorage<'a,T>: Storage<'a,T>{
     unsafe fn insert_child(&mut self, parent_guid: usize, guid: usize, value: T);
     unsafe fn get_node(&self, guid: usize) -> idtree::NodeRef<T>;
     unsafe fn get_node_mut(&mut self, guid: usize) -> idtree::NodeRefMut<T>;
+    unsafe fn set_parent(&mut self, guid: usize, parent_guid: usize);
+    unsafe fn make_root(&mut self, guid: usize);
+    unsafe fn detach(&mut self, guid: usize);
+    unsafe fn remove_node(&mut self, guid: usize, mode: ChildrenMode);
+    unsafe fn parent_id(&self, guid: usize) -> Option<usize>;
+    unsafe fn children_ids(&self, guid: usize) -> Vec<usize>;
+    // From the parent of the entity to it's root
+    unsafe fn ancestors_ids(&self, guid: usize) -> Vec<usize>;
+    // The entity and all it's descendants in hierarchical order
+    unsafe fn descendants_ids(&self, guid: usize) -> Vec<usize>;
     fn ordered_ids(&self) -> &[usize];
+    // Depth of each of the ordered ids, roots have depth 0
+    fn ordered_depths(&self) -> &[usize];
+    // Range of the ordered ids with the entity and all it's descendants
+    unsafe fn ordered_subtree(&self, guid: usize) -> Range<usize>;
+}
+
+// What happens to the children of a hierarchical component when it's removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildrenMode{
+    ToGrandparent,
+    ToRoots,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraversalOrder{
+    DepthFirst,
+    BreadthFirst,
+}
+
+// How traverse_for visits a hierarchy, by default depth first over all the
+// trees. When starting from an entity depths are relative to it
+#[derive(Clone, Copy, Debug)]
+pub struct Traversal{
+    pub order: TraversalOrder,
+    pub max_depth: Option<usize>,
+    pub root: Option<Entity>,
+}
+
+impl Traversal{
+    pub fn depth_first() -> Traversal{
+        Traversal{
+            order: TraversalOrder::DepthFirst,
+            max_depth: None,
+            root: None,
+        }
+    }
+
+    pub fn breadth_first() -> Traversal{
+        Traversal{
+            order: TraversalOrder::BreadthFirst,
+            max_depth: None,
+            root: None,
+        }
+    }
+
+    pub fn max_depth(self, max_depth: usize) -> Traversal{
+        Traversal{
+            max_depth: Some(max_depth),
+            .. self
+        }
+    }
+
+    pub fn subtree(self, root: &Entity) -> Traversal{
+        Traversal{
+            root: Some(*root),
+            .. self
+        }
+    }
 }
 
 pub struct ReadHierarchical<'a, T: 'a + Component>{
@@ -1053,7 +1213,7 @@ pub struct HierarchicalStorageWriteLocal<'a, S: HierarchicalStorage<'a,T> + 'a,
 }
 
 
-impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a, idtree::NodeRef<'a, T>> for HierarchicalStorageReadLocal<'a, S, T>{
+impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal> StorageRef<'a, idtree::NodeRef<'a, T>> for HierarchicalStorageReadLocal<'a, S, T>{
     fn get(&self, guid: usize) -> idtree::NodeRef<'a, T>{
         unsafe{ mem::transmute::<idtree::NodeRef<T>, idtree::NodeRef<T>>(self.storage.get_node(guid)) }
     }
@@ -1063,7 +1223,7 @@ impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a
     }
 }
 
-impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a, idtree::NodeRefMut<'a, T>> for HierarchicalStorageWriteLocal<'a, S, T>{
+impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal> StorageRef<'a, idtree::NodeRefMut<'a, T>> for HierarchicalStorageWriteLocal<'a, S, T>{
     fn get(&self, guid: usize) -> idtree::NodeRefMut<'a, T>{
         unsafe{ mem::transmute::<idtree::NodeRefMut<T>, idtree::NodeRefMut<T>>((*self.storage.get()).get_node_mut(guid)) }
     }
@@ -1086,7 +1246,7 @@ pub trait OrderedDataLocal<'a>{
 }
 
 
-impl<'a, T: 'a + ComponentSync> OrderedDataLocal<'a> for ReadHierarchical<'a,T>
+impl<'a, T: 'a + Component> OrderedDataLocal<'a> for ReadHierarchical<'a,T>
     where <T as Component>::Storage: HierarchicalStorage<'a,T>,
           for<'b> ReadGuardRef<'b, <T as Component>::Storage>: IntoOrderedIter
 {
@@ -1115,7 +1275,7 @@ impl<'a, T: 'a + ComponentSync> OrderedDataLocal<'a> for ReadHierarchical<'a,T>
 }
 
 
-impl<'a, T: 'a + ComponentSync> OrderedDataLocal<'a> for WriteHierarchical<'a,T>
+impl<'a, T: 'a + Component> OrderedDataLocal<'a> for WriteHierarchical<'a,T>
     where <T as Component>::Storage: HierarchicalStorage<'a,T>,
           for<'b> WriteGuardRef<'b, <T as Component>::Storage>: IntoOrderedIterMut
 {
@@ -1280,6 +1440,20 @@ macro_rules! impl_combined_ordered_iter {
             }
         }
 
+        impl<'a, $uo: ::HierarchicalData<'a>, $($u: ::UnorderedData<'a>),* > ::HierarchicalData<'a> for ($uo, $($u),*)
+            where
+            <$uo as ::OrderedData<'a>>::Storage: 'a,
+            $uo: 'a,
+            $(
+                <$u as ::UnorderedData<'a>>::Storage: 'a,
+                $u: 'a,
+            )*
+        {
+            fn traversal_ids(world: &'a ::World, mask: ::bitmask::Bitmask, traversal: &::Traversal) -> Vec<(usize, usize)>{
+                <$uo as ::HierarchicalData<'a>>::traversal_ids(world, mask, traversal)
+            }
+        }
+
         impl<'a, $uo: ::OrderedDataLocal<'a>, $($u: ::UnorderedDataLocal<'a>),* > ::OrderedDataLocal<'a> for ($uo, $($u),*)
             where
                 <$uo as ::OrderedDataLocal<'a>>::Storage: 'a,
@@ -1347,6 +1521,20 @@ macro_rules! impl_combined_ordered_iter {
                 $uo::ordered_ids(world, mask)
             }
         }
+
+        impl<'a, $uo: ::HierarchicalDataLocal<'a>, $($u: ::UnorderedDataLocal<'a>),* > ::HierarchicalDataLocal<'a> for ($uo, $($u),*)
+            where
+                <$uo as ::OrderedDataLocal<'a>>::Storage: 'a,
+                $uo: 'a,
+                $(
+                    <$u as ::UnorderedDataLocal<'a>>::Storage: 'a,
+                    $u: 'a,
+                )*
+        {
+            fn traversal_ids(world: &'a ::World, mask: ::bitmask::Bitmask, traversal: &::Traversal) -> Vec<(usize, usize)>{
+                <$uo as ::HierarchicalDataLocal<'a>>::traversal_ids(world, mask, traversal)
+            }
+        }
     )
 }
 
@@ -1400,18 +1588,11 @@ impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a
     }
 }
 
-pub struct ReadAndParentIter<'a, T: Component>
-    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
-          for<'b> RwLockReadGuard<'b, <T as Component>::Storage>: IntoHierarchicalIter<'b,T>
-{
+pub struct ReadAndParentIter<'a, T: 'a>{
     it: ForestHierarchicalIter<'a, T> //<RwLockWriteGuard<'a, <T as Component>::Storage> as IntoOrderedIterMut>::OrderedIterMut
 }
 
-impl<'a,T> Iterator for ReadAndParentIter<'a, T>
-    where T: Component,
-          <T as Component>::Storage: HierarchicalStorage<'a,T>,
-          for<'b> RwLockReadGuard<'b, <T as Component>::Storage>: IntoHierarchicalIter<'b,T>,
-{
+impl<'a, T: 'a> Iterator for ReadAndParentIter<'a, T>{
     type Item = (&'a T, Option<&'a T>);
     fn next(&mut self) -> Option<(&'a T, Option<&'a T>)>{
         self.it.next().map(|n| {
@@ -1472,18 +1653,11 @@ impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentSync> StorageRef<'a
     }
 }
 
-pub struct WriteAndParentIter<'a, T: Component>
-    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
-          for<'b> RwLockWriteGuard<'b, <T as Component>::Storage>: IntoHierarchicalIterMut<'b,T>
-{
+pub struct WriteAndParentIter<'a, T: 'a>{
     it: ForestHierarchicalIterMut<'a, T> //<RwLockWriteGuard<'a, <T as Component>::Storage> as IntoOrderedIterMut>::OrderedIterMut
 }
 
-impl<'a,T> Iterator for WriteAndParentIter<'a, T>
-    where T: Component,
-          <T as Component>::Storage: HierarchicalStorage<'a,T>,
-          for<'b> RwLockWriteGuard<'b, <T as Component>::Storage>: IntoHierarchicalIterMut<'b,T>,
-{
+impl<'a, T: 'a> Iterator for WriteAndParentIter<'a, T>{
     type Item = (&'a mut T, Option<&'a T>);
     fn next(&mut self) -> Option<(&'a mut T, Option<&'a T>)>{
         self.it.next().map(|mut n| {
@@ -1524,124 +1698,729 @@ impl<'a, T: 'a + ComponentSync> OrderedData<'a> for WriteAndParent<'a,T>
     }
 }
 
+// Local ReadAndParent/WriteAndParent
+pub struct ParentStorageReadLocal<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal>{
+    storage: ReadGuardRef<'a, S>,
+    _marker: marker::PhantomData<&'a T>,
+}
 
-// OneToN
-// use component::{OneToNComponent, OneToNComponentSync, OneToNComponentThreadLocal};
+impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal> StorageRef<'a, (&'a T, Option<&'a T>)> for ParentStorageReadLocal<'a, S, T>{
+    fn get(&self, guid: usize) -> (&'a T, Option<&'a T>){
+        let node = unsafe{ mem::transmute::<idtree::NodeRef<T>, idtree::NodeRef<T>>(self.storage.get_node(guid)) };
+        let parent = node.parent().map(|p| unsafe{ mem::transmute::<&T, &T>(&p) });
+        unsafe{ mem::transmute::<(&T, Option<&T>), (&T, Option<&T>)>((&node, parent)) }
+    }
 
-pub trait OneToNStorage<'a,T>: Storage<'a,T>{
-    fn insert_slice(&mut self, guid: usize, t: &[T]) where T: Clone;
-    unsafe fn get_slice(&self, guid: usize) -> &[T];
-    unsafe fn get_slice_mut(&mut self, guid: usize) -> &mut [T];
+    fn contains(&self, guid: usize) -> bool {
+        self.storage.contains(guid)
+    }
 }
 
+pub struct ParentStorageWriteLocal<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal>{
+    storage: UnsafeCell<WriteGuardRef<'a, S>>,
+    _marker: marker::PhantomData<&'a T>,
+}
 
-// OneToNHierarchical
-pub trait HierarchicalOneToNStorage<'a,T>: Storage<'a,T>{
-    unsafe fn insert_root(&mut self, guid: usize, t: T) -> idtree::NodeRefMut<T>;
-    unsafe fn insert_child(&mut self, parent: idtree::NodeId, t: T) -> idtree::NodeRefMut<T>;
+impl<'a, S: HierarchicalStorage<'a,T> + 'a, T: 'a + ComponentThreadLocal> StorageRef<'a, (&'a mut T, Option<&'a T>)> for ParentStorageWriteLocal<'a, S, T>{
+    fn get(&self, guid: usize) -> (&'a mut T, Option<&'a T>){
+        let mut node = unsafe{ mem::transmute::<idtree::NodeRefMut<T>, idtree::NodeRefMut<T>>((*self.storage.get()).get_node_mut(guid)) };
+        let parent = node.parent().map(|p| unsafe{ mem::transmute::<&T, &T>(&p) });
+        unsafe{ mem::transmute::<(&mut T, Option<&T>), (&mut T, Option<&T>)>((&mut node, parent)) }
+    }
+
+    fn contains(&self, guid: usize) -> bool {
+        unsafe{ (*self.storage.get()).contains(guid) }
+    }
+}
+
+impl<'a, T: 'a + Component> OrderedDataLocal<'a> for ReadAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> ReadGuardRef<'b, <T as Component>::Storage>: IntoHierarchicalIter<'b,T>
+{
+    type Iter = ReadAndParentIter<'a,T>;
+    type Components = T;
+    type ComponentsRef = (&'a T, Option<&'a T>);
+    type Storage = ParentStorageReadLocal<'a, <T as Component>::Storage, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        ReadAndParentIter{
+            it: world.storage_thread_local::<T>().unwrap().into_hierarchical_iter()
+        }
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        ParentStorageReadLocal{
+            storage: world.storage_thread_local::<T>().unwrap(),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.thread_local_ordered_entities_for::<T>(mask)
+    }
+}
+
+impl<'a, T: 'a + Component> OrderedDataLocal<'a> for WriteAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> WriteGuardRef<'b, <T as Component>::Storage>: IntoHierarchicalIterMut<'b,T>
+{
+    type Iter = WriteAndParentIter<'a,T>;
+    type Components = T;
+    type ComponentsRef = (&'a mut T, Option<&'a T>);
+    type Storage = ParentStorageWriteLocal<'a, <T as Component>::Storage, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        WriteAndParentIter{
+            it: world.storage_thread_local_mut::<T>().unwrap().into_hierarchical_iter_mut()
+        }
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        ParentStorageWriteLocal{
+            storage: UnsafeCell::new(world.storage_thread_local_mut::<T>().unwrap()),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.thread_local_ordered_entities_for::<T>(mask)
+    }
+}
+
+
+// Ordered iteration of any unordered data S following the order of the
+// hierarchy H, entities that aren't part of H are skipped
+pub struct OrderedBy<'a, H: 'a + Hierarchy, S: 'a>{
+    _marker: marker::PhantomData<&'a (H, S)>,
+}
+
+impl<'a, H: 'a + Hierarchy, S: UnorderedData<'a> + 'a> OrderedData<'a> for OrderedBy<'a, H, S>
+    where <S as UnorderedData<'a>>::Storage: StorageRef<'a, <S as UnorderedData<'a>>::ComponentsRef> + 'a
+{
+    type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+    type Components = <S as UnorderedData<'a>>::Components;
+    type ComponentsRef = <S as UnorderedData<'a>>::ComponentsRef;
+    type Storage = <S as UnorderedData<'a>>::Storage;
+    fn components_mask(world: &'a World) -> Bitmask{
+        <S as UnorderedData<'a>>::components_mask(world)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        let ids = <Self as OrderedData>::ordered_ids(world, <Self as OrderedData>::components_mask(world));
+        HierarchyEntitiesIter::new(ids, <Self as OrderedData>::storage(world))
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        <S as UnorderedData<'a>>::storage(world)
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.hierarchy_ordered_entities_for::<H>(mask)
+    }
+}
+
+impl<'a, H: 'a + Hierarchy, S: UnorderedDataLocal<'a> + 'a> OrderedDataLocal<'a> for OrderedBy<'a, H, S>
+    where <S as UnorderedDataLocal<'a>>::Storage: StorageRef<'a, <S as UnorderedDataLocal<'a>>::ComponentsRef> + 'a
+{
+    type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+    type Components = <S as UnorderedDataLocal<'a>>::Components;
+    type ComponentsRef = <S as UnorderedDataLocal<'a>>::ComponentsRef;
+    type Storage = <S as UnorderedDataLocal<'a>>::Storage;
+    fn components_mask(world: &'a World) -> Bitmask{
+        <S as UnorderedDataLocal<'a>>::components_mask(world)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        let ids = <Self as OrderedDataLocal>::ordered_ids(world, <Self as OrderedDataLocal>::components_mask(world));
+        HierarchyEntitiesIter::new(ids, <Self as OrderedDataLocal>::storage(world))
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        <S as UnorderedDataLocal<'a>>::storage(world)
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.hierarchy_ordered_entities_for::<H>(mask)
+    }
+}
+
+// Like ReadAndParent and WriteAndParent but the parent comes from the
+// hierarchy H instead of the component's storage
+pub struct ReadAndParentIn<'a, H: 'a + Hierarchy, T: 'a + Component>{
+    _marker: marker::PhantomData<&'a (H, T)>,
+}
+
+pub struct WriteAndParentIn<'a, H: 'a + Hierarchy, T: 'a + Component>{
+    _marker: marker::PhantomData<&'a (H, T)>,
+}
+
+pub struct ParentInStorageRead<'a, S: 'a, T: 'a>{
+    storage: RwLockReadGuard<'a, S>,
+    parents: RwLockReadGuard<'a, ParentStorage>,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, S: 'a, T: 'a> StorageRef<'a, (&'a T, Option<&'a T>)> for ParentInStorageRead<'a, S, T>
+    where for<'b> S: Storage<'b, T, Get = &'b T>
+{
+    fn get(&self, guid: usize) -> (&'a T, Option<&'a T>){
+        let parent = unsafe{ self.parents.parent_id(guid) }
+            .filter(|parent| self.storage.contains(*parent))
+            .map(|parent| unsafe{ mem::transmute::<&T, &T>(self.storage.get(parent)) });
+        (unsafe{ mem::transmute::<&T, &T>(self.storage.get(guid)) }, parent)
+    }
+
+    fn contains(&self, guid: usize) -> bool {
+        self.storage.contains(guid) && self.parents.contains(guid)
+    }
+}
+
+pub struct ParentInStorageWrite<'a, S: 'a, T: 'a>{
+    storage: UnsafeCell<RwLockWriteGuard<'a, S>>,
+    parents: RwLockReadGuard<'a, ParentStorage>,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, S: 'a, T: 'a> StorageRef<'a, (&'a mut T, Option<&'a T>)> for ParentInStorageWrite<'a, S, T>
+    where for<'b> S: Storage<'b, T, Get = &'b T, GetMut = &'b mut T>
+{
+    fn get(&self, guid: usize) -> (&'a mut T, Option<&'a T>){
+        let storage = unsafe{ &mut *self.storage.get() };
+        let parent = unsafe{ self.parents.parent_id(guid) }
+            .filter(|parent| storage.contains(*parent))
+            .map(|parent| unsafe{ mem::transmute::<&T, &T>(storage.get(parent)) });
+        (unsafe{ mem::transmute::<&mut T, &mut T>(storage.get_mut(guid)) }, parent)
+    }
+
+    fn contains(&self, guid: usize) -> bool {
+        unsafe{ (*self.storage.get()).contains(guid) && self.parents.contains(guid) }
+    }
+}
+
+impl<'a, H: 'a + Hierarchy, T: 'a + ComponentSync> OrderedData<'a> for ReadAndParentIn<'a, H, T>
+    where for<'b> <T as Component>::Storage: Storage<'b, T, Get = &'b T>
+{
+    type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+    type Components = T;
+    type ComponentsRef = (&'a T, Option<&'a T>);
+    type Storage = ParentInStorageRead<'a, <T as Component>::Storage, T>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        let ids = Self::ordered_ids(world, Self::components_mask(world));
+        HierarchyEntitiesIter::new(ids, Self::storage(world))
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        ParentInStorageRead{
+            storage: world.storage::<T>().unwrap(),
+            parents: world.hierarchy_storage::<H>().unwrap(),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.hierarchy_ordered_entities_for::<H>(mask)
+    }
 }
 
-// pub struct ReadOneToN<'a, T: 'a + OneToNComponent>{
-//     marker: marker::PhantomData<&'a T>,
-// }
-//
-//
-// pub struct WriteOneToN<'a, T: 'a + OneToNComponent>{
-//     marker: marker::PhantomData<&'a T>,
-// }
-//
-//
-// impl<'a, T: 'a + OneToNComponentSync> UnorderedData<'a> for ReadOneToN<'a,T>
-//     where for<'b> RwLockReadGuard<'b, <T as Component>::Storage>: IntoIter
-// {
-//     type Iter = <RwLockReadGuard<'a, <T as Component>::Storage> as IntoIter>::Iter;
-//     type Components = T;
-//     type ComponentsRef = &'a [T];
-//     type Storage = StorageRead<'a, <T as Component>::Storage, Self::Components>;
-//     fn components_mask(world: &'a World) -> usize{
-//         world.components_mask::<T>()
-//     }
-//
-//     fn into_iter(world: &'a ::World) -> Self::Iter{
-//         world.storage::<T>().unwrap().into_iter()
-//     }
-//
-//     fn storage(world: &'a ::World) -> Self::Storage{
-//         StorageRead{
-//             storage: world.storage::<T>().unwrap(),
-//             _marker: marker::PhantomData,
-//         }
-//     }
-// }
-//
-//
-// impl<'a, T: 'a + OneToNComponentSync> UnorderedData<'a> for WriteOneToN<'a,T>
-//     where for<'b> RwLockWriteGuard<'b, <T as Component>::Storage>: IntoIterMut
-// {
-//     type Iter = <RwLockWriteGuard<'a, <T as Component>::Storage> as IntoIterMut>::IterMut;
-//     type Components = T;
-//     type ComponentsRef = &'a mut [T];
-//     type Storage = StorageWrite<'a, <T as Component>::Storage, Self::Components>;
-//     fn components_mask(world: &'a World) -> usize{
-//         world.components_mask::<T>()
-//     }
-//
-//     fn into_iter(world: &'a ::World) -> Self::Iter{
-//         world.storage_mut::<T>().unwrap().into_iter_mut()
-//     }
-//
-//     fn storage(world: &'a ::World) -> Self::Storage{
-//         StorageWrite{
-//             storage: UnsafeCell::new(world.storage_mut::<T>().unwrap()),
-//             _marker: marker::PhantomData,
-//         }
-//     }
-// }
-//
-//
-// impl<'a, T: 'a + OneToNComponentThreadLocal> UnorderedDataLocal<'a> for ReadOneToN<'a,T>
-//     where for<'b> ReadGuardRef<'b, <T as Component>::Storage>: IntoIter
-// {
-//     type Iter = <ReadGuardRef<'a, <T as Component>::Storage> as IntoIter>::Iter;
-//     type Components = T;
-//     type ComponentsRef = &'a [T];
-//     type Storage = StorageReadLocal<'a, <T as Component>::Storage, Self::Components>;
-//     fn components_mask(world: &'a World) -> usize{
-//         world.components_mask::<T>()
-//     }
-//
-//     fn into_iter(world: &'a ::World) -> Self::Iter{
-//         world.storage_thread_local::<T>().unwrap().into_iter()
-//     }
-//
-//     fn storage(world: &'a ::World) -> Self::Storage{
-//         StorageReadLocal{
-//             storage: world.storage_thread_local::<T>().unwrap(),
-//             _marker: marker::PhantomData,
-//         }
-//     }
-// }
-//
-//
-// impl<'a, T: 'a + OneToNComponentThreadLocal> UnorderedDataLocal<'a> for WriteOneToN<'a,T>
-//     where for<'b> WriteGuardRef<'b, <T as Component>::Storage>: IntoIterMut
-// {
-//     type Iter = <WriteGuardRef<'a, <T as Component>::Storage> as IntoIterMut>::IterMut;
-//     type Components = T;
-//     type ComponentsRef = &'a mut [T];
-//     type Storage = StorageWriteLocal<'a, <T as Component>::Storage, Self::Components>;
-//     fn components_mask(world: &'a World) -> usize{
-//         world.components_mask::<T>()
-//     }
-//
-//     fn into_iter(world: &'a ::World) -> Self::Iter{
-//         world.storage_thread_local_mut::<T>().unwrap().into_iter_mut()
-//     }
-//
-//     fn storage(world: &'a ::World) -> Self::Storage{
-//         StorageWriteLocal{
-//             storage: UnsafeCell::new(world.storage_thread_local_mut::<T>().unwrap()),
-//             _marker: marker::PhantomData,
-//         }
-//     }
-// }
+impl<'a, H: 'a + Hierarchy, T: 'a + ComponentSync> OrderedData<'a> for WriteAndParentIn<'a, H, T>
+    where for<'b> <T as Component>::Storage: Storage<'b, T, Get = &'b T, GetMut = &'b mut T>
+{
+    type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+    type Components = T;
+    type ComponentsRef = (&'a mut T, Option<&'a T>);
+    type Storage = ParentInStorageWrite<'a, <T as Component>::Storage, T>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        let ids = Self::ordered_ids(world, Self::components_mask(world));
+        HierarchyEntitiesIter::new(ids, Self::storage(world))
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        ParentInStorageWrite{
+            storage: UnsafeCell::new(world.storage_mut::<T>().unwrap()),
+            parents: world.hierarchy_storage::<H>().unwrap(),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+        world.hierarchy_ordered_entities_for::<H>(mask)
+    }
+}
+
+// Ordered data that can also be visited following a Traversal, the ids are
+// returned with their depth
+pub trait HierarchicalData<'a>: OrderedData<'a>{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>;
+}
+
+pub trait HierarchicalDataLocal<'a>: OrderedDataLocal<'a>{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>;
+}
+
+impl<'a, T: 'a + ComponentSync> HierarchicalData<'a> for ReadHierarchical<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> RwLockReadGuard<'b, <T as Component>::Storage>: IntoOrderedIter
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + ComponentSync> HierarchicalData<'a> for WriteHierarchical<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> RwLockWriteGuard<'b, <T as Component>::Storage>: IntoOrderedIterMut
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + ComponentSync> HierarchicalData<'a> for ReadAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> RwLockReadGuard<'b, <T as Component>::Storage>: IntoHierarchicalIter<'b,T>
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + ComponentSync> HierarchicalData<'a> for WriteAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> RwLockWriteGuard<'b, <T as Component>::Storage>: IntoHierarchicalIterMut<'b,T>
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + Component> HierarchicalDataLocal<'a> for ReadHierarchical<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> ReadGuardRef<'b, <T as Component>::Storage>: IntoOrderedIter
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.thread_local_traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + Component> HierarchicalDataLocal<'a> for ReadAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> ReadGuardRef<'b, <T as Component>::Storage>: IntoHierarchicalIter<'b,T>
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.thread_local_traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + Component> HierarchicalDataLocal<'a> for WriteHierarchical<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> WriteGuardRef<'b, <T as Component>::Storage>: IntoOrderedIterMut
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.thread_local_traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+impl<'a, T: 'a + Component> HierarchicalDataLocal<'a> for WriteAndParent<'a,T>
+    where <T as Component>::Storage: HierarchicalStorage<'a,T>,
+          for<'b> WriteGuardRef<'b, <T as Component>::Storage>: IntoHierarchicalIterMut<'b,T>
+{
+    fn traversal_ids(world: &'a World, mask: Bitmask, traversal: &Traversal) -> Vec<(usize, usize)>{
+        world.thread_local_traversal_ids_for::<T>(mask, traversal)
+    }
+}
+
+pub struct TraversalIter<'a, T, S>{
+    ids: vec::IntoIter<(usize, usize)>,
+    storage: S,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T, S: StorageRef<'a, T> + 'a> TraversalIter<'a, T, S>{
+    pub(crate) fn new(ids: Vec<(usize, usize)>, storage: S) -> TraversalIter<'a, T, S>{
+        TraversalIter{
+            ids: ids.into_iter(),
+            storage,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T, S: StorageRef<'a, T> + 'a> Iterator for TraversalIter<'a, T, S>{
+    type Item = (usize, T);
+    fn next(&mut self) -> Option<(usize, T)>{
+        self.ids.next().map(|(depth, guid)| (depth, self.storage.get(guid)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>){
+        self.ids.size_hint()
+    }
+}
+
+// Ordered data that can be processed in parallel, every tree is handed to a
+// different task and processed in hierarchical order so parents are still
+// processed before their children
+pub trait ParOrderedData<'a>: for<'b> ParComponentsRef<'b>{
+    fn par_for_each<F>(world: &'a World, f: F)
+        where F: for<'b> Fn(<Self as ParComponentsRef<'b>>::ComponentsRef) + Sync;
+}
+
+// The components only live for the call to the callback so they can't be
+// sent to other tasks or kept past the storage guard
+pub trait ParComponentsRef<'b>{
+    type ComponentsRef;
+}
+
+impl<'a, 'b, T: 'a + Component> ParComponentsRef<'b> for WriteHierarchical<'a,T>{
+    type ComponentsRef = &'b mut T;
+}
+
+impl<'a, 'b, T: 'a + Component> ParComponentsRef<'b> for WriteAndParent<'a,T>{
+    type ComponentsRef = (&'b mut T, Option<&'b T>);
+}
+
+impl<'a, T: 'a + ComponentSync<Storage = forest::Forest<T>> + Sync> ParOrderedData<'a> for WriteHierarchical<'a,T>{
+    fn par_for_each<F>(world: &'a World, f: F)
+        where F: for<'b> Fn(&'b mut T) + Sync
+    {
+        let mut storage = world.storage_mut::<T>().unwrap();
+        storage.par_for_each_mut(f)
+    }
+}
+
+impl<'a, T: 'a + ComponentSync<Storage = forest::Forest<T>> + Sync> ParOrderedData<'a> for WriteAndParent<'a,T>{
+    fn par_for_each<F>(world: &'a World, f: F)
+        where F: for<'b> Fn((&'b mut T, Option<&'b T>)) + Sync
+    {
+        let mut storage = world.storage_mut::<T>().unwrap();
+        storage.par_for_each_and_parent_mut(|t, parent| f((t, parent)))
+    }
+}
+
+
+// OneToN
+use component::{OneToNComponent, OneToNComponentSync, OneToNComponentThreadLocal};
+use oneton_densevec::{DenseOneToNVec, OneToNDenseIter, OneToNDenseIterMut};
+
+pub trait OneToNStorage<'a,T>: Storage<'a,T>{
+    fn insert_slice(&mut self, guid: usize, t: &[T]) where T: Clone;
+    unsafe fn get_slice(&self, guid: usize) -> &[T];
+    unsafe fn get_slice_mut(&mut self, guid: usize) -> &mut [T];
+    fn push(&mut self, guid: usize, t: T);
+    unsafe fn remove_at(&mut self, guid: usize, index: usize) -> T;
+    unsafe fn truncate(&mut self, guid: usize, len: usize);
+    fn replace_slice(&mut self, guid: usize, t: &[T]) where T: Clone;
+}
+
+
+// Contiguous storages, used by chunked iteration
+pub trait SliceStorage<'a,T>: Storage<'a,T>{
+    fn as_slice(&self) -> &[T];
+    fn as_mut_slice(&mut self) -> &mut [T];
+    unsafe fn position(&self, guid: usize) -> usize;
+}
+
+
+// OneToNHierarchical
+pub trait HierarchicalOneToNStorage<'a,T>: Storage<'a,T>{
+    unsafe fn insert_root(&mut self, guid: usize, t: T) -> idtree::NodeRefMut<T>;
+    unsafe fn insert_child(&mut self, parent: idtree::NodeId, t: T) -> idtree::NodeRefMut<T>;
+}
+
+// Depth first iteration over the trees of each entity with a
+// HierarchicalOneToNComponent as built with HierarchyBuilder
+pub struct ReadHierarchy<'a, T: 'a + Component>{
+    _marker: marker::PhantomData<&'a T>,
+}
+
+pub struct ReadHierarchyAndParent<'a, T: 'a + Component>{
+    _marker: marker::PhantomData<&'a T>,
+}
+
+pub struct WriteHierarchyAndParent<'a, T: 'a + Component>{
+    _marker: marker::PhantomData<&'a T>,
+}
+
+pub struct HierarchyStorageRead<'a, T: 'a>{
+    storage: ReadGuardRef<'a, OneToNForest<T>>,
+}
+
+impl<'a, T: 'a + HierarchicalOneToNComponent> HierarchyStorageRead<'a, T>{
+    fn new(world: &'a World) -> HierarchyStorageRead<'a, T>{
+        HierarchyStorageRead{
+            storage: ::ReadGuardRef::new(::ReadGuard::Sync(world.storage::<T>().unwrap())),
+        }
+    }
+
+    fn new_local(world: &'a World) -> HierarchyStorageRead<'a, T>{
+        HierarchyStorageRead{
+            storage: world.storage_thread_local::<T>().unwrap(),
+        }
+    }
+}
+
+impl<'a, T: 'a> StorageRef<'a, HierarchyIter<'a, T>> for HierarchyStorageRead<'a, T>{
+    fn get(&self, guid: usize) -> HierarchyIter<'a, T>{
+        let storage = unsafe{ mem::transmute::<&OneToNForest<T>, &OneToNForest<T>>(&self.storage) };
+        unsafe{ storage.hierarchy(guid) }
+    }
+
+    fn contains(&self, guid: usize) -> bool{
+        self.storage.contains(guid)
+    }
+}
+
+impl<'a, T: 'a> StorageRef<'a, HierarchyAndParentIter<'a, T>> for HierarchyStorageRead<'a, T>{
+    fn get(&self, guid: usize) -> HierarchyAndParentIter<'a, T>{
+        let storage = unsafe{ mem::transmute::<&OneToNForest<T>, &OneToNForest<T>>(&self.storage) };
+        unsafe{ storage.hierarchy_and_parent(guid) }
+    }
+
+    fn contains(&self, guid: usize) -> bool{
+        self.storage.contains(guid)
+    }
+}
+
+pub struct HierarchyStorageWrite<'a, T: 'a>{
+    storage: UnsafeCell<WriteGuardRef<'a, OneToNForest<T>>>,
+}
+
+impl<'a, T: 'a + HierarchicalOneToNComponent> HierarchyStorageWrite<'a, T>{
+    fn new(world: &'a World) -> HierarchyStorageWrite<'a, T>{
+        HierarchyStorageWrite{
+            storage: UnsafeCell::new(::WriteGuardRef::new(::WriteGuard::Sync(world.storage_mut::<T>().unwrap()))),
+        }
+    }
+
+    fn new_local(world: &'a World) -> HierarchyStorageWrite<'a, T>{
+        HierarchyStorageWrite{
+            storage: UnsafeCell::new(world.storage_thread_local_mut::<T>().unwrap()),
+        }
+    }
+}
+
+impl<'a, T: 'a> StorageRef<'a, HierarchyAndParentIterMut<'a, T>> for HierarchyStorageWrite<'a, T>{
+    fn get(&self, guid: usize) -> HierarchyAndParentIterMut<'a, T>{
+        let storage = unsafe{ mem::transmute::<&mut OneToNForest<T>, &mut OneToNForest<T>>(&mut (*self.storage.get())) };
+        unsafe{ storage.hierarchy_and_parent_mut(guid) }
+    }
+
+    fn contains(&self, guid: usize) -> bool{
+        unsafe{ (*self.storage.get()).contains(guid) }
+    }
+}
+
+pub struct HierarchyEntitiesIter<'a, T, S>{
+    _ids: ::IndexGuard<'a>,
+    ptr: *const usize,
+    end: *const usize,
+    storage: S,
+    _marker: marker::PhantomData<T>,
+}
+
+impl<'a, T, S: StorageRef<'a, T> + 'a> HierarchyEntitiesIter<'a, T, S>{
+    fn new(ids: ::IndexGuard<'a>, storage: S) -> HierarchyEntitiesIter<'a, T, S>{
+        HierarchyEntitiesIter{
+            ptr: ids.index.as_ptr(),
+            end: unsafe{ ids.index.as_ptr().offset(ids.index.len() as isize) },
+            _ids: ids,
+            storage,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T, S: StorageRef<'a, T> + 'a> Iterator for HierarchyEntitiesIter<'a, T, S>{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item>{
+        unsafe {
+            if self.ptr == self.end {
+                None
+            } else {
+                let guid = *self.ptr;
+                self.ptr = self.ptr.offset(1);
+                Some(self.storage.get(guid))
+            }
+        }
+    }
+}
+
+macro_rules! impl_hierarchy_data {
+    ($ty: ident, $components_ref: ident, $storage: ident) => (
+        impl<'a, T: 'a + HierarchicalOneToNComponentSync> OrderedData<'a> for $ty<'a,T> {
+            type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+            type Components = T;
+            type ComponentsRef = $components_ref<'a, T>;
+            type Storage = $storage<'a, T>;
+            fn components_mask(world: &'a World) -> Bitmask{
+                Bitmask::has(world.components_mask::<T>())
+            }
+
+            fn into_iter(world: &'a ::World) -> Self::Iter{
+                let ids = <Self as OrderedData>::ordered_ids(world, <Self as OrderedData>::components_mask(world));
+                HierarchyEntitiesIter::new(ids, <Self as OrderedData>::storage(world))
+            }
+
+            fn storage(world: &'a ::World) -> Self::Storage{
+                $storage::new(world)
+            }
+
+            fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+                world.entities_for_mask(mask)
+            }
+        }
+
+        impl<'a, T: 'a + HierarchicalOneToNComponent> OrderedDataLocal<'a> for $ty<'a,T> {
+            type Iter = HierarchyEntitiesIter<'a, Self::ComponentsRef, Self::Storage>;
+            type Components = T;
+            type ComponentsRef = $components_ref<'a, T>;
+            type Storage = $storage<'a, T>;
+            fn components_mask(world: &'a World) -> Bitmask{
+                Bitmask::has(world.components_mask::<T>())
+            }
+
+            fn into_iter(world: &'a ::World) -> Self::Iter{
+                let ids = <Self as OrderedDataLocal>::ordered_ids(world, <Self as OrderedDataLocal>::components_mask(world));
+                HierarchyEntitiesIter::new(ids, <Self as OrderedDataLocal>::storage(world))
+            }
+
+            fn storage(world: &'a ::World) -> Self::Storage{
+                $storage::new_local(world)
+            }
+
+            fn ordered_ids(world: &'a ::World, mask: Bitmask) -> IndexGuard{
+                world.entities_for_mask(mask)
+            }
+        }
+    )
+}
+
+impl_hierarchy_data!(ReadHierarchy, HierarchyIter, HierarchyStorageRead);
+impl_hierarchy_data!(ReadHierarchyAndParent, HierarchyAndParentIter, HierarchyStorageRead);
+impl_hierarchy_data!(WriteHierarchyAndParent, HierarchyAndParentIterMut, HierarchyStorageWrite);
+
+pub struct ReadOneToN<'a, T: 'a + OneToNComponent>{
+    _marker: marker::PhantomData<&'a T>,
+}
+
+
+pub struct WriteOneToN<'a, T: 'a + OneToNComponent>{
+    _marker: marker::PhantomData<&'a T>,
+}
+
+
+impl<'a, T: 'a + OneToNComponentSync> UnorderedData<'a> for ReadOneToN<'a,T>{
+    type Iter = OneToNDenseIter<'a, T>;
+    type Components = T;
+    type ComponentsRef = &'a [T];
+    type Storage = StorageRead<'a, DenseOneToNVec<T>, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        world.storage::<T>().unwrap().into_iter()
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        StorageRead{
+            storage: world.storage::<T>().unwrap(),
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+
+impl<'a, T: 'a + OneToNComponentSync> UnorderedData<'a> for WriteOneToN<'a,T>{
+    type Iter = OneToNDenseIterMut<'a, T>;
+    type Components = T;
+    type ComponentsRef = &'a mut [T];
+    type Storage = StorageWrite<'a, DenseOneToNVec<T>, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        world.storage_mut::<T>().unwrap().into_iter_mut()
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        StorageWrite{
+            storage: UnsafeCell::new(world.storage_mut::<T>().unwrap()),
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+
+impl<'a, T: 'a + OneToNComponentThreadLocal> UnorderedDataLocal<'a> for ReadOneToN<'a,T>{
+    type Iter = OneToNDenseIter<'a, T>;
+    type Components = T;
+    type ComponentsRef = &'a [T];
+    type Storage = StorageReadLocal<'a, DenseOneToNVec<T>, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        world.storage_thread_local::<T>().unwrap().into_iter()
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        StorageReadLocal{
+            storage: world.storage_thread_local::<T>().unwrap(),
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+
+impl<'a, T: 'a + OneToNComponentThreadLocal> UnorderedDataLocal<'a> for WriteOneToN<'a,T>{
+    type Iter = OneToNDenseIterMut<'a, T>;
+    type Components = T;
+    type ComponentsRef = &'a mut [T];
+    type Storage = StorageWriteLocal<'a, DenseOneToNVec<T>, Self::Components>;
+    fn components_mask(world: &'a World) -> Bitmask{
+        Bitmask::has(world.components_mask::<T>())
+    }
+
+    fn try_components_mask(world: &'a World) -> Result<Bitmask, Error>{
+        world.try_components_mask::<T>().map(Bitmask::has)
+    }
+
+    fn into_iter(world: &'a ::World) -> Self::Iter{
+        world.storage_thread_local_mut::<T>().unwrap().into_iter_mut()
+    }
+
+    fn storage(world: &'a ::World) -> Self::Storage{
+        StorageWriteLocal{
+            storage: UnsafeCell::new(world.storage_thread_local_mut::<T>().unwrap()),
+            _marker: marker::PhantomData,
+        }
+    }
+}
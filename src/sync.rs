@@ -7,6 +7,11 @@ use component::Component;
 use entity::Entity;
 use storage::Storage;
 
+// Raw pointer that can be shared between threads when the accesses through
+// it are known not to overlap
+pub(crate) struct SyncPtr<T>(pub(crate) *mut T);
+unsafe impl<T> Sync for SyncPtr<T>{}
+
 pub struct IndexGuard<'a>{
     pub(crate) _index_guard: RwLockReadGuard<'a, Vec<usize>>,
     pub(crate) index: &'a [usize],
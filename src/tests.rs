@@ -1134,6 +1134,77 @@ fn pointer_to_hierarchy_root(){
     }
 }
 
+#[test]
+fn chunked_iteration() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::DenseVec<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Vel{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Vel{
+        type Storage = ::DenseVec<Vel>;
+        fn type_name() -> String{
+            "Vel".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    world.register::<Vel>();
+    let mut all = vec![];
+    for i in 0..10 {
+        let e = world.create_entity()
+            .add(Pos{x: i as f32, y: i as f32})
+            .add(Vel{x: 1., y: 1.})
+            .build();
+        all.push(e);
+    }
+
+    fn update(world: &::World){
+        let entities = world.entities();
+        entities.for_each_chunk_for::<(::Write<Pos>, ::Read<Vel>),_>(4, |chunk_entities, (pos, vel)| {
+            assert!(chunk_entities.len() <= 4);
+            assert_eq!(chunk_entities.len(), pos.len());
+            assert_eq!(pos.len(), vel.len());
+            for (pos, vel) in pos.iter_mut().zip(vel) {
+                pos.x += vel.x;
+                pos.y += vel.y;
+            }
+        });
+    }
+
+    update(&world);
+    {
+        let entities = world.entities();
+        for (i, pos) in entities.iter_for::<::Read<Pos>>().enumerate() {
+            assert_eq!(*pos, Pos{x: i as f32 + 1., y: i as f32 + 1.});
+        }
+    }
+
+    // Entity 3 no longer matches so chunks around it need to be gathered
+    world.remove_component_from::<Vel>(&all[3]);
+    update(&world);
+    let entities = world.entities();
+    for (i, pos) in entities.iter_for::<::Read<Pos>>().enumerate() {
+        let expected = if i == 3 { i as f32 + 1. } else { i as f32 + 2. };
+        assert_eq!(*pos, Pos{x: expected, y: expected});
+    }
+}
+
 // #[test]
 // fn insert_read_slice_alloc() {
 //     struct Vertex{
@@ -1178,3 +1249,1746 @@ fn pointer_to_hierarchy_root(){
 //         assert_eq!(poss[0], Vertex{x: poss.len() as f32, y: poss.len() as f32});
 //     }
 // }
+
+#[test]
+fn query_random_access() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::DenseVec<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Vel{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Vel{
+        type Storage = ::DenseVec<Vel>;
+        fn type_name() -> String{
+            "Vel".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    world.register::<Vel>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .add(Vel{x: 1., y: 1.})
+        .build();
+    let e2 = world.create_entity()
+        .add(Pos{x: 2., y: 2.})
+        .build();
+    let e3 = world.create_entity()
+        .add(Pos{x: 3., y: 3.})
+        .add(Vel{x: 3., y: 3.})
+        .build();
+
+    let entities = world.entities();
+    {
+        let query = entities.query_for::<(::Read<Pos>, ::ReadOption<Vel>)>();
+        let (pos1, vel1) = query.get(&e1).unwrap();
+        let (pos2, vel2) = query.get(&e2).unwrap();
+        assert_eq!((*pos1, vel1.map(|vel| *vel)), (Pos{x: 1., y: 1.}, Some(Vel{x: 1., y: 1.})));
+        assert_eq!((*pos2, vel2), (Pos{x: 2., y: 2.}, None));
+    }
+
+    let mut query = entities.query_for::<(::Read<Pos>, ::Write<Vel>)>();
+    assert!(query.get_many_mut([e1]).unwrap().into_iter().all(|(pos, _)| *pos == Pos{x: 1., y: 1.}));
+    assert!(query.get_many_mut([e2]).is_none());
+    assert!(query.get_many_mut([e1, e2]).is_none());
+    assert!(query.get_many_mut([e1, e1]).is_none());
+
+    for (pos, vel) in query.get_many_mut([e1, e3]).unwrap() {
+        vel.x += pos.x;
+        vel.y += pos.y;
+    }
+    drop(query);
+
+    assert_eq!(*entities.component_for::<Vel>(&e1).unwrap(), Vel{x: 2., y: 2.});
+    assert_eq!(*entities.component_for::<Vel>(&e3).unwrap(), Vel{x: 6., y: 6.});
+}
+
+#[test]
+fn relations() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Targets{
+        weight: u32,
+    }
+
+    impl ::Relation for Targets{
+        fn type_name() -> String{
+            "Targets".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::DenseVec<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    world.register_relation::<Targets>();
+    let e1 = world.create_entity().add(Pos{x: 1., y: 1.}).build();
+    let e2 = world.create_entity().add(Pos{x: 2., y: 2.}).build();
+    let e3 = world.create_entity().add(Pos{x: 3., y: 3.}).build();
+
+    world.add_relation(&e1, &e2, Targets{weight: 1});
+    world.add_relation(&e1, &e3, Targets{weight: 2});
+    world.add_relation(&e2, &e3, Targets{weight: 3});
+
+    {
+        let entities = world.entities();
+        let targets = entities.relations_from::<Targets>(&e1).collect::<Vec<_>>();
+        assert_eq!(targets, vec![(e2, &Targets{weight: 1}), (e3, &Targets{weight: 2})]);
+        let sources = entities.relations_to::<Targets>(&e3).collect::<Vec<_>>();
+        assert_eq!(sources, vec![e1, e2]);
+        assert_eq!(entities.relations_to::<Targets>(&e1).count(), 0);
+    }
+
+    assert_eq!(world.remove_relation::<Targets>(&e1, &e2), Some(Targets{weight: 1}));
+    assert_eq!(world.entities().relations_to::<Targets>(&e2).count(), 0);
+
+    world.remove_entity(&e3);
+    let entities = world.entities();
+    assert_eq!(entities.relations_from::<Targets>(&e1).count(), 0);
+    assert_eq!(entities.relations_from::<Targets>(&e2).count(), 0);
+    assert_eq!(entities.relations_to::<Targets>(&e3).count(), 0);
+}
+
+#[test]
+fn hierarchical_reparent() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    fn ordered(world: &::World) -> Vec<f32>{
+        world.entities()
+            .ordered_iter_for::<::ReadHierarchical<Pos>>()
+            .map(|n| n.data.x)
+            .collect()
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .build();
+    let e2 = world.create_entity()
+        .add(Pos{x: 2., y: 2.})
+        .build();
+    let e3 = world.create_entity()
+        .add_child(&e1, Pos{x: 3., y: 3.})
+        .build();
+    let _e4 = world.create_entity()
+        .add_child(&e2, Pos{x: 4., y: 4.})
+        .build();
+    let _e5 = world.create_entity()
+        .add_child(&e3, Pos{x: 5., y: 5.})
+        .build();
+    assert_eq!(ordered(&world), vec![1., 3., 5., 2., 4.]);
+
+    world.set_parent::<Pos>(&e3, &e2);
+    assert_eq!(ordered(&world), vec![1., 2., 4., 3., 5.]);
+    assert_eq!(world.entities().tree_node_for::<Pos>(&e3).unwrap().parent().map(|p| p.data), Some(Pos{x: 2., y: 2.}));
+
+    world.make_root::<Pos>(&e3);
+    assert!(world.entities().tree_node_for::<Pos>(&e3).unwrap().parent().is_none());
+    assert_eq!(ordered(&world), vec![1., 2., 4., 3., 5.]);
+
+    world.set_parent::<Pos>(&e2, &e1);
+    world.detach::<Pos>(&e2);
+    assert_eq!(ordered(&world), vec![1., 4., 3., 5., 2.]);
+    assert_eq!(world.entities().tree_node_for::<Pos>(&e2).unwrap().children().count(), 0);
+}
+
+#[test]
+fn hierarchical_remove_modes() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    fn ordered(world: &::World) -> Vec<f32>{
+        world.entities()
+            .ordered_iter_for::<::ReadHierarchical<Pos>>()
+            .map(|n| n.data.x)
+            .collect()
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .build();
+    let e2 = world.create_entity()
+        .add(Pos{x: 2., y: 2.})
+        .build();
+    let e3 = world.create_entity()
+        .add_child(&e1, Pos{x: 3., y: 3.})
+        .build();
+    let e4 = world.create_entity()
+        .add_child(&e2, Pos{x: 4., y: 4.})
+        .build();
+    let e5 = world.create_entity()
+        .add_child(&e3, Pos{x: 5., y: 5.})
+        .build();
+    let e6 = world.create_entity()
+        .add_child(&e5, Pos{x: 6., y: 6.})
+        .build();
+
+    world.remove_component_from_hierarchy::<Pos>(&e5, ::ChildrenMode::ToGrandparent);
+    assert_eq!(ordered(&world), vec![1., 3., 6., 2., 4.]);
+    assert_eq!(world.entities().tree_node_for::<Pos>(&e6).unwrap().parent().map(|p| p.data.x), Some(3.));
+
+    world.remove_component_from_hierarchy::<Pos>(&e3, ::ChildrenMode::ToRoots);
+    assert_eq!(ordered(&world), vec![1., 6., 2., 4.]);
+    assert!(world.entities().tree_node_for::<Pos>(&e6).unwrap().parent().is_none());
+    assert!(world.entities().component_for::<Pos>(&e3).is_none());
+
+    world.remove_entity_recursive::<Pos>(&e2);
+    assert_eq!(ordered(&world), vec![1., 6.]);
+    assert!(world.entities().component_for::<Pos>(&e4).is_none());
+    assert_eq!(world.entities().iter_for::<::Read<Pos>>().count(), 2);
+}
+
+#[test]
+fn hierarchical_ordered_index_updates() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Tag;
+
+    impl ::Component for Tag{
+        type Storage = ::DenseVec<Tag>;
+        fn type_name() -> String{
+            "Tag".to_owned()
+        }
+    }
+
+    fn ordered_tagged(world: &::World) -> Vec<f32>{
+        world.entities()
+            .ordered_iter_for::<(::ReadHierarchical<Pos>, ::Read<Tag>)>()
+            .map(|(n, _)| n.data.x)
+            .collect()
+    }
+
+    fn unordered_tagged(world: &::World) -> Vec<f32>{
+        world.entities()
+            .iter_for::<(::Read<Pos>, ::Read<Tag>)>()
+            .map(|(pos, _)| pos.x)
+            .collect()
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    world.register::<Tag>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .add(Tag)
+        .build();
+    let e2 = world.create_entity()
+        .add(Pos{x: 2., y: 2.})
+        .add(Tag)
+        .build();
+    let e3 = world.create_entity()
+        .add_child(&e1, Pos{x: 3., y: 3.})
+        .build();
+    let e4 = world.create_entity()
+        .add_child(&e3, Pos{x: 4., y: 4.})
+        .add(Tag)
+        .build();
+    assert_eq!(ordered_tagged(&world), vec![1., 4., 2.]);
+    assert_eq!(unordered_tagged(&world), vec![1., 2., 4.]);
+
+    world.add_component_to(&e3, Tag);
+    assert_eq!(ordered_tagged(&world), vec![1., 3., 4., 2.]);
+
+    world.set_parent::<Pos>(&e1, &e2);
+    assert_eq!(ordered_tagged(&world), vec![2., 1., 3., 4.]);
+    assert_eq!(unordered_tagged(&world), vec![1., 2., 3., 4.]);
+
+    world.detach::<Pos>(&e3);
+    assert_eq!(ordered_tagged(&world), vec![2., 1., 4., 3.]);
+
+    world.remove_component_from::<Tag>(&e1);
+    assert_eq!(ordered_tagged(&world), vec![2., 4., 3.]);
+
+    world.make_root::<Pos>(&e1);
+    let _e5 = world.create_entity()
+        .add_child(&e2, Pos{x: 5., y: 5.})
+        .add(Tag)
+        .build();
+    world.add_component_to(&e1, Tag);
+    assert_eq!(ordered_tagged(&world), vec![2., 5., 3., 1., 4.]);
+
+    world.remove_component_from_hierarchy::<Pos>(&e1, ::ChildrenMode::ToGrandparent);
+    world.set_parent::<Pos>(&e2, &e4);
+    assert_eq!(ordered_tagged(&world), vec![3., 4., 2., 5.]);
+
+    let e6 = world.create_entity()
+        .add(Pos{x: 6., y: 6.})
+        .build();
+    assert_eq!(ordered_tagged(&world), vec![3., 4., 2., 5.]);
+    world.add_component_to(&e6, Tag);
+    assert_eq!(ordered_tagged(&world), vec![3., 4., 2., 5., 6.]);
+    assert_eq!(unordered_tagged(&world), vec![2., 3., 4., 5., 6.]);
+}
+
+#[test]
+fn hierarchical_traversals() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    fn traverse(world: &::World, traversal: ::Traversal) -> Vec<(usize, f32)>{
+        world.entities()
+            .traverse_for::<::ReadHierarchical<Pos>>(traversal)
+            .map(|(depth, n)| (depth, n.data.x))
+            .collect()
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .build();
+    let e2 = world.create_entity()
+        .add_child(&e1, Pos{x: 2., y: 2.})
+        .build();
+    let e3 = world.create_entity()
+        .add_child(&e1, Pos{x: 3., y: 3.})
+        .build();
+    let _e4 = world.create_entity()
+        .add_child(&e2, Pos{x: 4., y: 4.})
+        .build();
+    let _e5 = world.create_entity()
+        .add(Pos{x: 5., y: 5.})
+        .build();
+    let _e6 = world.create_entity()
+        .add_child(&e3, Pos{x: 6., y: 6.})
+        .build();
+
+    assert_eq!(traverse(&world, ::Traversal::depth_first()),
+        vec![(0, 1.), (1, 2.), (2, 4.), (1, 3.), (2, 6.), (0, 5.)]);
+    assert_eq!(traverse(&world, ::Traversal::breadth_first()),
+        vec![(0, 1.), (0, 5.), (1, 2.), (1, 3.), (2, 4.), (2, 6.)]);
+    assert_eq!(traverse(&world, ::Traversal::depth_first().max_depth(1)),
+        vec![(0, 1.), (1, 2.), (1, 3.), (0, 5.)]);
+    assert_eq!(traverse(&world, ::Traversal::breadth_first().subtree(&e1).max_depth(1)),
+        vec![(0, 1.), (1, 2.), (1, 3.)]);
+    assert_eq!(traverse(&world, ::Traversal::depth_first().subtree(&e3)),
+        vec![(0, 3.), (1, 6.)]);
+
+    for (depth, mut node) in world.entities().traverse_for::<::WriteHierarchical<Pos>>(::Traversal::breadth_first().subtree(&e2)){
+        node.y = depth as f32;
+    }
+    let ys = world.entities()
+        .ordered_iter_for::<::ReadHierarchical<Pos>>()
+        .map(|n| n.data.y)
+        .collect::<Vec<_>>();
+    assert_eq!(ys, vec![1., 0., 1., 3., 6., 5.]);
+}
+
+#[test]
+fn hierarchical_thread_local_traversals() {
+    use std::rc::Rc;
+
+    #[derive(Debug,PartialEq,Clone)]
+    struct Pos(Rc<f32>);
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register_thread_local::<Pos>();
+    let e1 = world.create_entity()
+        .add_thread_local(Pos(Rc::new(1.)))
+        .build();
+    let e2 = world.create_entity()
+        .add_child_thread_local(e1, Pos(Rc::new(2.)))
+        .build();
+    world.create_entity()
+        .add_child_thread_local(e2, Pos(Rc::new(3.)))
+        .build();
+
+    let parents = world.entities_thread_local()
+        .traverse_for::<::ReadAndParent<Pos>>(::Traversal::depth_first())
+        .map(|(depth, (pos, parent))| (depth, *pos.0, parent.map(|p| *p.0)))
+        .collect::<Vec<_>>();
+    assert_eq!(parents, vec![(0, 1., None), (1, 2., Some(1.)), (2, 3., Some(2.))]);
+
+    for (pos, parent) in world.entities_thread_local().ordered_iter_for::<::WriteAndParent<Pos>>(){
+        pos.0 = Rc::new(*pos.0 + parent.map(|p| *p.0).unwrap_or(0.));
+    }
+    let xs = world.entities_thread_local()
+        .traverse_for::<::ReadHierarchical<Pos>>(::Traversal::breadth_first().subtree(&e2))
+        .map(|(depth, n)| (depth, *n.data.0))
+        .collect::<Vec<_>>();
+    assert_eq!(xs, vec![(0, 3.), (1, 6.)]);
+}
+
+#[test]
+fn hierarchy_entity_navigation() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::Forest<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Health(u32);
+
+    impl ::Component for Health{
+        type Storage = ::DenseVec<Health>;
+        fn type_name() -> String{
+            "Health".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    world.register::<Health>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .add(Health(100))
+        .build();
+    let e2 = world.create_entity()
+        .add_child(&e1, Pos{x: 2., y: 2.})
+        .build();
+    let e3 = world.create_entity()
+        .add_child(&e1, Pos{x: 3., y: 3.})
+        .build();
+    let e4 = world.create_entity()
+        .add_child(&e2, Pos{x: 4., y: 4.})
+        .build();
+    let e5 = world.create_entity()
+        .add(Health(50))
+        .build();
+
+    let entities = world.entities();
+    assert_eq!(entities.parent_of::<Pos>(&e4), Some(e2));
+    assert_eq!(entities.parent_of::<Pos>(&e1), None);
+    assert_eq!(entities.parent_of::<Pos>(&e5), None);
+    assert_eq!(entities.children_of::<Pos>(&e1), vec![e2, e3]);
+    assert_eq!(entities.ancestors_of::<Pos>(&e4), vec![e2, e1]);
+    assert_eq!(entities.descendants_of::<Pos>(&e1), vec![e2, e4, e3]);
+    assert!(entities.descendants_of::<Pos>(&e5).is_empty());
+
+    let root = entities.ancestors_of::<Pos>(&e4).pop().unwrap();
+    assert_eq!(*entities.component_for::<Health>(&root).unwrap(), Health(100));
+}
+
+#[test]
+fn independent_hierarchies() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Style(u32);
+
+    impl ::Component for Style{
+        type Storage = ::DenseVec<Style>;
+        fn type_name() -> String{
+            "Style".to_owned()
+        }
+    }
+
+    struct UiTree;
+    impl ::Hierarchy for UiTree{
+        fn type_name() -> String{
+            "UiTree".to_owned()
+        }
+    }
+
+    struct Attachment;
+    impl ::Hierarchy for Attachment{
+        fn type_name() -> String{
+            "Attachment".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Style>();
+    world.register_hierarchy::<UiTree>();
+    world.register_hierarchy::<Attachment>();
+    let e1 = world.create_entity()
+        .add(Style(1))
+        .build();
+    let e2 = world.create_entity()
+        .add(Style(2))
+        .build();
+    let e3 = world.create_entity()
+        .add(Style(3))
+        .build();
+    world.set_parent_in::<UiTree>(&e3, &e2);
+    world.set_parent_in::<UiTree>(&e2, &e1);
+    world.set_parent_in::<Attachment>(&e1, &e3);
+
+    for (style, parent) in world.entities().ordered_iter_for::<::WriteAndParentIn<UiTree, Style>>(){
+        style.0 += parent.map(|parent| parent.0).unwrap_or(0);
+    }
+
+    let ui = world.entities()
+        .ordered_iter_for::<::ReadAndParentIn<UiTree, Style>>()
+        .map(|(style, parent)| (style.0, parent.map(|parent| parent.0)))
+        .collect::<Vec<_>>();
+    assert_eq!(ui, vec![(1, None), (3, Some(1)), (6, Some(3))]);
+
+    let attached = world.entities()
+        .ordered_iter_for::<::OrderedBy<Attachment, ::Read<Style>>>()
+        .map(|style| style.0)
+        .collect::<Vec<_>>();
+    assert_eq!(attached, vec![6, 1]);
+    assert_eq!(world.entities().parent_in::<Attachment>(&e1), Some(e3));
+    assert_eq!(world.entities().parent_in::<UiTree>(&e1), None);
+
+    world.remove_entity(&e2);
+    assert_eq!(world.entities().parent_in::<UiTree>(&e3), Some(e1));
+    let ui = world.entities()
+        .ordered_iter_for::<::OrderedBy<UiTree, ::Read<Style>>>()
+        .map(|style| style.0)
+        .collect::<Vec<_>>();
+    assert_eq!(ui, vec![1, 6]);
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_config_layout() {
+    use std::fs;
+
+    let root = ::tempfile::tempdir().unwrap();
+    let target = root.path().join("build");
+    let sources = root.path().join("crates");
+    let systems = sources.join("systems");
+    fs::create_dir_all(sources.join("physics")).unwrap();
+    fs::create_dir_all(systems.join("render")).unwrap();
+
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(&target)
+        .profile("release")
+        .source_roots(vec![sources.clone(), systems.clone()]);
+    assert_eq!(config.library_path("physics"), target.join("release").join("libphysics.so"));
+    assert_eq!(config.source_path("physics"), Some(sources.join("physics")));
+    assert_eq!(config.source_path("render"), Some(systems.join("render")));
+    assert_eq!(config.source_path("audio"), None);
+
+    let config = config
+        .profile("dev")
+        .library_naming(::LibraryNaming::Custom{ prefix: "".to_owned(), suffix: ".dll".to_owned() });
+    assert_eq!(config.library_path("physics"), target.join("debug").join("physics.dll"));
+
+    let config = config.profile("profiling");
+    assert_eq!(config.library_path("physics"), target.join("profiling").join("physics.dll"));
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_reload_events() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = ::tempfile::tempdir().unwrap();
+    let build = root.path().join("build.sh");
+    fs::write(&build, "#!/bin/sh\n\
+        echo 'error[E0425]: cannot find value `x` in this scope' >&2\n\
+        echo ' --> physics/src/lib.rs:3:5' >&2\n\
+        echo 'error: aborting due to previous error' >&2\n\
+        exit 1\n").unwrap();
+    fs::set_permissions(&build, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::CargoBuilder::with_command(&build));
+    let mut world = ::World::with_dynamic_systems_config(config);
+    match world.preload_dynamic_libraries(&["physics"]) {
+        Err(::Error::BuildFailed{ ref libraries, .. }) => assert_eq!(libraries, &vec!["physics".to_owned()]),
+        other => panic!("Expected build failure, got {:?}", other),
+    }
+
+    let diagnostic = ::Diagnostic{
+        level: ::DiagnosticLevel::Error,
+        message: "cannot find value `x` in this scope".to_owned(),
+        file: Some("physics/src/lib.rs".into()),
+        line: Some(3),
+        column: Some(5),
+    };
+    let events = world.reload_events();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0], ::ReloadEvent::Started{ libraries: vec!["physics".to_owned()] });
+    match events[1] {
+        ::ReloadEvent::Failed{ ref libraries, ref diagnostics, .. } => {
+            assert_eq!(libraries, &vec!["physics".to_owned()]);
+            assert_eq!(diagnostics, &vec![diagnostic]);
+        }
+        ref other => panic!("Expected build failure, got {:?}", other),
+    }
+    assert!(world.reload_events().is_empty());
+
+    world.add_resource(Vec::<::ReloadEvent>::new());
+    assert!(world.preload_dynamic_libraries(&["physics"]).is_err());
+    world.run_once();
+    assert_eq!(world.resource::<Vec<::ReloadEvent>>().unwrap().len(), 2);
+}
+
+// Compiles source into a dynamic library named lib<name>.so in dir using the
+// rustc in PATH. Fixtures can't depend on rinecs so they declare their own
+// versions of the types systems receive, they only hold a reference to the
+// world so they have the same layout. The abi header of the systems is
+// computed here and exported from rinecs_abi_header, fixtures never access
+// components so they don't need rinecs_abi
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+fn build_fixture_library(dir: &::std::path::Path, name: &str, systems: &[(&str, ::SystemKind)], source: &str) -> ::std::path::PathBuf {
+    use std::fs;
+    use std::process::Command;
+
+    let mut prelude = "
+        #![allow(unused)]
+        use std::os::raw::c_void;
+        pub struct Entities<'a>(&'a u8);
+        pub struct Resources<'a>(&'a u8);
+        pub struct EntitiesThreadLocal<'a>(&'a u8);
+        pub struct ResourcesThreadLocal<'a>(&'a u8);
+    ".to_owned();
+    if !systems.is_empty() {
+        let headers = systems.iter().map(|&(system, kind)| {
+            let header = ::AbiHeader::new(kind, &[]);
+            format!("b\"{}\" => AbiHeader{{ version_hash: {}, kind: {}, components: 0, layout_hash: {} }},",
+                system, header.version_hash, header.kind, header.layout_hash)
+        }).collect::<Vec<_>>();
+        prelude += &format!("
+            #[repr(C)]
+            pub struct AbiHeader{{ version_hash: u64, kind: u32, components: u32, layout_hash: u64 }}
+
+            #[no_mangle]
+            pub extern \"C\" fn rinecs_abi_header(system: *const u8, len: usize, header: *mut AbiHeader) -> bool{{
+                let system = unsafe{{ std::slice::from_raw_parts(system, len) }};
+                let found = match system {{
+                    {}
+                    _ => return false,
+                }};
+                unsafe{{ *header = found; }}
+                true
+            }}
+        ", headers.join("\n"));
+    }
+    fs::create_dir_all(dir).unwrap();
+    let source_path = dir.join(format!("{}.rs", name));
+    fs::write(&source_path, prelude + source).unwrap();
+    let library_path = dir.join(format!("lib{}.so", name));
+    let output = Command::new("rustc")
+        .arg("--crate-type").arg("cdylib")
+        .arg("--crate-name").arg(name)
+        .arg("-o").arg(&library_path)
+        .arg(&source_path)
+        .output()
+        .expect("Couldn't run rustc to build the fixture library");
+    assert!(output.status.success(), "Fixture {} failed to build: {}", name, String::from_utf8_lossy(&output.stderr));
+    library_path
+}
+
+// A thread local with data system that stores its version in the u32 its
+// data points to
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+fn versioned_system_source(version: u32) -> String {
+    format!("
+        #[no_mangle]
+        pub fn update(data: *mut c_void, _: EntitiesThreadLocal, _: ResourcesThreadLocal){{
+            unsafe{{ **(data as *mut *mut u32) = {}; }}
+        }}
+    ", version)
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_custom_builder() {
+    use std::fs;
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Stands in for a compiler by copying prebuilt files to the target dir
+    struct CopyBuilder{
+        fixtures: ::std::path::PathBuf,
+        built: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ::SystemBuilder for CopyBuilder{
+        fn build(&self, config: &::DynamicSystemsConfig, libraries: &[&str]) -> Result<(), ::BuildError>{
+            fs::create_dir_all(config.library_dir()).unwrap();
+            for library in libraries {
+                self.built.lock().unwrap().push((*library).to_owned());
+                let fixture = self.fixtures.join(config.library_naming.file_name(library));
+                fs::copy(&fixture, config.library_path(library)).map_err(|e| ::BuildError{
+                    diagnostics: vec![],
+                    output: format!("{}", e),
+                })?;
+            }
+            Ok(())
+        }
+    }
+
+    let root = ::tempfile::tempdir().unwrap();
+    let fixtures = root.path().join("fixtures");
+    fs::create_dir_all(&fixtures).unwrap();
+    let built = Arc::new(Mutex::new(vec![]));
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(CopyBuilder{ fixtures: fixtures.clone(), built: built.clone() });
+    let mut world = ::World::with_dynamic_systems_config(config);
+
+    assert!(world.preload_dynamic_libraries(&["physics", "render"]).is_err());
+    assert_eq!(*built.lock().unwrap(), vec!["physics".to_owned()]);
+    match world.reload_events()[1] {
+        ::ReloadEvent::Failed{ ref diagnostics, .. } => assert!(diagnostics.is_empty()),
+        ref other => panic!("Expected build failure, got {:?}", other),
+    }
+
+    // Changing the sources rebuilds the library through the builder and
+    // the new version is swapped in on a later frame
+    let sources = root.path().join("src");
+    fs::create_dir_all(sources.join("physics")).unwrap();
+    fs::write(sources.join("physics").join("lib.rs"), "// version 1").unwrap();
+    build_fixture_library(&fixtures, "physics", &[("update", ::SystemKind::ThreadLocalWithData)], &versioned_system_source(1));
+    built.lock().unwrap().clear();
+
+    let version = Box::new(Cell::new(0u32));
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .source_roots(vec![sources.clone()])
+        .reload_mode(::ReloadMode::Poll)
+        .builder(CopyBuilder{ fixtures: fixtures.clone(), built: built.clone() });
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.new_dynamic_system_with_data_thread_local("physics::update", &*version as *const Cell<u32>);
+    world.start_dynamic_systems_watch().unwrap();
+    world.run_once();
+    assert_eq!(version.get(), 1);
+    assert_eq!(*built.lock().unwrap(), vec!["physics".to_owned()]);
+    world.reload_events();
+
+    build_fixture_library(&fixtures, "physics", &[("update", ::SystemKind::ThreadLocalWithData)], &versioned_system_source(2));
+    fs::write(sources.join("physics").join("lib.rs"), "// version 2").unwrap();
+    let start = Instant::now();
+    while version.get() != 2 && start.elapsed() < Duration::from_secs(30) {
+        thread::sleep(Duration::from_millis(100));
+        world.run_once();
+    }
+    assert_eq!(version.get(), 2);
+    assert_eq!(*built.lock().unwrap(), vec!["physics".to_owned(), "physics".to_owned()]);
+    let events = world.reload_events();
+    assert!(events.contains(&::ReloadEvent::Succeeded{ libraries: vec!["physics".to_owned()] }));
+    assert!(events.contains(&::ReloadEvent::Reloaded{ library: "physics".to_owned() }));
+
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("empty_target"))
+        .builder(::Prebuilt);
+    let mut world = ::World::with_dynamic_systems_config(config);
+    assert!(world.preload_dynamic_libraries(&["physics"]).is_err());
+    assert_eq!(world.reload_events()[1], ::ReloadEvent::Succeeded{ libraries: vec!["physics".to_owned()] });
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_reload_hooks() {
+    use std::cell::Cell;
+
+    // update counts its runs and loads in the two u32 its data points to.
+    // on_unload hands the count over to migrate through the serialized state,
+    // tick has no data so its hooks would crash if they were called
+    let source = "
+        unsafe fn counter<'a>(data: *mut c_void) -> &'a mut [u32; 2]{
+            &mut **(data as *mut *mut [u32; 2])
+        }
+
+        #[no_mangle]
+        pub fn update(data: *mut c_void, _: EntitiesThreadLocal, _: ResourcesThreadLocal){
+            unsafe{ counter(data)[0] += 1; }
+        }
+
+        #[no_mangle]
+        pub fn update_on_unload(data: *mut c_void) -> Vec<u8>{
+            let counter = unsafe{ counter(data) };
+            let state = counter[0].to_le_bytes().to_vec();
+            counter[0] = 0;
+            state
+        }
+
+        #[no_mangle]
+        pub fn update_migrate(data: *mut c_void, state: Vec<u8>){
+            unsafe{ counter(data)[0] = u32::from_le_bytes([state[0], state[1], state[2], state[3]]); }
+        }
+
+        #[no_mangle]
+        pub fn update_on_load(data: *mut c_void){
+            unsafe{ counter(data)[1] += 1; }
+        }
+
+        #[no_mangle]
+        pub fn tick(_: EntitiesThreadLocal, _: ResourcesThreadLocal){}
+
+        #[no_mangle]
+        pub fn tick_on_unload(data: *mut c_void) -> Vec<u8>{
+            unsafe{ vec![*(data as *mut u8)] }
+        }
+
+        #[no_mangle]
+        pub fn tick_on_load(data: *mut c_void){
+            unsafe{ *(data as *mut u8) = 1; }
+        }
+    ";
+
+    let root = ::tempfile::tempdir().unwrap();
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::Prebuilt);
+    build_fixture_library(&config.library_dir(), "counter", &[
+        ("update", ::SystemKind::ThreadLocalWithData),
+        ("tick", ::SystemKind::ThreadLocal),
+    ], source);
+
+    let counter = Box::new([Cell::new(0u32), Cell::new(0u32)]);
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.new_dynamic_system_with_data_thread_local("counter::update", &*counter as *const [Cell<u32>; 2] as *mut [u32; 2]);
+    world.new_dynamic_system_thread_local("counter::tick");
+    world.run_once();
+    world.run_once();
+    world.run_once();
+    assert_eq!(counter[0].get(), 3);
+    assert_eq!(counter[1].get(), 0);
+
+    world.reload_dynamic_library("counter").unwrap();
+    world.run_once();
+    assert_eq!(counter[0].get(), 4);
+    assert_eq!(counter[1].get(), 1);
+    assert!(world.reload_events().contains(&::ReloadEvent::Reloaded{ library: "counter".to_owned() }));
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_refuse_unfingerprinted() {
+    use std::cell::Cell;
+
+    let root = ::tempfile::tempdir().unwrap();
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::Prebuilt);
+    let library_dir = config.library_dir();
+    build_fixture_library(&library_dir, "physics", &[("update", ::SystemKind::ThreadLocalWithData)], &versioned_system_source(1));
+
+    let version = Box::new(Cell::new(0u32));
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.new_dynamic_system_with_data_thread_local("physics::update", &*version as *const Cell<u32>);
+    world.run_once();
+    assert_eq!(version.get(), 1);
+    world.reload_events();
+
+    // A library without fingerprint or with a wrong one is never swapped in
+    build_fixture_library(&library_dir, "physics", &[], &versioned_system_source(2));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    assert_eq!(version.get(), 1);
+    let events = world.reload_events();
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        ::ReloadEvent::AbiMismatch{ ref system, .. } => assert_eq!(system, "update"),
+        ref other => panic!("Expected abi mismatch, got {:?}", other),
+    }
+
+    build_fixture_library(&library_dir, "physics", &[("update", ::SystemKind::ThreadLocal)], &versioned_system_source(3));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    assert_eq!(version.get(), 1);
+
+    build_fixture_library(&library_dir, "physics", &[("update", ::SystemKind::ThreadLocalWithData)], &versioned_system_source(4));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    assert_eq!(version.get(), 4);
+}
+
+#[test]
+fn dynamic_systems_abi_fingerprint() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::DenseVec<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Vel{
+        x: f64,
+    }
+
+    impl ::Component for Vel{
+        type Storage = ::DenseVec<Vel>;
+        fn type_name() -> String{
+            "Vel".to_owned()
+        }
+    }
+
+    fn update(_: ::Entities, _: ::Resources){}
+    fn spawn(data: &mut usize, _: ::EntitiesCreation, _: ::ResourcesThreadLocal){
+        *data += 1;
+    }
+
+    dynamic_systems!{
+        abi_test_update: Send = update [Pos, Vel];
+        abi_test_spawn: CreationWithData<usize> = spawn [];
+    }
+
+    let mut layouts = ::fxhash::FxHashMap::default();
+    layouts.insert("Pos".to_owned(), ::ComponentLayout::of::<Pos>());
+    layouts.insert("Vel".to_owned(), ::ComponentLayout::of::<Vel>());
+
+    let mut header = ::AbiHeader::default();
+    let system = "abi_test_update";
+    assert!(rinecs_abi_header(system.as_ptr(), system.len(), &mut header));
+    assert!(header.check(::SystemKind::Send).is_ok());
+    assert!(header.check(::SystemKind::ThreadLocal).is_err());
+    assert_eq!(header.components, 2);
+    let system = "missing";
+    assert!(!rinecs_abi_header(system.as_ptr(), system.len(), &mut header));
+    assert!(env!("RINECS_RUSTC_VERSION").starts_with("rustc "));
+    let mut old_version = header;
+    old_version.version_hash += 1;
+    assert!(old_version.check(::SystemKind::Send).is_err());
+
+    let fingerprint = rinecs_abi("abi_test_update").unwrap();
+    assert_eq!(fingerprint.layout_hash, header.layout_hash);
+    assert_eq!(fingerprint.components, vec!["Pos".to_owned(), "Vel".to_owned()]);
+    assert!(fingerprint.check(::SystemKind::Send, &layouts).is_ok());
+    assert!(fingerprint.check(::SystemKind::ThreadLocal, &layouts).is_err());
+    assert!(rinecs_abi("abi_test_spawn").unwrap().check(::SystemKind::CreationWithData, &layouts).is_ok());
+    assert!(rinecs_abi("missing").is_none());
+
+    let mut old_version = fingerprint.clone();
+    old_version.version = "0.0.0".to_owned();
+    assert!(old_version.check(::SystemKind::Send, &layouts).is_err());
+
+    layouts.insert("Vel".to_owned(), ::ComponentLayout{ name: "Vel".to_owned(), size: 4, align: 4 });
+    assert!(fingerprint.check(::SystemKind::Send, &layouts).is_err());
+    layouts.remove("Vel");
+    assert!(fingerprint.check(::SystemKind::Send, &layouts).is_err());
+
+    let manifest = rinecs_manifest();
+    assert_eq!(manifest, vec![
+        ::SystemManifest{
+            name: "abi_test_update".to_owned(),
+            kind: ::SystemKind::Send,
+            order: 0,
+            components: vec!["Pos".to_owned(), "Vel".to_owned()],
+        },
+        ::SystemManifest{
+            name: "abi_test_spawn".to_owned(),
+            kind: ::SystemKind::CreationWithData,
+            order: 1,
+            components: vec![],
+        },
+    ]);
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_poll_mode() {
+    let root = ::tempfile::tempdir().unwrap();
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::Prebuilt)
+        .reload_mode(::ReloadMode::Poll);
+    let mut world = ::World::with_dynamic_systems_config(config);
+
+    // Nothing is loaded until a dynamic system is used
+    world.poll_dynamic_systems();
+    world.run_once();
+    assert!(world.reload_events().is_empty());
+
+    // Prebuilt doesn't fail but there's no library to load
+    match world.preload_dynamic_libraries(&["physics"]) {
+        Err(::Error::LibraryLoad{..}) => (),
+        other => panic!("Expected library load error, got {:?}", other),
+    }
+    world.add_resource(Vec::<::ReloadEvent>::new());
+    world.run_once();
+    world.poll_dynamic_systems();
+    assert_eq!(*world.resource::<Vec<::ReloadEvent>>().unwrap(), vec![
+        ::ReloadEvent::Started{ libraries: vec!["physics".to_owned()] },
+        ::ReloadEvent::Succeeded{ libraries: vec!["physics".to_owned()] },
+    ]);
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_reload_between_frames() {
+    let root = ::tempfile::tempdir().unwrap();
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::Prebuilt);
+    build_fixture_library(&config.library_dir(), "physics", &[], "");
+
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.add_resource(Vec::<::ReloadEvent>::new());
+    world.preload_dynamic_libraries(&["physics"]).unwrap();
+    assert!(world.reload_dynamic_library("render").is_err());
+
+    world.run_once();
+    world.resource_mut::<Vec<::ReloadEvent>>().unwrap().clear();
+
+    world.reload_dynamic_library("physics").unwrap();
+    world.reload_dynamic_library("physics").unwrap();
+    assert!(world.reload_events().is_empty());
+
+    world.run_once();
+    assert_eq!(*world.resource::<Vec<::ReloadEvent>>().unwrap(), vec![
+        ::ReloadEvent::Reloaded{ library: "physics".to_owned() }
+    ]);
+
+    world.run_once();
+    assert_eq!(world.resource::<Vec<::ReloadEvent>>().unwrap().len(), 1);
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_reload_while_running() {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    // first and second store their version in the probe both of them get
+    // as data. first also calls on_run so the host can change the library
+    // while the frame is running
+    fn source(version: u32) -> String {
+        format!("
+            #[repr(C)]
+            pub struct Probe{{ versions: [u32; 2], on_run: extern \"C\" fn(*mut Probe) }}
+
+            #[no_mangle]
+            pub fn first(data: *mut c_void, _: EntitiesThreadLocal, _: ResourcesThreadLocal){{
+                unsafe{{
+                    let probe = *(data as *mut *mut Probe);
+                    (*probe).versions[0] = {0};
+                    ((*probe).on_run)(probe);
+                }}
+            }}
+
+            #[no_mangle]
+            pub fn second(data: *mut c_void, _: EntitiesThreadLocal, _: ResourcesThreadLocal){{
+                unsafe{{ (**(data as *mut *mut Probe)).versions[1] = {0}; }}
+            }}
+        ", version)
+    }
+
+    #[repr(C)]
+    struct Probe{
+        versions: [u32; 2],
+        on_run: extern "C" fn(*mut Probe),
+        replace: Option<(PathBuf, PathBuf)>,
+    }
+
+    // Gives the updater thread well over the watcher delay to queue the
+    // reload before the frame continues
+    extern "C" fn replace_library(probe: *mut Probe){
+        let probe = unsafe{ &mut *probe };
+        if let Some((from, to)) = probe.replace.take() {
+            fs::copy(from, to).unwrap();
+            thread::sleep(Duration::from_secs(3));
+        }
+    }
+
+    let root = ::tempfile::tempdir().unwrap();
+    let systems = [("first", ::SystemKind::ThreadLocalWithData), ("second", ::SystemKind::ThreadLocalWithData)];
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .reload_mode(::ReloadMode::Background)
+        .builder(::Prebuilt);
+    let library_path = config.library_path("physics");
+    build_fixture_library(&config.library_dir(), "physics", &systems, &source(1));
+    let version_2 = build_fixture_library(&root.path().join("fixtures"), "physics", &systems, &source(2));
+
+    let mut probe = Box::new(Probe{
+        versions: [0, 0],
+        on_run: replace_library,
+        replace: None,
+    });
+    let probe_ptr = &mut *probe as *mut Probe;
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.new_dynamic_system_with_data_thread_local("physics::first", probe_ptr);
+    world.new_dynamic_system_with_data_thread_local("physics::second", probe_ptr);
+    world.start_dynamic_systems_watch().unwrap();
+    world.run_once();
+    assert_eq!(unsafe{ (*probe_ptr).versions }, [1, 1]);
+
+    // The library changes on disk while first runs, second still runs the
+    // old code in the same frame and both switch on the next one
+    unsafe{ (*probe_ptr).replace = Some((version_2, library_path)) };
+    world.run_once();
+    assert_eq!(unsafe{ (*probe_ptr).versions }, [1, 1]);
+    assert!(unsafe{ (*probe_ptr).replace.is_none() });
+
+    world.run_once();
+    assert_eq!(unsafe{ (*probe_ptr).versions }, [2, 2]);
+    assert!(world.reload_events().contains(&::ReloadEvent::Reloaded{ library: "physics".to_owned() }));
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_manifest_removal() {
+    // The manifest is read as rust types so the fixture declares the same
+    // SystemManifest and SystemKind as rinecs, it has to be built with the
+    // same rustc as the host like any real library. Every system appends
+    // it's name to the runs file each time it runs
+    fn source(systems: &[&str], version_hash: u64, runs: &::std::path::Path) -> String {
+        let systems_source = systems.iter()
+            .map(|system| format!("
+                #[no_mangle]
+                pub fn {0}(_: EntitiesThreadLocal, _: ResourcesThreadLocal){{
+                    use std::io::Write;
+                    let mut runs = ::std::fs::OpenOptions::new().create(true).append(true).open({1:?}).unwrap();
+                    writeln!(runs, \"{0}\").unwrap();
+                }}
+            ", system, runs))
+            .collect::<String>();
+        let manifest = systems.iter()
+            .map(|system| format!("
+                SystemManifest{{ name: \"{}\".to_owned(), kind: SystemKind::ThreadLocal, order: 0, components: vec![] }},
+            ", system))
+            .collect::<String>();
+        format!("
+            #[repr(u32)]
+            pub enum SystemKind{{ Send, ThreadLocal, Creation, SendWithData, ThreadLocalWithData, CreationWithData }}
+
+            pub struct SystemManifest{{ pub name: String, pub kind: SystemKind, pub order: i32, pub components: Vec<String> }}
+
+            #[no_mangle]
+            pub extern \"C\" fn rinecs_version_hash() -> u64{{ {} }}
+
+            #[no_mangle]
+            pub fn rinecs_manifest() -> Vec<SystemManifest>{{ vec![{}] }}
+
+            {}
+        ", version_hash, manifest, systems_source)
+    }
+
+    let both = [("first", ::SystemKind::ThreadLocal), ("second", ::SystemKind::ThreadLocal)];
+    let first = [("first", ::SystemKind::ThreadLocal)];
+    let root = ::tempfile::tempdir().unwrap();
+    let runs = root.path().join("runs");
+    let second_runs = || ::std::fs::read_to_string(&runs)
+        .unwrap_or(String::new())
+        .lines()
+        .filter(|system| *system == "second")
+        .count();
+
+    // Systems only added from the manifest go away with it
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("manifest"))
+        .builder(::Prebuilt);
+    let library_dir = config.library_dir();
+    build_fixture_library(&library_dir, "physics", &both, &source(&["first", "second"], ::version_hash(), &runs));
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.load_dynamic_library("physics").unwrap();
+    world.run_once();
+    world.reload_events();
+    assert_eq!(second_runs(), 1);
+
+    build_fixture_library(&library_dir, "physics", &first, &source(&["first"], ::version_hash(), &runs));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    world.run_once();
+    let events = world.reload_events();
+    assert!(events.contains(&::ReloadEvent::Reloaded{ library: "physics".to_owned() }));
+    assert!(events.contains(&::ReloadEvent::Removed{ library: "physics".to_owned(), system: "second".to_owned() }));
+    let removed_runs = second_runs();
+    world.run_once();
+    world.run_once();
+    assert_eq!(second_runs(), removed_runs);
+
+    // A system also added by path keeps the library from being swapped for
+    // one that doesn't export it anymore
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("pinned"))
+        .builder(::Prebuilt);
+    let library_dir = config.library_dir();
+    build_fixture_library(&library_dir, "physics", &both, &source(&["first", "second"], ::version_hash(), &runs));
+    let mut world = ::World::with_dynamic_systems_config(config);
+    world.load_dynamic_library("physics").unwrap();
+    world.new_dynamic_system_thread_local("physics::second");
+    world.run_once();
+    world.reload_events();
+
+    build_fixture_library(&library_dir, "physics", &first, &source(&["first"], ::version_hash(), &runs));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    assert_eq!(world.reload_events(), vec![
+        ::ReloadEvent::SymbolMissing{ library: "physics".to_owned(), system: "second".to_owned() }
+    ]);
+
+    // The manifest of a library built with another rinecs is never read
+    build_fixture_library(&library_dir, "physics", &both, &source(&["first", "second"], ::version_hash().wrapping_add(1), &runs));
+    world.reload_dynamic_library("physics").unwrap();
+    world.run_once();
+    let events = world.reload_events();
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        ::ReloadEvent::Failed{ ref libraries, .. } => assert_eq!(*libraries, vec!["physics".to_owned()]),
+        ref other => panic!("Expected failed reload, got {:?}", other),
+    }
+}
+
+#[cfg(all(feature="dynamic_systems", target_os="linux"))]
+#[test]
+fn dynamic_systems_manifest_unregistered_component() {
+    let source = format!("
+        #[repr(u32)]
+        pub enum SystemKind{{ Send, ThreadLocal, Creation, SendWithData, ThreadLocalWithData, CreationWithData }}
+
+        pub struct SystemManifest{{ pub name: String, pub kind: SystemKind, pub order: i32, pub components: Vec<String> }}
+
+        #[no_mangle]
+        pub extern \"C\" fn rinecs_version_hash() -> u64{{ {} }}
+
+        #[no_mangle]
+        pub fn rinecs_manifest() -> Vec<SystemManifest>{{
+            vec![SystemManifest{{ name: \"update\".to_owned(), kind: SystemKind::ThreadLocal, order: 0, components: vec![\"Pos\".to_owned()] }}]
+        }}
+
+        #[no_mangle]
+        pub fn update(_: EntitiesThreadLocal, _: ResourcesThreadLocal){{}}
+    ", ::version_hash());
+
+    let root = ::tempfile::tempdir().unwrap();
+    let config = ::DynamicSystemsConfig::new()
+        .target_dir(root.path().join("target"))
+        .builder(::Prebuilt);
+    build_fixture_library(&config.library_dir(), "physics", &[("update", ::SystemKind::ThreadLocal)], &source);
+    let mut world = ::World::with_dynamic_systems_config(config);
+    assert_eq!(world.load_dynamic_library("physics"), Err(::Error::SystemComponentNotRegistered{
+        library: "physics".to_owned(),
+        system: "update".to_owned(),
+        component: "Pos".to_owned(),
+    }));
+}
+
+#[test]
+fn try_methods_return_errors() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::DenseVec<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Vel{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Vel{
+        type Storage = ::DenseVec<Vel>;
+        fn type_name() -> String{
+            "Vel".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    assert!(world.try_register::<Pos>().is_ok());
+    assert_eq!(world.try_register::<Pos>(), Err(::Error::AlreadyRegistered("Pos".to_owned())));
+
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .build();
+
+    assert_eq!(world.try_add_component_to(&e1, Vel{x: 1., y: 1.}), Err(::Error::NotRegistered("Vel".to_owned())));
+    assert!(world.entities().try_iter_for::<::Read<Pos>>().is_ok());
+    assert_eq!(
+        world.entities().try_iter_for::<(::Read<Pos>, ::Read<Vel>)>().err(),
+        Some(::Error::NotRegistered("Vel".to_owned())));
+
+    world.register::<Vel>();
+    assert!(world.try_add_component_to(&e1, Vel{x: 1., y: 1.}).is_ok());
+    assert_eq!(world.entities().try_iter_for::<(::Read<Pos>, ::Read<Vel>)>().unwrap().count(), 1);
+
+    assert!(world.try_remove_component_from::<Vel>(&e1).is_ok());
+    assert_eq!(
+        world.try_remove_component_from::<Vel>(&e1),
+        Err(::Error::MissingComponent{ component: "Vel".to_owned(), entity: e1.guid() }));
+    assert_eq!(world.entities().iter_for::<(::Read<Pos>, ::Read<Vel>)>().count(), 0);
+
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Node(u32);
+
+    impl ::Component for Node{
+        type Storage = ::Forest<Node>;
+        fn type_name() -> String{
+            "Node".to_owned()
+        }
+    }
+
+    struct Tree;
+
+    impl ::Hierarchy for Tree{
+        fn type_name() -> String{
+            "Tree".to_owned()
+        }
+    }
+
+    assert_eq!(world.try_add_component_to_thread_local(&e1, Node(0)), Err(::Error::NotRegistered("Node".to_owned())));
+    assert_eq!(
+        world.try_remove_component_from_hierarchy::<Node>(&e1, ::ChildrenMode::ToRoots),
+        Err(::Error::NotRegistered("Node".to_owned())));
+    assert!(world.try_register_thread_local::<Node>().is_ok());
+    assert_eq!(world.try_register_thread_local::<Node>(), Err(::Error::AlreadyRegistered("Node".to_owned())));
+    assert_eq!(world.try_register::<Node>(), Err(::Error::AlreadyRegistered("Node".to_owned())));
+    assert_eq!(
+        world.try_remove_component_from_hierarchy::<Node>(&e1, ::ChildrenMode::ToRoots),
+        Err(::Error::MissingComponent{ component: "Node".to_owned(), entity: e1.guid() }));
+    assert!(world.try_add_component_to_thread_local(&e1, Node(0)).is_ok());
+    assert!(world.try_remove_component_from_hierarchy::<Node>(&e1, ::ChildrenMode::ToRoots).is_ok());
+
+    assert!(world.try_register_hierarchy::<Tree>().is_ok());
+    assert_eq!(world.try_register_hierarchy::<Tree>(), Err(::Error::AlreadyRegistered("Tree".to_owned())));
+}
+
+#[test]
+fn gpu_storage_dirty_ranges() {
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    #[repr(C)]
+    struct Pos{
+        x: f32,
+        y: f32,
+    }
+
+    impl ::Component for Pos{
+        type Storage = ::GpuStorage<Pos>;
+        fn type_name() -> String{
+            "Pos".to_owned()
+        }
+    }
+
+    impl ::GpuComponent for Pos{}
+    unsafe impl ::Pod for Pos{}
+
+    let mut world = ::World::new();
+    world.register::<Pos>();
+    let mut all = vec![];
+    for i in 0..5 {
+        all.push(world.create_entity()
+            .add(Pos{x: i as f32, y: i as f32})
+            .build());
+    }
+
+    {
+        let entities = world.entities();
+        {
+            let storage = entities.storage_for::<Pos>();
+            assert_eq!(storage.dirty_ranges(), vec![0..5]);
+            assert_eq!(storage.as_bytes().len(), 5 * ::std::mem::size_of::<Pos>());
+        }
+        entities.clear_dirty_for::<Pos>();
+        assert!(!entities.storage_for::<Pos>().is_dirty());
+    }
+
+    {
+        let entities = world.entities();
+        entities.component_for_mut::<Pos>(&all[1]).unwrap().x = 10.;
+        entities.component_for_mut::<Pos>(&all[3]).unwrap().x = 30.;
+        let storage = entities.storage_for::<Pos>();
+        assert_eq!(storage.dirty_ranges(), vec![1..2, 3..4]);
+        assert_eq!(storage.as_slice()[3], Pos{x: 30., y: 3.});
+    }
+
+    world.entities().clear_dirty_for::<Pos>();
+    world.remove_entity(&all[1]);
+    {
+        let entities = world.entities();
+        assert_eq!(entities.storage_for::<Pos>().dirty_ranges(), vec![1..2]);
+        assert!(entities.storage_for::<Pos>().is_dirty());
+        assert_eq!(*entities.component_for::<Pos>(&all[4]).unwrap(), Pos{x: 4., y: 4.});
+        entities.clear_dirty_for::<Pos>();
+        for pos in entities.iter_for::<::Write<Pos>>() {
+            pos.y += 1.;
+        }
+        assert_eq!(entities.storage_for::<Pos>().dirty_ranges(), vec![0..4]);
+        entities.clear_dirty_for::<Pos>();
+        assert!(!entities.storage_for::<Pos>().is_dirty());
+    }
+}
+
+#[test]
+fn oneton_hierarchy_ordered_iteration(){
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Bone(u32);
+    impl ::Component for Bone{
+        type Storage = ::OneToNForest<Bone>;
+        fn type_name() -> String{
+            "Bone".to_owned()
+        }
+    }
+    impl ::HierarchicalOneToNComponent for Bone{}
+
+    let mut world = ::World::new();
+    world.register::<Bone>();
+
+    let mut skeleton = world.create_entity();
+    {
+        let mut bones = skeleton.add_hierarchy::<Bone>();
+        assert!(bones.hierarchy().is_none());
+        let root = bones.new_node(Bone(0));
+        let child1 = bones.append_child(root, Bone(1));
+        bones.append_child(child1, Bone(2));
+        bones.append_child(root, Bone(3));
+        let built = bones.hierarchy_and_parent().unwrap()
+            .map(|(bone, parent)| (*bone, parent.map(|parent| *parent)))
+            .collect::<Vec<_>>();
+        assert_eq!(built[2], (Bone(2), Some(Bone(1))));
+        for (bone, _) in bones.hierarchy_and_parent_mut().unwrap(){
+            bone.0 *= 2;
+        }
+        assert_eq!(bones.hierarchy().unwrap().count(), 4);
+    }
+    skeleton.build();
+
+    {
+        let entities = world.entities();
+        let mut hierarchies = entities.ordered_iter_for::<::ReadHierarchyAndParent<Bone>>();
+        let bones = hierarchies.next().unwrap()
+            .map(|(bone, parent)| (*bone, parent.map(|parent| *parent)))
+            .collect::<Vec<_>>();
+        assert_eq!(bones, vec![
+            (Bone(0), None),
+            (Bone(2), Some(Bone(0))),
+            (Bone(4), Some(Bone(2))),
+            (Bone(6), Some(Bone(0))),
+        ]);
+        assert!(hierarchies.next().is_none());
+    }
+
+    for hierarchy in world.entities().ordered_iter_for::<::WriteHierarchyAndParent<Bone>>(){
+        for (bone, parent) in hierarchy{
+            bone.0 += parent.map(|parent| parent.0).unwrap_or(0);
+        }
+    }
+
+    let bones = world.entities().ordered_iter_for::<::ReadHierarchy<Bone>>()
+        .next().unwrap()
+        .map(|node| node.data.0)
+        .collect::<Vec<_>>();
+    assert_eq!(bones, vec![0, 2, 6, 6]);
+}
+
+#[test]
+fn modify_one_to_n_slices(){
+    #[derive(Debug,PartialEq,Copy,Clone)]
+    struct Item(u32);
+
+    impl ::Component for Item{
+        type Storage = ::DenseOneToNVec<Item>;
+        fn type_name() -> String{
+            "Item".to_owned()
+        }
+    }
+
+    impl ::OneToNComponent for Item{}
+
+    let mut world = ::World::new();
+    world.register::<Item>();
+    let e1 = world.create_entity()
+        .add_slice(&[Item(1)])
+        .build();
+    let e2 = world.create_entity()
+        .add_slice(&[Item(2), Item(3)])
+        .build();
+    let e3 = world.create_entity()
+        .add_slice(&[Item(4), Item(5), Item(6)])
+        .build();
+    let e4 = world.create_entity().build();
+
+    world.push_component_to(&e2, Item(7));
+    assert_eq!(world.remove_component_at::<Item>(&e3, 1), Item(5));
+    world.push_component_to(&e4, Item(8));
+
+    {
+        let entities = world.entities();
+        entities.truncate_slice_component_of::<Item>(&e3, 1);
+        entities.replace_slice_component_of(&e1, &[Item(9), Item(10)]);
+        for items in entities.iter_for::<::WriteOneToN<Item>>(){
+            for item in items{
+                item.0 *= 10;
+            }
+        }
+    }
+
+    let entities = world.entities();
+    assert_eq!(entities.iter_for::<::ReadOneToN<Item>>().count(), 4);
+    let items = entities.query_for::<::ReadOneToN<Item>>();
+    assert_eq!(items.get(&e1), Some(&[Item(90), Item(100)][..]));
+    assert_eq!(items.get(&e2), Some(&[Item(20), Item(30), Item(70)][..]));
+    assert_eq!(items.get(&e3), Some(&[Item(40)][..]));
+    assert_eq!(items.get(&e4), Some(&[Item(80)][..]));
+}
+
+#[test]
+fn one_to_n_compaction(){
+    #[derive(Debug,PartialEq,Clone)]
+    struct Item(String);
+
+    impl ::Component for Item{
+        type Storage = ::DenseOneToNVec<Item>;
+        fn type_name() -> String{
+            "Item".to_owned()
+        }
+    }
+
+    impl ::OneToNComponent for Item{}
+
+    let mut world = ::World::new();
+    world.register::<Item>();
+    let entities = (0..10).map(|i| world.create_entity()
+        .add_slice(&[Item(i.to_string())])
+        .build()
+    ).collect::<Vec<_>>();
+
+    world.entities().set_compaction_threshold_for::<Item>(1.);
+
+    // growing the first entity moves it's chunk to the end of the buffer
+    for i in 0..20 {
+        world.push_component_to(&entities[0], Item(format!("0.{}", i)));
+    }
+    for e in &entities[5..] {
+        world.remove_entity(e);
+    }
+    assert!(world.entities().storage_for::<Item>().unused() > 0);
+
+    world.entities().compact_for::<Item>();
+    assert_eq!(world.entities().storage_for::<Item>().unused(), 0);
+
+    let entities_ref = world.entities();
+    let items = entities_ref.query_for::<::ReadOneToN<Item>>();
+    let first = items.get(&entities[0]).unwrap();
+    assert_eq!(first.len(), 21);
+    assert_eq!(first[0], Item("0".to_owned()));
+    assert_eq!(first[20], Item("0.19".to_owned()));
+    for (i, e) in entities[1..5].iter().enumerate() {
+        assert_eq!(items.get(e), Some(&[Item((i + 1).to_string())][..]));
+    }
+    assert_eq!(entities_ref.iter_for::<::ReadOneToN<Item>>().count(), 5);
+}
+
+#[test]
+fn propagate_hierarchy(){
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Local(i32);
+    impl ::Component for Local{
+        type Storage = ::Forest<Local>;
+        fn type_name() -> String{
+            "Local".to_owned()
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Global(i32);
+    impl ::Component for Global{
+        type Storage = ::DenseVec<Global>;
+        fn type_name() -> String{
+            "Global".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Local>();
+    world.register::<Global>();
+
+    let root1 = world.create_entity()
+        .add(Local(1))
+        .add(Global(0))
+        .build();
+    let child1 = world.create_entity()
+        .add_child(&root1, Local(2))
+        .add(Global(0))
+        .build();
+    let grandchild1 = world.create_entity()
+        .add_child(&child1, Local(3))
+        .add(Global(0))
+        .build();
+    let root2 = world.create_entity()
+        .add(Local(10))
+        .add(Global(0))
+        .build();
+    let child2 = world.create_entity()
+        .add_child(&root2, Local(20))
+        .add(Global(0))
+        .build();
+
+    let combine = |parent: Option<&Global>, local: &Local| Global(parent.map(|p| p.0).unwrap_or(0) + local.0);
+    world.propagate_hierarchy::<Local, Global, _>(combine);
+
+    {
+        let entities = world.entities();
+        assert_eq!(**entities.component_for::<Global>(&root1).unwrap(), Global(1));
+        assert_eq!(**entities.component_for::<Global>(&child1).unwrap(), Global(3));
+        assert_eq!(**entities.component_for::<Global>(&grandchild1).unwrap(), Global(6));
+        assert_eq!(**entities.component_for::<Global>(&root2).unwrap(), Global(10));
+        assert_eq!(**entities.component_for::<Global>(&child2).unwrap(), Global(30));
+
+        // only subtrees with changes are updated
+        entities.component_for_mut::<Global>(&child2).unwrap().0 = -1;
+        entities.component_for_mut::<Local>(&child1).unwrap().0 = 5;
+    }
+    world.propagate_hierarchy::<Local, Global, _>(combine);
+
+    let entities = world.entities();
+    assert_eq!(**entities.component_for::<Global>(&root1).unwrap(), Global(1));
+    assert_eq!(**entities.component_for::<Global>(&child1).unwrap(), Global(6));
+    assert_eq!(**entities.component_for::<Global>(&grandchild1).unwrap(), Global(9));
+    assert_eq!(**entities.component_for::<Global>(&child2).unwrap(), Global(-1));
+}
+
+#[test]
+fn parallel_ordered_hierarchy(){
+    use std::sync::Mutex;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Depth{
+        id: usize,
+        parent: Option<usize>,
+        depth: u32,
+    }
+    impl ::Component for Depth{
+        type Storage = ::Forest<Depth>;
+        fn type_name() -> String{
+            "Depth".to_owned()
+        }
+    }
+
+    let mut world = ::World::new();
+    world.register::<Depth>();
+
+    // 8 trees, every node with 2 children down to depth 4
+    let mut nodes = vec![];
+    for _ in 0..8 {
+        let id = nodes.len();
+        let root = world.create_entity()
+            .add(Depth{id, parent: None, depth: 0})
+            .build();
+        nodes.push((root, 0));
+        let mut level = vec![(root, id)];
+        for depth in 1..5 {
+            let mut next = vec![];
+            for &(parent, parent_id) in level.iter() {
+                for _ in 0..2 {
+                    let id = nodes.len();
+                    let child = world.create_entity()
+                        .add_child(&parent, Depth{id, parent: Some(parent_id), depth: 0})
+                        .build();
+                    nodes.push((child, depth));
+                    next.push((child, id));
+                }
+            }
+            level = next;
+        }
+    }
+
+    // WriteHierarchical only hands out the node so the depth of the parents
+    // is looked up in the values already written, which fails if a child is
+    // visited before it's parent
+    let written = Mutex::new(HashMap::new());
+    world.entities().par_ordered_for_each::<::WriteHierarchical<Depth>, _>(|node| {
+        let mut written = written.lock().unwrap();
+        node.depth = node.parent
+            .map(|parent| written.get(&parent).expect("child visited before it's parent") + 1)
+            .unwrap_or(0);
+        written.insert(node.id, node.depth);
+    });
+    assert_eq!(written.lock().unwrap().len(), nodes.len());
+    for &(ref entity, depth) in nodes.iter() {
+        assert_eq!(world.entities().component_for::<Depth>(entity).unwrap().depth, depth);
+    }
+
+    for node in world.entities().iter_for::<::Write<Depth>>() {
+        node.depth = 0;
+    }
+    world.entities().par_ordered_for_each::<::WriteAndParent<Depth>, _>(|(node, parent)| {
+        node.depth = parent.map(|p| p.depth + 1).unwrap_or(0);
+    });
+    for &(ref entity, depth) in nodes.iter() {
+        assert_eq!(world.entities().component_for::<Depth>(entity).unwrap().depth, depth);
+    }
+}
@@ -14,20 +14,24 @@ use smallvec;
 use ::Entity;
 use component::{self, ComponentSync, Component, ComponentThreadLocal,
     OneToNComponentSync, OneToNComponentThreadLocal};
-use storage::{Storage, HierarchicalStorage, OneToNStorage};
+use storage::{Storage, HierarchicalStorage, OneToNStorage, ChildrenMode};
 use entity::{EntityBuilder, Entities, EntitiesThreadLocal};
+use relation::{Relation, RelationStorage, Hierarchy, ParentStorage};
 use sync::*;
 use rayon::prelude::*;
 use ::{Bitmask, MaskType, NextMask};
+use error::Error;
 #[cfg(feature="dynamic_systems")]
-use dynamic_system_loader::DynamicSystemsLoader;
+use dynamic_system_loader::{DynamicSystemsLoader, DynamicSystemsConfig, ReloadEvent, ReloadMode};
+#[cfg(feature="dynamic_systems")]
+use abi::{ComponentLayout, SystemKind, SystemManifest};
 
 #[cfg(feature="stats_events")]
 use seitan::*;
 #[cfg(feature="stats_events")]
 use std::time;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Priority{
     Send(usize),
     ThreadLocal(usize),
@@ -44,10 +48,13 @@ pub struct World{
     entities: Vec<(Entity, ::MaskType)>, // Doesn't need lock cause never accesed mut from Entities?
     entities_index_per_mask: UnsafeCell<HashMap<Bitmask, RwLock<Vec<usize>>>>,
     entities_index_per_mask_guard: RwLock<()>,
-    ordered_entities_index_per_mask: RwLock<HashMap<component::Id, HashMap<Bitmask, Vec<usize>>>>,
-    reverse_components_mask_index: HashMap<MaskType, component::Id>,
+    ordered_entities_index_per_mask: UnsafeCell<HashMap<component::Id, HashMap<Bitmask, RwLock<Vec<usize>>>>>,
     remove_components_mask_index: HashMap<MaskType, Box<Fn(&World, usize)>>,
 
+    relations: HashMap<TypeId, Box<Any>>,
+    hierarchies: HashMap<TypeId, Box<Any>>,
+    remove_relations: Vec<Box<Fn(&World, usize)>>,
+
     next_component_mask: NextMask,
     pub(crate) components_mask_index: HashMap<component::Id, MaskType>,
 
@@ -65,8 +72,19 @@ pub struct World{
     #[cfg(feature="stats_events")]
     stats_events: HashMap<String, SenderRc<'static, time::Duration>>,
 
+    // The loader is only created the first time a dynamic system is used
+    #[cfg(feature="dynamic_systems")]
+    dynamic_systems: Option<DynamicSystemsLoader>,
+
+    #[cfg(feature="dynamic_systems")]
+    dynamic_systems_config: DynamicSystemsConfig,
+
+    #[cfg(feature="dynamic_systems")]
+    component_layouts: Vec<ComponentLayout>,
+
+    // Systems added from each library manifest by load_dynamic_library
     #[cfg(feature="dynamic_systems")]
-    dynamic_systems: DynamicSystemsLoader,
+    dynamic_libraries: HashMap<String, Vec<(String, Priority)>>,
 }
 
 trait AnySystem<TraitObject>{
@@ -132,9 +150,11 @@ impl World{
             components_mask_index: HashMap::default(),
             entities_index_per_mask_guard: RwLock::new(()),
             entities_index_per_mask: UnsafeCell::new(HashMap::default()),
-            ordered_entities_index_per_mask: RwLock::new(HashMap::default()),
-            reverse_components_mask_index: HashMap::default(),
+            ordered_entities_index_per_mask: UnsafeCell::new(HashMap::default()),
             remove_components_mask_index: HashMap::default(),
+            relations: HashMap::default(),
+            hierarchies: HashMap::default(),
+            remove_relations: vec![],
             systems: vec![],
             systems_thread_local: vec![],
             world_systems: vec![],
@@ -149,22 +169,42 @@ impl World{
             stats_events: HashMap::default(),
 
             #[cfg(feature="dynamic_systems")]
-            dynamic_systems: DynamicSystemsLoader::new().unwrap(),
+            dynamic_systems: None,
+
+            #[cfg(feature="dynamic_systems")]
+            dynamic_systems_config: DynamicSystemsConfig::default(),
+
+            #[cfg(feature="dynamic_systems")]
+            component_layouts: vec![],
+
+            #[cfg(feature="dynamic_systems")]
+            dynamic_libraries: HashMap::default(),
         }
     }
 
+    // The dynamic systems loader will use a custom target dir, profile,
+    // library naming or source roots instead of the cargo defaults
+    #[cfg(feature="dynamic_systems")]
+    pub fn with_dynamic_systems_config(config: DynamicSystemsConfig) -> World{
+        let mut world = World::new();
+        world.dynamic_systems_config = config;
+        world
+    }
+
     pub fn register<C: ComponentSync>(&mut self) {
-        if self.storages.get(&C::id()).is_some(){
-            panic!("{} already registered or not unique component id", C::type_name());
-        }
-        if self.storages_thread_local.get(&C::id()).is_some(){
-            panic!("{} already registered or not unique component id", C::type_name());
+        self.try_register::<C>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_register<C: ComponentSync>(&mut self) -> Result<(), Error> {
+        if self.storages.get(&C::id()).is_some() || self.storages_thread_local.get(&C::id()).is_some(){
+            return Err(Error::AlreadyRegistered(C::type_name()));
         }
         let storage = Box::new(RwLock::new(<C as Component>::Storage::new())) as Box<Any>;
         let next_mask = self.next_component_mask.next();
         self.components_mask_index.insert(C::id(), next_mask.clone());
-        self.reverse_components_mask_index.insert(next_mask.clone(), C::id());
         self.storages.insert(C::id(), storage);
+        #[cfg(feature="dynamic_systems")]
+        self.register_component_layout(ComponentLayout::of::<C>());
         self.remove_components_mask_index.insert(next_mask, Box::new(move |world, guid|{
             // let s: &RwLock<<C as ::Component>::Storage> = any.downcast_ref().unwrap();
             // s.write().unwrap().remove(guid)
@@ -173,20 +213,23 @@ impl World{
                 .expect(&format!("Trying to delete component {} without registering first", C::type_name()))
                 .remove(guid);
         }));
+        Ok(())
     }
 
     pub fn register_thread_local<C: ComponentThreadLocal>(&mut self) {
-        let storage = Box::new(RefCell::new(<C as Component>::Storage::new())) as Box<Any>;
-        if self.storages.get(&C::id()).is_some(){
-            panic!("{} already registered or not unique component id", C::type_name());
-        }
-        if self.storages_thread_local.get(&C::id()).is_some(){
-            panic!("{} already registered or not unique component id", C::type_name());
+        self.try_register_thread_local::<C>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_register_thread_local<C: ComponentThreadLocal>(&mut self) -> Result<(), Error> {
+        if self.storages.get(&C::id()).is_some() || self.storages_thread_local.get(&C::id()).is_some(){
+            return Err(Error::AlreadyRegistered(C::type_name()));
         }
+        let storage = Box::new(RefCell::new(<C as Component>::Storage::new())) as Box<Any>;
         let next_mask = self.next_component_mask.next();
         self.components_mask_index.insert(C::id(), next_mask.clone());
-        self.reverse_components_mask_index.insert(next_mask.clone(), C::id());
         self.storages_thread_local.insert(C::id(), storage);
+        #[cfg(feature="dynamic_systems")]
+        self.register_component_layout(ComponentLayout::of::<C>());
         self.remove_components_mask_index.insert(next_mask, Box::new(move |world, guid|{
             //let s: &RefCell<<C as ::Component>::Storage> = any.downcast_ref().unwrap();
             //s.borrow_mut().remove(guid)
@@ -196,10 +239,48 @@ impl World{
                 .remove(guid);
 
         }));
+        Ok(())
+    }
+
+    pub fn register_relation<R: Relation + Send + Sync>(&mut self) {
+        self.try_register_relation::<R>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_register_relation<R: Relation + Send + Sync>(&mut self) -> Result<(), Error> {
+        if self.relations.get(&R::id()).is_some(){
+            return Err(Error::AlreadyRegistered(R::type_name()));
+        }
+        let storage = Box::new(RwLock::new(RelationStorage::<R>::new())) as Box<Any>;
+        self.relations.insert(R::id(), storage);
+        self.remove_relations.push(Box::new(move |world, guid|{
+            world.relation_storage_mut::<R>()
+                .expect(&format!("Trying to delete relation {} without registering first", R::type_name()))
+                .remove_entity(guid);
+        }));
+        Ok(())
+    }
+
+    pub fn register_hierarchy<H: Hierarchy>(&mut self) {
+        self.try_register_hierarchy::<H>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_register_hierarchy<H: Hierarchy>(&mut self) -> Result<(), Error> {
+        if self.hierarchies.get(&H::id()).is_some(){
+            return Err(Error::AlreadyRegistered(H::type_name()));
+        }
+        let storage = Box::new(RwLock::new(ParentStorage::new())) as Box<Any>;
+        self.hierarchies.insert(H::id(), storage);
+        self.remove_relations.push(Box::new(move |world, guid|{
+            let mut storage = world.hierarchy_storage_mut::<H>()
+                .expect(&format!("Trying to delete from hierarchy {} without registering first", H::type_name()));
+            if storage.contains(guid){
+                unsafe{ storage.remove_node(guid, ChildrenMode::ToGrandparent) }
+            }
+        }));
+        Ok(())
     }
 
     pub fn create_entity(&mut self) -> EntityBuilder{
-        self.clear_entities_per_mask_index();
         EntityBuilder::new(self)
     }
 
@@ -207,62 +288,374 @@ impl World{
         Entities::new(self)
     }
 
+    pub fn propagate_hierarchy<L, G, F>(&self, f: F)
+        where L: ComponentSync<Storage = ::Forest<L>> + Sync,
+              G: ComponentSync + Sync,
+              for<'b> <G as Component>::Storage: Storage<'b, G, Get = &'b G, GetMut = &'b mut G>,
+              <G as Component>::Storage: Sync,
+              F: Fn(Option<&G>, &L) -> G + Sync
+    {
+        self.entities().propagate_hierarchy::<L, G, F>(f)
+    }
+
     pub fn entities_thread_local<'a>(&'a self) -> EntitiesThreadLocal<'a>{
         EntitiesThreadLocal::new(self)
     }
 
     pub fn add_component_to<C: ComponentSync>(&mut self, entity: &Entity, component: C){
-        self.clear_entities_per_mask_index();
+        self.try_add_component_to(entity, component).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_add_component_to<C: ComponentSync>(&mut self, entity: &Entity, component: C) -> Result<(), Error>{
+        self.try_components_mask::<C>()?;
         self.storage_mut::<C>()
-            .expect(&format!("Trying to add component of type {} without registering first", C::type_name()))
+            .ok_or_else(|| Error::NotRegistered(C::type_name()))?
             .insert(entity.guid(), component);
-        let &mut (_entity, ref mut mask) = &mut self.entities[entity.guid()];
-        *mask |= self.components_mask_index[&C::id()].clone();
+        self.component_added::<C>(entity);
+        Ok(())
     }
 
     pub fn add_component_to_thread_local<C: ComponentThreadLocal>(&mut self, entity: &Entity, component: C){
-        self.clear_entities_per_mask_index();
+        self.try_add_component_to_thread_local(entity, component).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_add_component_to_thread_local<C: ComponentThreadLocal>(&mut self, entity: &Entity, component: C) -> Result<(), Error>{
+        self.try_components_mask::<C>()?;
         self.storage_thread_local_mut::<C>()
-            .expect(&format!("Trying to add component of type {} without registering first", C::type_name()))
+            .ok_or_else(|| Error::NotRegistered(C::type_name()))?
             .insert(entity.guid(), component);
-        let &mut (_entity, ref mut mask) = &mut self.entities[entity.guid()];
-        *mask |= self.components_mask_index[&C::id()].clone();
+        self.component_added::<C>(entity);
+        Ok(())
     }
 
     pub fn add_slice_component_to<C: OneToNComponentSync>(&mut self, entity: &Entity, component: &[C]){
-        self.clear_entities_per_mask_index();
         self.storage_mut::<C>()
             .expect(&format!("Trying to add component of type {} without registering first", C::type_name()))
             .insert_slice(entity.guid(), component);
-        let &mut (_entity, ref mut mask) = &mut self.entities[entity.guid()];
-        *mask |= self.components_mask_index[&C::id()].clone();
+        self.component_added::<C>(entity);
     }
 
     pub fn add_slice_component_to_thread_local<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]){
-        self.clear_entities_per_mask_index();
         self.storage_thread_local_mut::<C>()
             .expect(&format!("Trying to add component of type {} without registering first", C::type_name()))
             .insert_slice(entity.guid(), component);
-        let &mut (_entity, ref mut mask) = &mut self.entities[entity.guid()];
-        *mask |= self.components_mask_index[&C::id()].clone();
+        self.component_added::<C>(entity);
+    }
+
+    // Appends an element to the slice of the entity adding the component
+    // if the entity didn't have it yet
+    pub fn push_component_to<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: C){
+        let added = {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .expect(&format!("Trying to add component of type {} without registering first", C::type_name()));
+            let added = !storage.contains(entity.guid());
+            storage.push(entity.guid(), component);
+            added
+        };
+        if added {
+            self.component_added::<C>(entity);
+        }
+    }
+
+    pub fn remove_component_at<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, index: usize) -> C{
+        let mut storage = self.storage_thread_local_mut::<C>()
+            .expect(&format!("Trying to remove component of type {} without registering first", C::type_name()));
+        if !storage.contains(entity.guid()){
+            panic!("Trying to remove non existing component {}", C::type_name());
+        }
+        unsafe{ storage.remove_at(entity.guid(), index) }
+    }
+
+    pub fn truncate_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, len: usize){
+        let mut storage = self.storage_thread_local_mut::<C>()
+            .expect(&format!("Trying to remove component of type {} without registering first", C::type_name()));
+        if !storage.contains(entity.guid()){
+            panic!("Trying to truncate non existing component {}", C::type_name());
+        }
+        unsafe{ storage.truncate(entity.guid(), len) }
+    }
+
+    // Replaces the whole slice of the entity adding the component if the
+    // entity didn't have it yet
+    pub fn replace_slice_component_of<C: OneToNComponentThreadLocal>(&mut self, entity: &Entity, component: &[C]){
+        let added = {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .expect(&format!("Trying to add component of type {} without registering first", C::type_name()));
+            let added = !storage.contains(entity.guid());
+            storage.replace_slice(entity.guid(), component);
+            added
+        };
+        if added {
+            self.component_added::<C>(entity);
+        }
+    }
+
+    pub fn add_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity, relation: R){
+        self.relation_storage_mut::<R>()
+            .expect(&format!("Trying to add relation of type {} without registering first", R::type_name()))
+            .insert(*source, *target, relation);
+    }
+
+    pub fn remove_relation<R: Relation + Send + Sync>(&mut self, source: &Entity, target: &Entity) -> Option<R>{
+        self.relation_storage_mut::<R>()
+            .expect(&format!("Trying to remove relation of type {} without registering first", R::type_name()))
+            .remove(*source, *target)
+    }
+
+    // Moves the entity and all it's descendants under parent in the hierarchy
+    // H, adding any of them to it as a root if they weren't part of it yet
+    pub fn set_parent_in<H: Hierarchy>(&mut self, entity: &Entity, parent: &Entity){
+        {
+            let mut storage = self.hierarchy_storage_mut::<H>()
+                .expect(&format!("Trying to use non registered hierarchy {}", H::type_name()));
+            if !storage.contains(parent.guid()){
+                storage.insert(parent.guid(), ());
+            }
+            if storage.contains(entity.guid()){
+                unsafe{ storage.set_parent(entity.guid(), parent.guid()) }
+            }else{
+                unsafe{ storage.insert_child(parent.guid(), entity.guid(), ()) }
+            }
+        }
+        self.invalidate_ordered_index(H::id());
+    }
+
+    pub fn make_root_in<H: Hierarchy>(&mut self, entity: &Entity){
+        {
+            let mut storage = self.hierarchy_storage_mut::<H>()
+                .expect(&format!("Trying to use non registered hierarchy {}", H::type_name()));
+            if storage.contains(entity.guid()){
+                unsafe{ storage.make_root(entity.guid()) }
+            }else{
+                storage.insert(entity.guid(), ());
+            }
+        }
+        self.invalidate_ordered_index(H::id());
+    }
+
+    // The children of the entity take it's place in the hierarchy
+    pub fn remove_from_hierarchy<H: Hierarchy>(&mut self, entity: &Entity){
+        {
+            let mut storage = self.hierarchy_storage_mut::<H>()
+                .expect(&format!("Trying to use non registered hierarchy {}", H::type_name()));
+            if storage.contains(entity.guid()){
+                unsafe{ storage.remove_node(entity.guid(), ChildrenMode::ToGrandparent) }
+            }
+        }
+        self.invalidate_ordered_index(H::id());
+    }
+
+    pub fn parent_in<H: Hierarchy>(&self, entity: &Entity) -> Option<Entity>{
+        let storage = self.hierarchy_storage::<H>()
+            .expect(&format!("Trying to use non registered hierarchy {}", H::type_name()));
+        if storage.contains(entity.guid()){
+            unsafe{ storage.parent_id(entity.guid()) }.map(|guid| self.entities[guid].0)
+        }else{
+            None
+        }
     }
 
     pub fn remove_component_from<C: ::Component>(&mut self, entity: &::Entity){
-        self.storage_mut::<C>()
-            .expect(&format!("Trying to remove component of type {} without registering first", C::type_name()))
-            .remove(entity.guid());
-        self.entities[entity.guid()].1 ^= self.components_mask_index[&C::id()].clone();
-        let mask = self.components_mask::<C>();
+        self.try_remove_component_from::<C>(entity).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_remove_component_from<C: ::Component>(&mut self, entity: &::Entity) -> Result<(), Error>{
+        {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .ok_or_else(|| Error::NotRegistered(C::type_name()))?;
+            if !storage.contains(entity.guid()){
+                return Err(Error::MissingComponent{ component: C::type_name(), entity: entity.guid() });
+            }
+            storage.remove(entity.guid());
+        }
+        self.component_removed::<C>(entity);
+        Ok(())
+    }
+
+    pub fn remove_component_from_hierarchy<'a, C: Component>(&mut self, entity: &Entity, mode: ChildrenMode)
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        self.try_remove_component_from_hierarchy::<C>(entity, mode).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_remove_component_from_hierarchy<'a, C: Component>(&mut self, entity: &Entity, mode: ChildrenMode) -> Result<(), Error>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .ok_or_else(|| Error::NotRegistered(C::type_name()))?;
+            if !storage.contains(entity.guid()){
+                return Err(Error::MissingComponent{ component: C::type_name(), entity: entity.guid() });
+            }
+            unsafe{ storage.remove_node(entity.guid(), mode) };
+        }
+        self.component_removed::<C>(entity);
+        Ok(())
+    }
+
+    // Removes the entity and every entity below it in the hierarchy of C
+    pub fn remove_entity_recursive<'a, C: Component>(&mut self, entity: &Entity)
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        let descendants = {
+            let storage = self.storage_thread_local::<C>()
+                .expect(&format!("Trying to use non registered type {}", C::type_name()));
+            if storage.contains(entity.guid()){
+                unsafe{ storage.descendants_ids(entity.guid()) }
+            }else{
+                vec![entity.guid()]
+            }
+        };
+        for guid in descendants.into_iter().rev(){
+            let entity = self.entities[guid].0;
+            self.remove_entity(&entity);
+        }
+    }
+
+    // Entity level navigation of the hierarchy of C, entities without C have
+    // no parent, children, ancestors or descendants
+    pub fn parent_of<'a, C: Component>(&self, entity: &Entity) -> Option<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage_thread_local::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.parent_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn children_of<'a, C: Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage_thread_local::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.children_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn ancestors_of<'a, C: Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage_thread_local::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.ancestors_in_storage::<C>(&*storage, entity)
+    }
+
+    pub fn descendants_of<'a, C: Component>(&self, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage_thread_local::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.descendants_in_storage::<C>(&*storage, entity)
+    }
+
+    // Shared with Entities which reads the sync storage instead of the
+    // thread local one
+    pub(crate) fn parent_in_storage<'a, C: Component>(&self, storage: &<C as Component>::Storage, entity: &Entity) -> Option<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        if storage.contains(entity.guid()){
+            unsafe{ storage.parent_id(entity.guid()) }.map(|guid| self.entities[guid].0)
+        }else{
+            None
+        }
+    }
+
+    pub(crate) fn children_in_storage<'a, C: Component>(&self, storage: &<C as Component>::Storage, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        self.hierarchy_entities_of::<C,_>(storage, entity, |storage, guid| unsafe{ storage.children_ids(guid) })
+    }
+
+    pub(crate) fn ancestors_in_storage<'a, C: Component>(&self, storage: &<C as Component>::Storage, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        self.hierarchy_entities_of::<C,_>(storage, entity, |storage, guid| unsafe{ storage.ancestors_ids(guid) })
+    }
+
+    pub(crate) fn descendants_in_storage<'a, C: Component>(&self, storage: &<C as Component>::Storage, entity: &Entity) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        self.hierarchy_entities_of::<C,_>(storage, entity, |storage, guid| unsafe{
+            let mut descendants = storage.descendants_ids(guid);
+            descendants.remove(0);
+            descendants
+        })
+    }
+
+    fn hierarchy_entities_of<'a, C: Component, F>(&self, storage: &<C as Component>::Storage, entity: &Entity, f: F) -> Vec<Entity>
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>,
+              F: FnOnce(&<C as Component>::Storage, usize) -> Vec<usize>
+    {
+        if storage.contains(entity.guid()){
+            f(storage, entity.guid()).into_iter()
+                .map(|guid| self.entities[guid].0)
+                .collect()
+        }else{
+            vec![]
+        }
+    }
+
+    fn component_added<C: Component>(&mut self, entity: &Entity){
+        let mask = self.entities[entity.guid()].1.clone() | self.components_mask_index[&C::id()].clone();
+        self.set_entity_mask(entity.guid(), mask);
+    }
+
+    fn component_removed<C: Component>(&mut self, entity: &Entity){
+        let mask = self.entities[entity.guid()].1.clone() ^ self.components_mask_index[&C::id()].clone();
+        self.set_entity_mask(entity.guid(), mask);
+        // removing a node can move it's children in the hierarchy of C
+        self.invalidate_ordered_index(C::id());
+    }
+
+    fn set_entity_mask(&mut self, guid: usize, mask: MaskType){
+        let old_mask = mem::replace(&mut self.entities[guid].1, mask.clone());
+        self.invalidate_mask_indices(Some(old_mask), mask);
+    }
+
+    pub fn set_parent<'a, C: Component>(&mut self, entity: &Entity, parent: &Entity)
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .expect(&format!("Trying to reparent component of type {} without registering first", C::type_name()));
+            if !storage.contains(entity.guid()) || !storage.contains(parent.guid()){
+                panic!("Trying to reparent entities without component {}", C::type_name());
+            }
+            unsafe{ storage.set_parent(entity.guid(), parent.guid()) };
+        }
+        self.invalidate_ordered_index(C::id());
+    }
+
+    pub fn make_root<'a, C: Component>(&mut self, entity: &Entity)
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
+        {
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .expect(&format!("Trying to reparent component of type {} without registering first", C::type_name()));
+            if !storage.contains(entity.guid()){
+                panic!("Trying to reparent entity without component {}", C::type_name());
+            }
+            unsafe{ storage.make_root(entity.guid()) };
+        }
+        self.invalidate_ordered_index(C::id());
+    }
+
+    pub fn detach<'a, C: Component>(&mut self, entity: &Entity)
+        where <C as Component>::Storage: HierarchicalStorage<'a,C>
+    {
         {
-            let type_id = &self.reverse_components_mask_index[&mask];
-            if let Some(cache) = self.ordered_entities_index_per_mask.write().unwrap().get_mut(type_id){
-                cache.clear();
+            let mut storage = self.storage_thread_local_mut::<C>()
+                .expect(&format!("Trying to reparent component of type {} without registering first", C::type_name()));
+            if !storage.contains(entity.guid()){
+                panic!("Trying to reparent entity without component {}", C::type_name());
             }
+            unsafe{ storage.detach(entity.guid()) };
         }
-        self.clear_entities_per_mask_index();
+        self.invalidate_ordered_index(C::id());
     }
 
     pub fn remove_entity(&mut self, entity: &::Entity){
+        for remove_relations in self.remove_relations.iter(){
+            remove_relations(self, entity.guid());
+        }
         //if let Ok(pos) = self.entities.binary_search_by(|e| e.guid().cmp(&entity.guid())){
         let entity_mask = unsafe{ mem::transmute::<&mut ::MaskType, &mut ::MaskType>(&mut self.entities[entity.guid()].1) };
         let mut mask = MaskType::from(1usize);
@@ -274,14 +667,11 @@ impl World{
                 };
                 remove_component(self, entity.guid());
                 *entity_mask ^= mask.clone();
-                let type_id = &self.reverse_components_mask_index[&mask];
-                if let Some(cache) = self.ordered_entities_index_per_mask.write().unwrap().get_mut(type_id){
-                    cache.clear();
-                }
             }
             mask *= MaskType::from(2usize);
         }
-        // self.ordered_entities_index_per_mask.write().unwrap().clear();
+        // removing the entity from hierarchies and relations can reorder the
+        // rest of entities so all the ordered indices are rebuilt
         self.clear_entities_per_mask_index()
         // TODO: can't remove entities since we rely on order for fast entitty search
         // mostly on ordered_ids_for. others are add / remove component which could be slower
@@ -450,56 +840,69 @@ impl World{
 
 
     #[cfg(feature="dynamic_systems")]
-    pub fn preload_dynamic_libraries(&mut self, libs: &[&str]) -> Result<(), String> {
-        self.dynamic_systems.preload_libraries(libs)
+    pub fn preload_dynamic_libraries(&mut self, libs: &[&str]) -> Result<(), Error> {
+        self.dynamic_systems().preload_libraries(libs)
+    }
+
+    // Adds every system in the library manifest sorted by order. When the
+    // library is reloaded systems added to or removed from the manifest are
+    // added to or removed from the world too
+    #[cfg(feature="dynamic_systems")]
+    pub fn load_dynamic_library(&mut self, library: &str) -> Result<(), Error>{
+        if self.dynamic_libraries.contains_key(library) {
+            return Err(Error::DynamicSystems(format!("Dynamic library {} already loaded", library)));
+        }
+        let manifest = self.dynamic_systems().manifest(library)?;
+        self.dynamic_libraries.insert(library.to_owned(), vec![]);
+        self.add_manifest_systems(library, manifest)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system(&mut self, system_path: &str) -> &mut World{
-        let system = self.dynamic_systems.new_system(system_path).unwrap();
+        let system = self.dynamic_systems().new_system(system_path).unwrap();
         self.add_any_system(system, None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system_with_data<D: Send + 'static>(&mut self, system_path: &str, data: D) -> &mut World{
-        let system = self.dynamic_systems.new_system_with_data(system_path).unwrap();
+        let system = self.dynamic_systems().new_system_with_data(system_path).unwrap();
         self.add_any_system((system, data), None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system_thread_local(&mut self, system_path: &str) -> &mut World{
-        let system = self.dynamic_systems.new_system_thread_local(system_path).unwrap();
+        let system = self.dynamic_systems().new_system_thread_local(system_path).unwrap();
         self.add_any_system(system, None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system_with_data_thread_local<D: 'static>(&mut self, system_path: &str, data: D) -> &mut World{
-        let system = self.dynamic_systems.new_system_with_data_thread_local(system_path).unwrap();
+        let system = self.dynamic_systems().new_system_with_data_thread_local(system_path).unwrap();
         self.add_any_system((system, data), None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_creation_system(&mut self, system_path: &str) -> &mut World{
-        let system = self.dynamic_systems.new_creation_system(system_path).unwrap();
+        let system = self.dynamic_systems().new_creation_system(system_path).unwrap();
         self.add_any_system(system, None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_creation_system_with_data<D: 'static>(&mut self, system_path: &str, data: D) -> &mut World{
-        let system = self.dynamic_systems.new_creation_system_with_data(system_path).unwrap();
+        let system = self.dynamic_systems().new_creation_system_with_data(system_path).unwrap();
         self.add_any_system((system, data), None)
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn run_dynamic_system_once(&mut self, system_path: &str) -> &mut World{
-        let mut system = self.dynamic_systems.new_system(system_path).unwrap();
+        let mut system = self.dynamic_systems().new_system(system_path).unwrap();
         system.run(self.entities(), self.resources());
         self
     }
 
     #[cfg(feature="dynamic_systems")]
     pub fn run_dynamic_system_once_thread_local(&mut self, system_path: &str) -> &mut World{
-        let mut system = self.dynamic_systems.new_system_thread_local(system_path).unwrap();
+        let mut system = self.dynamic_systems().new_system_thread_local(system_path).unwrap();
         system.run(self.entities_thread_local(), self.resources_thread_local());
         self
     }
@@ -507,14 +910,14 @@ impl World{
     #[cfg(feature="stats_events")]
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system_with_stats(&mut self, system_path: &str, name: &str) -> &mut World{
-        let system = self.dynamic_systems.new_system(system_path).unwrap();
+        let system = self.dynamic_systems().new_system(system_path).unwrap();
         self.add_any_system(system, Some(name))
     }
 
     #[cfg(feature="stats_events")]
     #[cfg(feature="dynamic_systems")]
     pub fn new_dynamic_system_with_stats_thread_local(&mut self, system_path: &str, name: &str) -> &mut World{
-        let system = self.dynamic_systems.new_system_thread_local(system_path).unwrap();
+        let system = self.dynamic_systems().new_system_thread_local(system_path).unwrap();
         self.add_any_system(system, Some(name))
     }
 
@@ -534,11 +937,210 @@ impl World{
     }
 
     #[cfg(feature="dynamic_systems")]
-    pub fn start_dynamic_systems_watch(&mut self) -> Result<(), String>{
-        self.dynamic_systems.start()
+    pub fn start_dynamic_systems_watch(&mut self) -> Result<(), Error>{
+        self.dynamic_systems().start()
+    }
+
+    // Build and reload events since the last call, if a Vec<ReloadEvent>
+    // resource exists run_once appends them there instead
+    #[cfg(feature="dynamic_systems")]
+    pub fn reload_events(&mut self) -> Vec<ReloadEvent>{
+        self.dynamic_systems.as_mut()
+            .map(|loader| loader.reload_events().unwrap())
+            .unwrap_or(vec![])
+    }
+
+    // Rebuilds changed sources and applies pending library reloads on the
+    // calling thread, run_once already does this when the loader is in poll
+    // mode. Shouldn't be called while systems are running
+    #[cfg(feature="dynamic_systems")]
+    pub fn poll_dynamic_systems(&mut self){
+        let reloaded = match self.dynamic_systems.as_mut() {
+            Some(loader) => loader.poll().unwrap(),
+            None => vec![],
+        };
+        self.update_dynamic_libraries(reloaded);
+    }
+
+    // Queues a reload of a loaded library, applied at the start of the next
+    // run_once
+    #[cfg(feature="dynamic_systems")]
+    pub fn reload_dynamic_library(&mut self, library: &str) -> Result<(), Error>{
+        self.dynamic_systems().reload_library(library)
+    }
+
+    #[cfg(feature="dynamic_systems")]
+    fn dynamic_systems(&mut self) -> &mut DynamicSystemsLoader{
+        if self.dynamic_systems.is_none() {
+            let mut loader = DynamicSystemsLoader::with_config(self.dynamic_systems_config.clone()).unwrap();
+            for layout in self.component_layouts.iter() {
+                loader.register_component_layout(layout.clone()).unwrap();
+            }
+            self.dynamic_systems = Some(loader);
+        }
+        self.dynamic_systems.as_mut().unwrap()
+    }
+
+    #[cfg(feature="dynamic_systems")]
+    fn register_component_layout(&mut self, layout: ComponentLayout){
+        if let Some(loader) = self.dynamic_systems.as_mut() {
+            loader.register_component_layout(layout.clone()).unwrap();
+        }
+        self.component_layouts.push(layout);
+    }
+
+    // Systems already in the world are skipped so this can be called again
+    // with the manifest of a reloaded library
+    #[cfg(feature="dynamic_systems")]
+    fn add_manifest_systems(&mut self, library: &str, mut manifest: Vec<SystemManifest>) -> Result<(), Error>{
+        manifest.sort_by_key(|system| system.order);
+        for system in manifest {
+            if self.dynamic_libraries[library].iter().any(|&(ref name, _)| *name == system.name) {
+                continue;
+            }
+
+            if let Some(component) = system.components.iter()
+                .find(|component| !self.component_layouts.iter().any(|layout| layout.name == **component))
+            {
+                return Err(Error::SystemComponentNotRegistered{
+                    library: library.to_owned(),
+                    system: system.name.clone(),
+                    component: component.clone(),
+                });
+            }
+
+            let system_path = format!("{}::{}", library, system.name);
+            match system.kind {
+                SystemKind::Send => {
+                    let dynamic_system = self.dynamic_systems().new_manifest_system(&system_path)?;
+                    self.add_any_system(dynamic_system, None);
+                }
+                SystemKind::ThreadLocal => {
+                    let dynamic_system = self.dynamic_systems().new_manifest_system_thread_local(&system_path)?;
+                    self.add_any_system(dynamic_system, None);
+                }
+                SystemKind::Creation => {
+                    let dynamic_system = self.dynamic_systems().new_manifest_creation_system(&system_path)?;
+                    self.add_any_system(dynamic_system, None);
+                }
+                // With data systems have to be added with
+                // new_dynamic_system_with_data
+                _ => {
+                    let has_data = self.dynamic_systems().contains_system_with_data(&system_path)?;
+                    if !has_data {
+                        self.dynamic_systems().push_reload_event(ReloadEvent::NeedsData{
+                            library: library.to_owned(),
+                            system: system.name,
+                        }).unwrap();
+                    }
+                    continue;
+                }
+            }
+
+            let priority = *self.priority_queue.last().unwrap();
+            self.dynamic_libraries.get_mut(library).unwrap().push((system.name, priority));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature="dynamic_systems")]
+    fn update_dynamic_libraries(&mut self, reloaded: Vec<String>){
+        for library in reloaded {
+            if !self.dynamic_libraries.contains_key(&library) {
+                continue;
+            }
+
+            let manifest = match self.dynamic_systems().manifest(&library) {
+                Ok(manifest) => manifest,
+                Err(err) => {
+                    // the loader already dropped the systems the new library
+                    // doesn't export, the world copies still point to the old
+                    // library so they are removed too
+                    let systems = self.dynamic_libraries[&library].clone();
+                    let mut removed = vec![];
+                    for (name, priority) in systems {
+                        let system_path = format!("{}::{}", library, name);
+                        if !self.dynamic_systems().contains_system(&system_path).unwrap_or(false) {
+                            removed.push(name);
+                            self.remove_system(priority);
+                        }
+                    }
+                    self.dynamic_libraries.get_mut(&library).unwrap()
+                        .retain(|&(ref name, _)| !removed.contains(name));
+                    self.push_library_error(&library, format!("{}", err));
+                    continue;
+                }
+            };
+
+            let removed = self.dynamic_libraries[&library].iter()
+                .filter(|&&(ref name, _)| !manifest.iter().any(|system| system.name == *name))
+                .map(|&(_, priority)| priority)
+                .collect::<Vec<_>>();
+            for priority in removed {
+                self.remove_system(priority);
+            }
+            self.dynamic_libraries.get_mut(&library).unwrap()
+                .retain(|&(ref name, _)| manifest.iter().any(|system| system.name == *name));
+
+            if let Err(err) = self.add_manifest_systems(&library, manifest) {
+                self.push_library_error(&library, format!("{}", err));
+            }
+        }
+    }
+
+    #[cfg(feature="dynamic_systems")]
+    fn push_library_error(&mut self, library: &str, output: String){
+        self.dynamic_systems().push_reload_event(ReloadEvent::Failed{
+            libraries: vec![library.to_owned()],
+            diagnostics: vec![],
+            output,
+        }).unwrap();
+    }
+
+    // The system is replaced by an empty one so the indices in the
+    // priority queue of the rest of systems stay valid
+    #[cfg(feature="dynamic_systems")]
+    fn remove_system(&mut self, priority: Priority){
+        fn removed(_: Entities, _: ::Resources){}
+        fn removed_thread_local(_: EntitiesThreadLocal, _: ::ResourcesThreadLocal){}
+        fn removed_creation(_: ::EntitiesCreation, _: ::ResourcesThreadLocal){}
+
+        self.priority_queue.retain(|p| *p != priority);
+        match priority {
+            Priority::Send(i) =>
+                self.systems[i] = (None, SyncSystem::new(removed)),
+            Priority::ThreadLocal(i) =>
+                self.systems_thread_local[i] = (None, Box::new(removed_thread_local) as Box<for<'a> SystemThreadLocal<'a>>),
+            Priority::Creation(i) =>
+                self.world_systems[i] = (None, Box::new(removed_creation) as Box<for<'a> CreationSystem<'a>>),
+            Priority::Barrier => (),
+        }
+    }
+
+    #[cfg(feature="dynamic_systems")]
+    fn forward_reload_events(&mut self){
+        if self.resources.contains_key(&TypeId::of::<Vec<ReloadEvent>>()) {
+            let events = self.reload_events();
+            self.resource_mut::<Vec<ReloadEvent>>().unwrap().extend(events);
+        }
     }
 
     pub fn run_once(&mut self){
+        // Dynamic libraries are only swapped here, before any system runs,
+        // so every system sees the same library for the whole frame
+        #[cfg(feature="dynamic_systems")]
+        {
+            let reloaded = match self.dynamic_systems.as_mut() {
+                Some(loader) => match loader.reload_mode() {
+                    ReloadMode::Poll => loader.poll().unwrap(),
+                    ReloadMode::Background => loader.apply_reloads().unwrap(),
+                },
+                None => vec![],
+            };
+            self.update_dynamic_libraries(reloaded);
+            self.forward_reload_events();
+        }
+
         let systems_thread_local = unsafe{ mem::transmute::<
                 &mut Vec<(Option<String>, Box<for<'a> ::system::SystemThreadLocal<'a>>)>,
                 &mut Vec<(Option<String>, Box<for<'a> ::system::SystemThreadLocal<'a>>)>
@@ -647,6 +1249,32 @@ impl World{
         unsafe{
             let _guard = self.entities_index_per_mask_guard.write().unwrap();
             (*self.entities_index_per_mask.get()).clear();
+            (*self.ordered_entities_index_per_mask.get()).clear();
+        }
+    }
+
+    // Only the indices that match the entity with one of the masks but not
+    // with the other change, old_mask is None for new entities
+    fn invalidate_mask_indices(&mut self, old_mask: Option<MaskType>, new_mask: MaskType){
+        let changed = |mask: &Bitmask| {
+            let before = old_mask.as_ref().map(|old_mask| mask.check(old_mask.clone())).unwrap_or(false);
+            before != mask.check(new_mask.clone())
+        };
+        unsafe{
+            let _guard = self.entities_index_per_mask_guard.write().unwrap();
+            (*self.entities_index_per_mask.get()).retain(|mask, _| !changed(mask));
+            for index in (*self.ordered_entities_index_per_mask.get()).values_mut(){
+                index.retain(|mask, _| !changed(mask));
+            }
+        }
+    }
+
+    // Moving entities in a hierarchy only changes the order of that
+    // component or hierarchy
+    fn invalidate_ordered_index(&mut self, id: component::Id){
+        unsafe{
+            let _guard = self.entities_index_per_mask_guard.write().unwrap();
+            (*self.ordered_entities_index_per_mask.get()).remove(&id);
         }
     }
 
@@ -663,7 +1291,7 @@ impl World{
     }
 
     pub(crate) fn push_entity(&mut self, e: ::Entity, mask: ::MaskType){
-        self.clear_entities_per_mask_index();
+        self.invalidate_mask_indices(None, mask.clone());
         self.entities.push((e, mask));
     }
 
@@ -681,6 +1309,34 @@ impl World{
         })
     }
 
+    pub(crate) fn hierarchy_storage<H: Hierarchy>(&self) -> Option<RwLockReadGuard<ParentStorage>> {
+        self.hierarchies.get(&H::id()).map(|s| {
+            let s: &RwLock<ParentStorage> = s.downcast_ref().unwrap();
+            s.read().unwrap()
+        })
+    }
+
+    pub(crate) fn hierarchy_storage_mut<H: Hierarchy>(&self) -> Option<RwLockWriteGuard<ParentStorage>> {
+        self.hierarchies.get(&H::id()).map(|s| {
+            let s: &RwLock<ParentStorage> = s.downcast_ref().unwrap();
+            s.write().unwrap()
+        })
+    }
+
+    pub(crate) fn relation_storage<R: Relation + Send + Sync>(&self) -> Option<RwLockReadGuard<RelationStorage<R>>> {
+        self.relations.get(&R::id()).map(|s| {
+            let s: &RwLock<RelationStorage<R>> = s.downcast_ref().unwrap();
+            s.read().unwrap()
+        })
+    }
+
+    pub(crate) fn relation_storage_mut<R: Relation + Send + Sync>(&self) -> Option<RwLockWriteGuard<RelationStorage<R>>> {
+        self.relations.get(&R::id()).map(|s| {
+            let s: &RwLock<RelationStorage<R>> = s.downcast_ref().unwrap();
+            s.write().unwrap()
+        })
+    }
+
     pub(crate) fn storage_thread_local<C: ::Component>(&self) -> Option<ReadGuardRef<<C as ::Component>::Storage>> {
         let local = self.storages_thread_local.get(&C::id()).map(|s| {
             let s: &RefCell<<C as ::Component>::Storage> = s.downcast_ref().unwrap();
@@ -706,9 +1362,13 @@ impl World{
     }
 
     pub(crate) fn components_mask<C: ::Component>(&self) -> MaskType{
+        self.try_components_mask::<C>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn try_components_mask<C: ::Component>(&self) -> Result<MaskType, Error>{
         self.components_mask_index.get(&C::id())
-            .expect(&format!("Trying to use component {} before registering", C::type_name()))
-            .clone()
+            .cloned()
+            .ok_or_else(|| Error::NotRegistered(C::type_name()))
     }
 
     pub(crate) fn entities_for_mask(&self, mask: Bitmask) -> IndexGuard{
@@ -742,66 +1402,109 @@ impl World{
         }
     }
 
-    // This uses the same index as the non ordered which is probably not correct?
     pub(crate) fn ordered_entities_for<'a, C: Component>(&self, mask: Bitmask) -> IndexGuard
         where <C as Component>::Storage: ::HierarchicalStorage<'a,C>
     {
-        if !self.ordered_entities_index_per_mask.write()
-            .unwrap()
-            .entry(C::id())
-            .or_insert_with(|| HashMap::default())
-            .contains_key(&mask){
-            let entities = self.storage::<C>()
-                    .expect(&format!("Trying to use non registered type {}", C::type_name()))
-                    .ordered_ids()
-                    .into_iter()
-                    .map(|i| *i)
-                    .filter(|i| mask.check(self.entities[*i].1.clone()))
-                    .collect::<Vec<_>>();
-            unsafe{
-                let _guard = self.entities_index_per_mask_guard.write().unwrap();
-                (*self.entities_index_per_mask.get()).insert(mask.clone(), RwLock::new(entities));
-            }
-
-        }
-        let _index_guard = unsafe{
-            let _guard = self.entities_index_per_mask_guard.read().unwrap();
-            (*self.entities_index_per_mask.get()).get(&mask).unwrap().read().unwrap()
-        };
-        let ptr = _index_guard.as_ptr();
-        let len = _index_guard.len();
-        let index = unsafe{ slice::from_raw_parts(ptr, len) };
-        IndexGuard{
-            _index_guard,
-            index,
-        }
+        self.ordered_entities_index(C::id(), mask, |mask| {
+            let storage = self.storage::<C>()
+                .expect(&format!("Trying to use non registered type {}", C::type_name()));
+            self.filter_ordered_ids(storage.ordered_ids(), mask)
+        })
     }
 
     pub(crate) fn thread_local_ordered_entities_for<'a, C: Component>(&self, mask: Bitmask) -> IndexGuard
         where <C as Component>::Storage: ::HierarchicalStorage<'a,C>
     {
-        if !self.ordered_entities_index_per_mask.write()
-            .unwrap()
-            .entry(C::id())
-            .or_insert_with(|| HashMap::default())
-            .contains_key(&mask){
-
-            let entities = self.storage::<C>()
-                    .expect(&format!("Trying to use non registered type {}", C::type_name()))
-                    .ordered_ids()
-                    .into_iter()
-                    .map(|i| *i)
-                    .filter(|i| mask.check(self.entities[*i].1.clone()) )
-                    .collect::<Vec<_>>();
+        self.ordered_entities_index(C::id(), mask, |mask| {
+            let storage = self.storage_thread_local::<C>()
+                .expect(&format!("Trying to use non registered type {}", C::type_name()));
+            self.filter_ordered_ids(storage.ordered_ids(), mask)
+        })
+    }
+
+    // Depth and guid of the entities matching mask in the hierarchy of C,
+    // visited as specified by traversal
+    pub(crate) fn traversal_ids_for<'a, C: ComponentSync>(&self, mask: Bitmask, traversal: &::Traversal) -> Vec<(usize, usize)>
+        where <C as Component>::Storage: ::HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.traversal_ids::<C>(ReadGuardRef::new(ReadGuard::Sync(storage)), mask, traversal)
+    }
+
+    pub(crate) fn thread_local_traversal_ids_for<'a, C: Component>(&self, mask: Bitmask, traversal: &::Traversal) -> Vec<(usize, usize)>
+        where <C as Component>::Storage: ::HierarchicalStorage<'a,C>
+    {
+        let storage = self.storage_thread_local::<C>()
+            .expect(&format!("Trying to use non registered type {}", C::type_name()));
+        self.traversal_ids::<C>(storage, mask, traversal)
+    }
+
+    fn traversal_ids<'a, C: Component>(&self, storage: ReadGuardRef<<C as Component>::Storage>, mask: Bitmask, traversal: &::Traversal) -> Vec<(usize, usize)>
+        where <C as Component>::Storage: ::HierarchicalStorage<'a,C>
+    {
+        let (range, base_depth) = match traversal.root{
+            Some(root) => {
+                if !storage.contains(root.guid()){
+                    panic!("Trying to traverse from entity without component {}", C::type_name());
+                }
+                let range = unsafe{ storage.ordered_subtree(root.guid()) };
+                let base_depth = storage.ordered_depths()[range.start];
+                (range, base_depth)
+            }
+            None => (0 .. storage.ordered_ids().len(), 0),
+        };
+        let ids = &storage.ordered_ids()[range.clone()];
+        let depths = &storage.ordered_depths()[range];
+        let mut traversal_ids = ids.iter().zip(depths.iter())
+            .map(|(guid, depth)| (depth - base_depth, *guid))
+            .filter(|&(depth, guid)| traversal.max_depth.map(|max| depth <= max).unwrap_or(true)
+                && mask.check(self.entities[guid].1.clone()))
+            .collect::<Vec<_>>();
+        // depth first order sorted by depth is the breadth first order
+        if traversal.order == ::TraversalOrder::BreadthFirst{
+            traversal_ids.sort_by_key(|&(depth, _)| depth);
+        }
+        traversal_ids
+    }
+
+    fn filter_ordered_ids(&self, ordered_ids: &[usize], mask: &Bitmask) -> Vec<usize>{
+        ordered_ids.iter()
+            .map(|i| *i)
+            .filter(|i| mask.check(self.entities[*i].1.clone()))
+            .collect()
+    }
+
+    // The ordered index per mask is only built once and kept until the
+    // entities masks or the hierarchy of C change
+    pub(crate) fn hierarchy_ordered_entities_for<H: Hierarchy>(&self, mask: Bitmask) -> IndexGuard{
+        self.ordered_entities_index(H::id(), mask, |mask| {
+            let storage = self.hierarchy_storage::<H>()
+                .expect(&format!("Trying to use non registered hierarchy {}", H::type_name()));
+            self.filter_ordered_ids(storage.ordered_ids(), mask)
+        })
+    }
+
+    fn ordered_entities_index<F: FnOnce(&Bitmask) -> Vec<usize>>(&self, id: component::Id, mask: Bitmask, build: F) -> IndexGuard{
+        let contains_key = unsafe {
+            let _guard = self.entities_index_per_mask_guard.read().unwrap();
+            (*self.ordered_entities_index_per_mask.get()).get(&id)
+                .map(|index| index.contains_key(&mask))
+                .unwrap_or(false)
+        };
+        if !contains_key {
+            let entities = build(&mask);
             unsafe{
                 let _guard = self.entities_index_per_mask_guard.write().unwrap();
-                (*self.entities_index_per_mask.get()).insert(mask.clone(), RwLock::new(entities));
+                (*self.ordered_entities_index_per_mask.get())
+                    .entry(id)
+                    .or_insert_with(|| HashMap::default())
+                    .insert(mask.clone(), RwLock::new(entities));
             }
         }
-        let _guard = self.entities_index_per_mask_guard.read().unwrap();
         let _index_guard = unsafe{
             let _guard = self.entities_index_per_mask_guard.read().unwrap();
-            (*self.entities_index_per_mask.get()).get(&mask).unwrap().read().unwrap()
+            (*self.ordered_entities_index_per_mask.get())[&id].get(&mask).unwrap().read().unwrap()
         };
         let ptr = _index_guard.as_ptr();
         let len = _index_guard.len();
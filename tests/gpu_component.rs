@@ -0,0 +1,48 @@
+#![cfg(feature="dynamic_systems")]
+
+extern crate rinecs;
+#[macro_use] extern crate rinecs_derive;
+
+use std::mem;
+
+#[derive(GpuComponent, Debug, PartialEq, Copy, Clone)]
+#[repr(C)]
+struct Pos{
+    x: f32,
+    y: f32,
+}
+
+unsafe impl rinecs::Pod for Pos{}
+
+#[derive(GpuComponent, Debug, PartialEq, Copy, Clone)]
+#[repr(C)]
+struct Scale(f32);
+
+unsafe impl rinecs::Pod for Scale{}
+
+#[test]
+fn derived_gpu_component() {
+    let mut world = rinecs::World::new();
+    world.register::<Pos>();
+    world.register::<Scale>();
+    let e1 = world.create_entity()
+        .add(Pos{x: 1., y: 1.})
+        .add(Scale(1.))
+        .build();
+    let e2 = world.create_entity()
+        .add(Pos{x: 2., y: 2.})
+        .add(Scale(2.))
+        .build();
+
+    let entities = world.entities();
+    assert_eq!(entities.storage_for::<Pos>().dirty_ranges(), vec![0..2]);
+    assert_eq!(entities.storage_for::<Pos>().as_bytes().len(), 2 * mem::size_of::<Pos>());
+    entities.clear_dirty_for::<Pos>();
+    entities.clear_dirty_for::<Scale>();
+
+    **entities.component_for_mut::<Scale>(&e2).unwrap() = 4.;
+    assert!(!entities.storage_for::<Pos>().is_dirty());
+    assert_eq!(entities.storage_for::<Scale>().dirty_ranges(), vec![1..2]);
+    assert_eq!(*entities.component_for::<Pos>(&e1).unwrap(), Pos{x: 1., y: 1.});
+    assert_eq!(entities.storage_for::<Scale>().as_slice(), &[Scale(1.), Scale(4.)]);
+}